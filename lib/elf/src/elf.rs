@@ -176,6 +176,67 @@ pub const ET_CORE: ElfType = ElfType(4);
 pub const ET_LOPROC: ElfType = ElfType(0xFF00);
 pub const ET_HIPROC: ElfType = ElfType(0xFFFF);
 
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DynamicTag(pub i64);
+
+//
+// These constants are for the tags of the dynamic section
+//
+pub const DT_NULL: DynamicTag = DynamicTag(0);
+pub const DT_NEEDED: DynamicTag = DynamicTag(1);
+pub const DT_PLTRELSZ: DynamicTag = DynamicTag(2);
+pub const DT_PLTGOT: DynamicTag = DynamicTag(3);
+pub const DT_HASH: DynamicTag = DynamicTag(4);
+pub const DT_STRTAB: DynamicTag = DynamicTag(5);
+pub const DT_SYMTAB: DynamicTag = DynamicTag(6);
+pub const DT_RELA: DynamicTag = DynamicTag(7);
+pub const DT_RELASZ: DynamicTag = DynamicTag(8);
+pub const DT_RELAENT: DynamicTag = DynamicTag(9);
+pub const DT_STRSZ: DynamicTag = DynamicTag(10);
+pub const DT_SYMENT: DynamicTag = DynamicTag(11);
+pub const DT_INIT: DynamicTag = DynamicTag(12);
+pub const DT_FINI: DynamicTag = DynamicTag(13);
+pub const DT_SONAME: DynamicTag = DynamicTag(14);
+pub const DT_RPATH: DynamicTag = DynamicTag(15);
+pub const DT_SYMBOLIC: DynamicTag = DynamicTag(16);
+pub const DT_REL: DynamicTag = DynamicTag(17);
+pub const DT_RELSZ: DynamicTag = DynamicTag(18);
+pub const DT_RELENT: DynamicTag = DynamicTag(19);
+pub const DT_PLTREL: DynamicTag = DynamicTag(20);
+pub const DT_DEBUG: DynamicTag = DynamicTag(21);
+pub const DT_TEXTREL: DynamicTag = DynamicTag(22);
+pub const DT_JMPREL: DynamicTag = DynamicTag(23);
+pub const DT_BIND_NOW: DynamicTag = DynamicTag(24);
+pub const DT_INIT_ARRAY: DynamicTag = DynamicTag(25);
+pub const DT_FINI_ARRAY: DynamicTag = DynamicTag(26);
+pub const DT_INIT_ARRAYSZ: DynamicTag = DynamicTag(27);
+pub const DT_FINI_ARRAYSZ: DynamicTag = DynamicTag(28);
+pub const DT_RUNPATH: DynamicTag = DynamicTag(29);
+pub const DT_FLAGS: DynamicTag = DynamicTag(30);
+pub const DT_RELRSZ: DynamicTag = DynamicTag(35);
+pub const DT_RELR: DynamicTag = DynamicTag(36);
+pub const DT_RELRENT: DynamicTag = DynamicTag(37);
+pub const DT_RELACOUNT: DynamicTag = DynamicTag(0x6FFF_FFF9);
+pub const DT_RELCOUNT: DynamicTag = DynamicTag(0x6FFF_FFFA);
+pub const DT_FLAGS_1: DynamicTag = DynamicTag(0x6FFF_FFFB);
+
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RelocationType(pub u32);
+
+//
+// These constants are for the relocation types that do not need the symbol table
+//
+pub const R_386_NONE: RelocationType = RelocationType(0);
+pub const R_386_RELATIVE: RelocationType = RelocationType(8);
+pub const R_X86_64_NONE: RelocationType = RelocationType(0);
+pub const R_X86_64_RELATIVE: RelocationType = RelocationType(8);
+pub const R_AARCH64_NONE: RelocationType = RelocationType(0);
+pub const R_AARCH64_RELATIVE: RelocationType = RelocationType(1027);
+pub const R_RISCV_NONE: RelocationType = RelocationType(0);
+pub const R_RISCV_RELATIVE: RelocationType = RelocationType(3);
+
 pub const PF_X: SegmentFlags = SegmentFlags(1);
 pub const PF_W: SegmentFlags = SegmentFlags(2);
 pub const PF_R: SegmentFlags = SegmentFlags(4);
@@ -264,6 +325,59 @@ pub mod elf32 {
         pub p_flags: SegmentFlags,
         pub p_align: ElfWord,
     }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Dyn {
+        pub d_tag: i32,
+        pub d_val: ElfWord,
+    }
+
+    impl Dyn {
+        #[inline]
+        pub const fn tag(&self) -> DynamicTag {
+            DynamicTag(self.d_tag as i64)
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Rel {
+        pub r_offset: ElfAddr,
+        pub r_info: ElfWord,
+    }
+
+    impl Rel {
+        #[inline]
+        pub const fn r_sym(&self) -> u32 {
+            self.r_info >> 8
+        }
+
+        #[inline]
+        pub const fn r_type(&self) -> RelocationType {
+            RelocationType(self.r_info & 0xff)
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Rela {
+        pub r_offset: ElfAddr,
+        pub r_info: ElfWord,
+        pub r_addend: i32,
+    }
+
+    impl Rela {
+        #[inline]
+        pub const fn r_sym(&self) -> u32 {
+            self.r_info >> 8
+        }
+
+        #[inline]
+        pub const fn r_type(&self) -> RelocationType {
+            RelocationType(self.r_info & 0xff)
+        }
+    }
 }
 
 pub mod elf64 {
@@ -315,4 +429,156 @@ pub mod elf64 {
         pub p_memsz: ElfXWord,
         pub p_align: ElfXWord,
     }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Dyn {
+        pub d_tag: DynamicTag,
+        pub d_val: ElfXWord,
+    }
+
+    impl Dyn {
+        #[inline]
+        pub const fn tag(&self) -> DynamicTag {
+            self.d_tag
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Rel {
+        pub r_offset: ElfAddr,
+        pub r_info: ElfXWord,
+    }
+
+    impl Rel {
+        #[inline]
+        pub const fn r_sym(&self) -> u32 {
+            (self.r_info >> 32) as u32
+        }
+
+        #[inline]
+        pub const fn r_type(&self) -> RelocationType {
+            RelocationType(self.r_info as u32)
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Rela {
+        pub r_offset: ElfAddr,
+        pub r_info: ElfXWord,
+        pub r_addend: i64,
+    }
+
+    impl Rela {
+        #[inline]
+        pub const fn r_sym(&self) -> u32 {
+            (self.r_info >> 32) as u32
+        }
+
+        #[inline]
+        pub const fn r_type(&self) -> RelocationType {
+            RelocationType(self.r_info as u32)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::size_of;
+
+    #[test]
+    fn layout() {
+        assert_eq!(size_of::<elf32::Header>(), 52);
+        assert_eq!(size_of::<elf32::ProgramHeader>(), 32);
+        assert_eq!(size_of::<elf32::Dyn>(), 8);
+        assert_eq!(size_of::<elf32::Rel>(), 8);
+        assert_eq!(size_of::<elf32::Rela>(), 12);
+        assert_eq!(size_of::<elf64::Header>(), 64);
+        assert_eq!(size_of::<elf64::ProgramHeader>(), 56);
+        assert_eq!(size_of::<elf64::Dyn>(), 16);
+        assert_eq!(size_of::<elf64::Rel>(), 16);
+        assert_eq!(size_of::<elf64::Rela>(), 24);
+    }
+
+    #[test]
+    fn dynamic_tag() {
+        let entry = elf32::Dyn {
+            d_tag: 36,
+            d_val: 0x1000,
+        };
+        assert_eq!(entry.tag(), DT_RELR);
+        let entry = elf32::Dyn {
+            d_tag: -1,
+            d_val: 0,
+        };
+        assert_eq!(entry.tag(), DynamicTag(-1));
+        let entry = elf64::Dyn {
+            d_tag: DT_JMPREL,
+            d_val: 0x2000,
+        };
+        assert_eq!(entry.tag(), DT_JMPREL);
+    }
+
+    #[test]
+    fn relocation_info() {
+        let rel = elf32::Rel {
+            r_offset: 0x1000,
+            r_info: (5 << 8) | 8,
+        };
+        assert_eq!(rel.r_sym(), 5);
+        assert_eq!(rel.r_type(), R_386_RELATIVE);
+        let rela = elf32::Rela {
+            r_offset: 0x1000,
+            r_info: 3,
+            r_addend: -4,
+        };
+        assert_eq!(rela.r_sym(), 0);
+        assert_eq!(rela.r_type(), R_RISCV_RELATIVE);
+
+        let rel = elf64::Rel {
+            r_offset: 0x1000,
+            r_info: (7 << 32) | 8,
+        };
+        assert_eq!(rel.r_sym(), 7);
+        assert_eq!(rel.r_type(), R_X86_64_RELATIVE);
+        let rela = elf64::Rela {
+            r_offset: 0x1000,
+            r_info: 1027,
+            r_addend: 0x10,
+        };
+        assert_eq!(rela.r_sym(), 0);
+        assert_eq!(rela.r_type(), R_AARCH64_RELATIVE);
+    }
+
+    #[test]
+    fn header_is_valid() {
+        let mut header = elf64::Header {
+            e_ident: [0; EI_NIDENT],
+            e_type: ET_EXEC,
+            e_machine: EM_X86_64,
+            e_version: EV_CURRENT as ElfWord,
+            e_entry: 0,
+            e_phoff: 0,
+            e_shoff: 0,
+            e_flags: 0,
+            e_ehsize: 64,
+            e_phentsize: 56,
+            e_phnum: 0,
+            e_shentsize: 0,
+            e_shnum: 0,
+            e_shstrndx: 0,
+        };
+        header.e_ident[..4].copy_from_slice(&ELFMAG);
+        header.e_ident[EI_CLASS] = ELFCLASS64;
+        header.e_ident[EI_DATA] = ELFDATA2LSB;
+        header.e_ident[EI_VERSION] = EV_CURRENT;
+        assert!(header.is_valid(ET_EXEC, EM_X86_64));
+        assert!(!header.is_valid(ET_DYN, EM_X86_64));
+        assert!(!header.is_valid(ET_EXEC, EM_AARCH64));
+        header.e_ident[EI_CLASS] = ELFCLASS32;
+        assert!(!header.is_valid(ET_EXEC, EM_X86_64));
+    }
 }
@@ -76,21 +76,19 @@ impl DeviceTree<'_> {
         )
     }
 
-    pub fn memory_map(&self) -> Option<impl Iterator<Item = (u64, u64)>> {
-        unsafe {
-            let root = self.root();
-            let address_cells = root.address_cells();
-            let size_cells = root.size_cells();
-            let memory = match root.memory() {
-                Some(v) => v,
-                None => return None,
-            };
-            let slice = match memory.get_prop(PropName::REG) {
-                Some(prop) => slice::from_raw_parts(prop.ptr(), prop.len() / 4),
-                None => return None,
-            };
-            Some(AddressAndSizeIter::new(slice, address_cells, size_cells))
-        }
+    /// Returns the ranges of all `/memory` nodes
+    pub fn memory_map(&self) -> Option<impl Iterator<Item = (u64, u64)> + '_> {
+        let root = self.root();
+        let address_cells = root.address_cells();
+        let size_cells = root.size_cells();
+        root.memory()?;
+        Some(
+            root.memory_nodes()
+                .filter_map(|node| node.get_prop(PropName::REG))
+                .flat_map(move |prop| {
+                    AddressAndSizeIter::new(prop.words(), address_cells, size_cells)
+                }),
+        )
     }
 
     pub fn reserved_memory_map(&self) -> Option<Node<'_>> {
@@ -372,11 +370,11 @@ impl<'a> Node<'a> {
     }
 
     #[inline]
-    pub fn props(&self) -> FdtProps<'_> {
+    pub fn props(&self) -> FdtProps<'a> {
         FdtProps::new(self.tokens())
     }
 
-    pub fn get_prop(&self, prop_name: PropName) -> Option<FdtProperty<'_>> {
+    pub fn get_prop(&self, prop_name: PropName) -> Option<FdtProperty<'a>> {
         for prop in self.props() {
             if prop.name() == prop_name {
                 return Some(prop);
@@ -395,6 +393,16 @@ impl<'a> Node<'a> {
         self.get_prop(prop_name).and_then(|v| v.as_u32())
     }
 
+    #[inline]
+    pub fn get_prop_u64(&self, prop_name: PropName) -> Option<u64> {
+        self.get_prop(prop_name).and_then(|v| v.as_u64())
+    }
+
+    #[inline]
+    pub fn has_prop(&self, prop_name: PropName) -> bool {
+        self.get_prop(prop_name).is_some()
+    }
+
     pub fn find_first_child(&self, prefix: NodeName) -> Option<Node<'_>> {
         let mut level = 0;
         let mut iter = self.tokens();
@@ -565,12 +573,18 @@ impl RootNode<'_> {
         self.find_first_child(NodeName::ALIASES)
     }
 
-    /// TODO: multiple nodes
     #[inline]
     pub fn memory(&self) -> Option<Node<'_>> {
         self.find_first_child(NodeName::MEMORY)
     }
 
+    /// Returns all available `/memory` nodes
+    #[inline]
+    pub fn memory_nodes(&self) -> impl Iterator<Item = Node<'_>> {
+        self.children()
+            .filter(|v| v.name().without_unit() == NodeName::MEMORY && v.status_is_ok())
+    }
+
     #[inline]
     pub fn reserved_memory(&self) -> Option<Node<'_>> {
         self.find_first_child(NodeName::RESERVED_MEMORY)
@@ -636,6 +650,14 @@ impl ChosenNode<'_> {
     pub fn stdin_path(&self) -> Option<&str> {
         self.node.get_prop_str(PropName::STDIN_PATH)
     }
+
+    /// Returns the range of the initrd from `linux,initrd-start` and `linux,initrd-end`
+    #[inline]
+    pub fn initrd(&self) -> Option<(u64, u64)> {
+        let start = self.node.get_prop_u64(PropName::LINUX_INITRD_START)?;
+        let end = self.node.get_prop_u64(PropName::LINUX_INITRD_END)?;
+        (end > start).then(|| (start, end - start))
+    }
 }
 
 impl<'a> Deref for ChosenNode<'a> {
@@ -711,6 +733,10 @@ pub struct PropName<'a>(pub &'a str);
 impl PropName<'_> {
     /// Well-known property name `#address-cells`, <u32>
     pub const ADDRESS_CELLS: Self = Self("#address-cells");
+    /// Well-known property name `alignment`, <prop-encoded-array>
+    pub const ALIGNMENT: Self = Self("alignment");
+    /// Well-known property name `alloc-ranges`, <prop-encoded-array>
+    pub const ALLOC_RANGES: Self = Self("alloc-ranges");
     /// Well-known property name `bootargs`, <string>
     pub const BOOTARGS: Self = Self("bootargs");
     /// `#clock-cells`
@@ -739,6 +765,10 @@ impl PropName<'_> {
     pub const INTERRUPT_PARENT: Self = Self("interrupt-parent");
     /// Well-known property name `interrupts-extended`, <phandle> <prop-encoded-array>
     pub const INTERRUPTS_EXTENDED: Self = Self("interrupts-extended");
+    /// Well-known property name `linux,initrd-end`, <u32> or <u64>
+    pub const LINUX_INITRD_END: Self = Self("linux,initrd-end");
+    /// Well-known property name `linux,initrd-start`, <u32> or <u64>
+    pub const LINUX_INITRD_START: Self = Self("linux,initrd-start");
     /// Well-known property name `model`, <string>
     pub const MODEL: Self = Self("model");
     /// Well-known property name `name`, <string> (deprecated)
@@ -755,6 +785,8 @@ impl PropName<'_> {
     pub const REUSABLE: Self = Self("reusable");
     /// Well-known property name `serial-number`, <string>
     pub const SERIAL_NUMBER: Self = Self("serial-number");
+    /// Well-known property name `size`, <prop-encoded-array>
+    pub const SIZE: Self = Self("size");
     /// Well-known property name `#size-cells`, <u32>
     pub const SIZE_CELLS: Self = Self("#size-cells");
     /// Well-known property name `status`, <string>
@@ -912,6 +944,27 @@ impl<'a> FdtProperty<'a> {
         }
     }
 
+    /// Returns the value of a property that consists of one or two cells
+    #[inline]
+    pub fn as_u64(&self) -> Option<u64> {
+        let mut iter = self.words().iter();
+        match self.len {
+            4 => fdt_get_reg_val(&mut iter, 1).ok(),
+            8 => fdt_get_reg_val(&mut iter, 2).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the pairs of address and size in the property
+    #[inline]
+    pub fn address_and_size(
+        &self,
+        address_cells: u32,
+        size_cells: u32,
+    ) -> impl Iterator<Item = (u64, u64)> + 'a {
+        AddressAndSizeIter::new(self.words(), address_cells, size_cells)
+    }
+
     #[inline]
     pub fn string_list(self) -> impl Iterator<Item = &'a str> {
         StringListIter::new(unsafe { self.ptr() }, self.len())
@@ -1072,3 +1125,222 @@ fn fdt_get_reg_val(iter: &mut Iter<BeU32>, cell_size: u32) -> Result<u64, ()> {
 #[repr(transparent)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct PHandle(pub u32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal blob builder for the tests
+    #[derive(Default)]
+    struct Builder {
+        dt_struct: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Builder {
+        fn u32(&mut self, val: u32) -> &mut Self {
+            self.dt_struct.extend_from_slice(&val.to_be_bytes());
+            self
+        }
+
+        fn pad(&mut self) {
+            self.dt_struct
+                .resize(self.dt_struct.len().next_multiple_of(4), 0);
+        }
+
+        fn begin(&mut self, name: &str) -> &mut Self {
+            self.u32(DeviceTree::FDT_BEGIN_NODE);
+            self.dt_struct.extend_from_slice(name.as_bytes());
+            self.dt_struct.push(0);
+            self.pad();
+            self
+        }
+
+        fn end(&mut self) -> &mut Self {
+            self.u32(DeviceTree::FDT_END_NODE)
+        }
+
+        fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_offset = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.u32(DeviceTree::FDT_PROP)
+                .u32(value.len() as u32)
+                .u32(name_offset);
+            self.dt_struct.extend_from_slice(value);
+            self.pad();
+            self
+        }
+
+        fn prop_cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+            let value = cells
+                .iter()
+                .flat_map(|v| v.to_be_bytes())
+                .collect::<Vec<_>>();
+            self.prop(name, &value)
+        }
+
+        /// Returns the blob in a buffer aligned for the header
+        fn finish(&mut self, rsvmap: &[(u64, u64)]) -> Vec<u64> {
+            self.u32(DeviceTree::FDT_END);
+            let mut rsv = Vec::new();
+            for (base, size) in rsvmap.iter().chain(&[(0, 0)]) {
+                rsv.extend_from_slice(&base.to_be_bytes());
+                rsv.extend_from_slice(&size.to_be_bytes());
+            }
+            let off_mem_rsvmap = 40;
+            let off_dt_struct = off_mem_rsvmap + rsv.len();
+            let off_dt_strings = off_dt_struct + self.dt_struct.len();
+            let total_size = off_dt_strings + self.strings.len();
+            let mut blob = Vec::new();
+            for val in [
+                Header::MAGIC,
+                total_size as u32,
+                off_dt_struct as u32,
+                off_dt_strings as u32,
+                off_mem_rsvmap as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.dt_struct.len() as u32,
+            ] {
+                blob.extend_from_slice(&val.to_be_bytes());
+            }
+            blob.extend_from_slice(&rsv);
+            blob.extend_from_slice(&self.dt_struct);
+            blob.extend_from_slice(&self.strings);
+            blob.resize(blob.len().next_multiple_of(8), 0);
+            blob.chunks(8)
+                .map(|v| u64::from_ne_bytes(v.try_into().unwrap()))
+                .collect()
+        }
+    }
+
+    fn as_bytes(blob: &[u64]) -> &[u8] {
+        unsafe { slice::from_raw_parts(blob.as_ptr() as *const u8, blob.len() * 8) }
+    }
+
+    fn sample() -> Vec<u64> {
+        Builder::default()
+            .begin("")
+            .prop_cells("#address-cells", &[2])
+            .prop_cells("#size-cells", &[2])
+            .begin("chosen")
+            .prop_cells("linux,initrd-start", &[0, 0x4800_0000])
+            .prop_cells("linux,initrd-end", &[0x4810_0000])
+            .end()
+            .begin("memory@40000000")
+            .prop("device_type", b"memory\0")
+            .prop_cells("reg", &[0, 0x4000_0000, 0, 0x1000_0000])
+            .end()
+            .begin("memory@80000000")
+            .prop_cells(
+                "reg",
+                &[0, 0x8000_0000, 0, 0x0800_0000, 1, 0, 0, 0x1000_0000],
+            )
+            .end()
+            .begin("memory@c0000000")
+            .prop("status", b"disabled\0")
+            .prop_cells("reg", &[0, 0xc000_0000, 0, 0x1000_0000])
+            .end()
+            .end()
+            .finish(&[(0x4000_0000, 0x1000)])
+    }
+
+    #[test]
+    fn memory_nodes() {
+        let blob = sample();
+        let dt = DeviceTree::from_slice(as_bytes(&blob)).unwrap();
+
+        assert_eq!(dt.root().memory_nodes().count(), 2);
+        assert_eq!(
+            dt.memory_map().unwrap().collect::<Vec<_>>(),
+            [
+                (0x4000_0000, 0x1000_0000),
+                (0x8000_0000, 0x0800_0000),
+                (0x1_0000_0000, 0x1000_0000),
+            ]
+        );
+        assert_eq!(
+            dt.header().reserved_maps().collect::<Vec<_>>(),
+            [(0x4000_0000, 0x1000)]
+        );
+    }
+
+    #[test]
+    fn memory_map_without_memory() {
+        let blob = Builder::default()
+            .begin("")
+            .prop_cells("#address-cells", &[1])
+            .prop_cells("#size-cells", &[1])
+            .end()
+            .finish(&[]);
+        let dt = DeviceTree::from_slice(as_bytes(&blob)).unwrap();
+
+        assert!(dt.memory_map().is_none());
+        assert_eq!(dt.root().memory_nodes().count(), 0);
+    }
+
+    #[test]
+    fn as_u64() {
+        let blob = Builder::default()
+            .begin("")
+            .prop_cells("one", &[0x1234_5678])
+            .prop_cells("two", &[0x9abc_def0, 0x1234_5678])
+            .prop_cells("three", &[1, 2, 3])
+            .prop("empty", &[])
+            .end()
+            .finish(&[]);
+        let dt = DeviceTree::from_slice(as_bytes(&blob)).unwrap();
+        let root = dt.root();
+
+        assert_eq!(root.get_prop_u64(PropName("one")), Some(0x1234_5678));
+        assert_eq!(
+            root.get_prop_u64(PropName("two")),
+            Some(0x9abc_def0_1234_5678)
+        );
+        assert_eq!(root.get_prop_u64(PropName("three")), None);
+        assert_eq!(root.get_prop_u64(PropName("empty")), None);
+        assert_eq!(root.get_prop_u64(PropName("missing")), None);
+        assert!(root.has_prop(PropName("empty")));
+        assert!(!root.has_prop(PropName("missing")));
+    }
+
+    #[test]
+    fn initrd() {
+        let blob = sample();
+        let dt = DeviceTree::from_slice(as_bytes(&blob)).unwrap();
+
+        assert_eq!(
+            dt.root().chosen().unwrap().initrd(),
+            Some((0x4800_0000, 0x10_0000))
+        );
+    }
+
+    #[test]
+    fn initrd_invalid() {
+        for (start, end) in [(0x4810_0000u32, 0x4800_0000u32), (0x4800_0000, 0x4800_0000)] {
+            let blob = Builder::default()
+                .begin("")
+                .begin("chosen")
+                .prop_cells("linux,initrd-start", &[start])
+                .prop_cells("linux,initrd-end", &[end])
+                .end()
+                .end()
+                .finish(&[]);
+            let dt = DeviceTree::from_slice(as_bytes(&blob)).unwrap();
+            assert_eq!(dt.root().chosen().unwrap().initrd(), None);
+        }
+
+        let blob = Builder::default()
+            .begin("")
+            .begin("chosen")
+            .prop_cells("linux,initrd-start", &[0x4800_0000])
+            .end()
+            .end()
+            .finish(&[]);
+        let dt = DeviceTree::from_slice(as_bytes(&blob)).unwrap();
+        assert_eq!(dt.root().chosen().unwrap().initrd(), None);
+    }
+}
@@ -300,8 +300,12 @@ impl<'a> Leb128Reader<'a> {
     }
 
     pub fn read_bytes<'b>(&'b mut self, size: usize) -> Result<&'a [u8], ReadError> {
+        let end = self
+            .position
+            .checked_add(size)
+            .ok_or(ReadError::OutOfBounds)?;
         self.slice
-            .get(self.position..self.position + size)
+            .get(self.position..end)
             .map(|v| {
                 self.position += size;
                 v
@@ -312,7 +316,8 @@ impl<'a> Leb128Reader<'a> {
     #[inline]
     pub fn read_blob<'b>(&'b mut self) -> Result<&'a [u8], ReadError> {
         self.read_unsigned()
-            .and_then(move |size| self.read_bytes(size as usize))
+            .and_then(|size| usize::try_from(size).map_err(|_| ReadError::OutOfBounds))
+            .and_then(move |size| self.read_bytes(size))
     }
 }
 
@@ -363,6 +368,9 @@ impl Leb128Reader<'_> {
                 None => return Err(ReadError::UnexpectedEof),
             };
             cursor += 1;
+            if scale >= u64::BITS {
+                return Err(ReadError::InvalidData);
+            }
 
             value |= (d as u64 & 0x7F) << scale;
             scale += 7;
@@ -384,6 +392,9 @@ impl Leb128Reader<'_> {
                 None => return Err(ReadError::UnexpectedEof),
             };
             cursor += 1;
+            if scale >= u64::BITS {
+                return Err(ReadError::InvalidData);
+            }
 
             value |= (d as u64 & 0x7F) << scale;
             let signed = (d & 0x40) != 0;
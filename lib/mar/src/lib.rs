@@ -237,33 +237,166 @@ impl<'a> ArchiveReader<'a> {
     }
 }
 
-impl<'a> Iterator for ArchiveReader<'a> {
-    type Item = Entry<'a>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let tag = self.reader.read_byte().unwrap();
+impl<'a> ArchiveReader<'a> {
+    /// Reads the next entry, an error is returned for a truncated or unknown entry
+    pub fn read_entry(&mut self) -> Result<Entry<'a>, ReadError> {
+        let tag = self.reader.read_byte()?;
         match tag {
             TAG_NAMESPACE => {
-                let blob = self.reader.read_blob().ok()?;
+                let blob = self.reader.read_blob()?;
                 let mut reader = Leb128Reader::from_slice(blob);
-                let name: &str = reader.read().ok()?;
-                let xattr: ExtendedAttributes = reader.read().ok()?;
-                Some(Entry::Namespace(name, xattr))
+                let name: &str = reader.read()?;
+                let xattr: ExtendedAttributes = reader.read()?;
+                Ok(Entry::Namespace(name, xattr))
             }
             TAG_FILE => {
-                let blob = self.reader.read_blob().ok()?;
+                let blob = self.reader.read_blob()?;
                 let mut reader = Leb128Reader::from_slice(blob);
-                let name: &str = reader.read().ok()?;
-                let xattr: ExtendedAttributes = reader.read().ok()?;
-                let content = reader.read_blob().ok()?;
-                Some(Entry::File(name, xattr, content))
+                let name: &str = reader.read()?;
+                let xattr: ExtendedAttributes = reader.read()?;
+                let content = reader.read_blob()?;
+                Ok(Entry::File(name, xattr, content))
             }
             TAG_END => {
-                self.reader.read_blob().ok()?;
-                Some(Entry::End)
+                self.reader.read_blob()?;
+                Ok(Entry::End)
             }
-            // _ => panic!("UNKNOWN_TAG {tag:08x}"),
-            _ => None,
+            _ => Err(ReadError::InvalidData),
+        }
+    }
+}
+
+impl<'a> Iterator for ArchiveReader<'a> {
+    type Item = Entry<'a>;
+
+    /// Returns the next entry, or `None` if the entry cannot be read
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut writer = ArchiveWriter::new();
+        writer
+            .write(Entry::File(
+                "hello.txt",
+                ExtendedAttributes::empty(),
+                b"hello",
+            ))
+            .unwrap();
+        writer
+            .write(Entry::Namespace("/bin", ExtendedAttributes::empty()))
+            .unwrap();
+        writer
+            .write(Entry::File("empty", ExtendedAttributes::empty(), &[]))
+            .unwrap();
+        writer.finalize(&[]).unwrap()
+    }
+
+    #[test]
+    fn read_write() {
+        let archive = sample();
+        let mut reader = ArchiveReader::from_slice(&archive).unwrap();
+
+        match reader.read_entry() {
+            Ok(Entry::File("hello.txt", _, b"hello")) => {}
+            _ => panic!("expected hello.txt"),
+        }
+        match reader.read_entry() {
+            Ok(Entry::Namespace("/bin", _)) => {}
+            _ => panic!("expected /bin"),
+        }
+        match reader.read_entry() {
+            Ok(Entry::File("empty", _, &[])) => {}
+            _ => panic!("expected empty"),
+        }
+        assert!(matches!(reader.read_entry(), Ok(Entry::End)));
+        assert!(matches!(reader.read_entry(), Err(ReadError::UnexpectedEof)));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn additional_data() {
+        let mut writer = ArchiveWriter::new();
+        writer
+            .write(Entry::File("a", ExtendedAttributes::empty(), b"b"))
+            .unwrap();
+        let archive = writer.finalize(b"boot sector").unwrap();
+
+        assert_eq!(&archive[16..27], b"boot sector");
+        let mut reader = ArchiveReader::from_slice(&archive).unwrap();
+        assert!(matches!(reader.next(), Some(Entry::File("a", _, b"b"))));
+        assert!(matches!(reader.next(), Some(Entry::End)));
+    }
+
+    #[test]
+    fn invalid_header() {
+        let archive = sample();
+
+        assert!(ArchiveReader::from_slice(&archive[..15]).is_err());
+
+        let mut bad_magic = archive.clone();
+        bad_magic[0] ^= 1;
+        assert!(ArchiveReader::from_slice(&bad_magic).is_err());
+
+        // The body exceeds the image
+        assert!(ArchiveReader::from_slice(&archive[..archive.len() - 1]).is_err());
+    }
+
+    /// Returns the archive whose body is replaced with the bytes
+    fn with_body(body: &[u8]) -> Vec<u8> {
+        let mut archive = Header::new().into_bytes().to_vec();
+        archive[8..12].copy_from_slice(&16u32.to_le_bytes());
+        archive[12..16].copy_from_slice(&(body.len() as u32).to_le_bytes());
+        archive.extend_from_slice(body);
+        archive
+    }
+
+    #[test]
+    fn corrupted_entries() {
+        for body in [
+            // no end tag
+            &[][..],
+            // unknown tag
+            &[0x7f, 0x00],
+            // truncated size of the blob
+            &[TAG_FILE, 0x80],
+            // blob exceeds the body
+            &[TAG_FILE, 0x10, 0x00],
+            // huge blob size
+            &[
+                TAG_FILE, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+            ],
+            // overlong size
+            &[
+                TAG_NAMESPACE,
+                0x80,
+                0x80,
+                0x80,
+                0x80,
+                0x80,
+                0x80,
+                0x80,
+                0x80,
+                0x80,
+                0x80,
+                0x00,
+            ],
+            // name exceeds the entry
+            &[TAG_NAMESPACE, 0x02, 0x05, 0x41],
+            // name is not UTF-8
+            &[TAG_NAMESPACE, 0x03, 0x01, 0xff, 0x00],
+            // content is missing
+            &[TAG_FILE, 0x03, 0x01, 0x41, 0x00],
+        ] {
+            let archive = with_body(body);
+            let mut reader = ArchiveReader::from_slice(&archive).unwrap();
+            assert!(reader.read_entry().is_err(), "{:02x?}", body);
         }
     }
 }
@@ -41,6 +41,43 @@ impl<T, const N: usize> FixedVec<T, N> {
         }
     }
 
+    /// Inserts an element at `index`, shifting all elements after it to the right
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, val: T) -> Result<(), T> {
+        let len = self.len();
+        assert!(index <= len, "insertion index out of bounds");
+        if len >= self.capacity() {
+            return Err(val);
+        }
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            ptr::copy(p, p.add(1), len - index);
+            p.write(val);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes and returns the element at `index`, shifting all elements after it to the left
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(index < len, "removal index out of bounds");
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            let val = p.read();
+            ptr::copy(p.add(1), p, len - index - 1);
+            self.len -= 1;
+            val
+        }
+    }
+
     #[inline]
     pub const fn len(&self) -> usize {
         self.len
@@ -233,6 +270,35 @@ mod tests {
         vec.push(1234).expect("push_out_of_bounds");
     }
 
+    #[test]
+    fn insert_remove() {
+        let mut vec = FixedVec::<i32, 5>::new();
+
+        vec.insert(0, 3).unwrap();
+        vec.insert(0, 1).unwrap();
+        vec.insert(1, 2).unwrap();
+        vec.insert(3, 5).unwrap();
+        vec.insert(3, 4).unwrap();
+        assert_eq!(vec.as_slice(), &[1, 2, 3, 4, 5]);
+        assert_eq!(vec.insert(2, 6), Err(6));
+        assert_eq!(vec.as_slice(), &[1, 2, 3, 4, 5]);
+
+        assert_eq!(vec.remove(0), 1);
+        assert_eq!(vec.remove(3), 5);
+        assert_eq!(vec.remove(1), 3);
+        assert_eq!(vec.as_slice(), &[2, 4]);
+        assert_eq!(vec.len(), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn remove_out_of_bounds() {
+        let mut vec = FixedVec::<i32, 10>::new();
+
+        vec.push(1).unwrap();
+        vec.remove(1);
+    }
+
     #[test]
     fn retain() {
         let mut vec = FixedVec::<i32, 10>::new();
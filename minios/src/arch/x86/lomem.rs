@@ -20,7 +20,7 @@ pub struct LoMemoryManager {
 
 #[allow(unused)]
 impl LoMemoryManager {
-    const PAGE_SIZE: usize = 0x1000;
+    pub const PAGE_SIZE: usize = 0x1000;
 
     const PAGE_SIZE_M1: usize = Self::PAGE_SIZE - 1;
 
@@ -1,11 +1,16 @@
 //! MiniOS Execution Environment
 
+use crate::fs::FileError;
+use crate::fs::initrd::Initrd;
+use crate::fs::vfs::Vfs;
 use crate::io::fonts;
 use crate::io::graphics::display::FbDisplay8;
 use crate::io::graphics::fbcon::FbCon;
 use crate::io::graphics::{GraphicsOutputDevice, PixelFormat};
+use crate::io::media::partition::{ChsGeometry, SharedBlockDevice};
+use crate::io::tty::line_editor::LineEditor;
 use crate::io::tty::{SimpleTextInput, SimpleTextOutput};
-use crate::mem::MemoryManager;
+use crate::mem::{MemoryManager, MemoryType};
 use crate::null::NullTty;
 use crate::platform::*;
 use crate::*;
@@ -15,7 +20,9 @@ use core::mem::MaybeUninit;
 use core::ops::Range;
 use core::panic::PanicInfo;
 use core::ptr::NonNull;
+use core::time::Duration;
 use guid::Guid;
+use libhid::Modifier;
 
 static mut SYSTEM: MaybeUninit<System> = MaybeUninit::zeroed();
 
@@ -30,9 +37,13 @@ pub struct System {
     stdout: NonNull<dyn SimpleTextOutput>,
     stderr: NonNull<dyn SimpleTextOutput>,
     console_controller: ConsoleController,
+    line_editor: LineEditor,
 
     smbios: Option<smbios::SmBios>,
     device_tree: Option<fdt::DeviceTree<'static>>,
+    initrd: Option<Arc<Initrd>>,
+    vfs: Vfs,
+    block_devices: Vec<BlockDeviceEntry>,
 }
 
 #[repr(C)]
@@ -56,8 +67,12 @@ impl System {
                 stdout: NonNull::new(&raw mut NULL).unwrap(),
                 stderr: NonNull::new(&raw mut NULL).unwrap(),
                 console_controller: ConsoleController::new(),
+                line_editor: LineEditor::new(),
                 smbios: None,
                 device_tree: None,
+                initrd: None,
+                vfs: Vfs::new(),
+                block_devices: Vec::new(),
             };
 
             (&mut *(&raw mut SYSTEM)).write(env);
@@ -82,14 +97,20 @@ impl System {
                     reserved_memory_size: 0,
                     start_conventional_memory: 0,
                     conventional_memory_size: 0,
+                    initrd_base: 0,
+                    initrd_size: 0,
                 },
                 config_table: Vec::new(),
                 stdin: NonNull::new(&raw mut NULL).unwrap(),
                 stdout: NonNull::new(&raw mut NULL).unwrap(),
                 stderr: NonNull::new(&raw mut NULL).unwrap(),
                 console_controller: ConsoleController::new(),
+                line_editor: LineEditor::new(),
                 smbios: None,
                 device_tree: None,
+                initrd: None,
+                vfs: Vfs::new(),
+                block_devices: Vec::new(),
             };
             shared.device_tree = fdt::DeviceTree::parse(dtb as *const u8).ok();
             (&mut *(&raw mut SYSTEM)).write(shared);
@@ -120,8 +141,22 @@ impl System {
                 );
                 shared.smbios = smbios;
             }
+
+            if shared.initrd.is_none() {
+                if let Some((base, size)) = Self::device_tree()
+                    .and_then(|dt| dt.root().chosen())
+                    .and_then(|v| v.initrd())
+                {
+                    match Initrd::from_static(base as usize as *const u8, size as usize) {
+                        Ok(initrd) => Self::attach_initrd(initrd),
+                        Err(err) => println!("INITRD: {:?}", err),
+                    }
+                }
+            }
         }
 
+        Self::mount_boot_device();
+
         main();
 
         panic!("The system has halted");
@@ -156,12 +191,133 @@ impl System {
         Self::boot_info().platform
     }
 
+    /// Returns the time elapsed since the platform timer was started
+    #[inline]
+    pub fn monotonic() -> Duration {
+        Platform::monotonic()
+    }
+
+    /// Suspends execution for at least the specified duration
+    pub fn sleep(duration: Duration) {
+        let deadline = Self::monotonic() + duration;
+        while Self::monotonic() < deadline {
+            Platform::idle(deadline);
+        }
+    }
+
+    /// Busy-waits for at least the specified number of microseconds
+    pub fn udelay(us: u64) {
+        let deadline = Self::monotonic() + Duration::from_micros(us);
+        while Self::monotonic() < deadline {
+            Hal::cpu().no_op();
+        }
+    }
+
     #[inline]
     pub fn smbios<'a>() -> Option<&'a smbios::SmBios> {
         let shared = Self::shared();
         shared.smbios.as_ref()
     }
 
+    /// Returns the initial ramdisk found at boot
+    #[inline]
+    pub fn initrd<'a>() -> Option<&'a Initrd> {
+        let shared = Self::shared();
+        shared.initrd.as_deref()
+    }
+
+    /// Sets the initial ramdisk provided by the loader and reserves its range
+    ///
+    /// # Safety
+    ///
+    /// The range must contain a valid image and must not be used for other purposes.
+    pub unsafe fn set_initrd(base: usize, size: usize) -> Result<(), FileError> {
+        unsafe {
+            let initrd = Initrd::from_static(base as *const u8, size)?;
+            let _ = MemoryManager::register_memmap(
+                base as u64..(base + size) as u64,
+                MemoryType::Reserved,
+            );
+            Self::attach_initrd(initrd);
+        }
+        Ok(())
+    }
+
+    /// Keeps the initrd and mounts it on the root unless another file system is there
+    fn attach_initrd(initrd: Initrd) {
+        let initrd = Arc::new(initrd);
+        let vfs = Self::vfs();
+        if !vfs.is_mounted("/") {
+            let _ = vfs.mount("/", initrd.clone());
+        }
+        unsafe {
+            Self::shared_mut().initrd = Some(initrd);
+        }
+    }
+
+    /// Returns the virtual file system
+    #[inline]
+    pub fn vfs<'a>() -> &'a mut Vfs {
+        unsafe {
+            let shared = Self::shared_mut();
+            &mut shared.vfs
+        }
+    }
+
+    /// Registers the block device found by the platform
+    pub unsafe fn register_block_device(
+        name: &str,
+        device: SharedBlockDevice,
+        geometry: Option<ChsGeometry>,
+        is_boot_device: bool,
+    ) {
+        unsafe {
+            let shared = Self::shared_mut();
+            shared.block_devices.push(BlockDeviceEntry {
+                name: name.to_owned(),
+                device,
+                geometry,
+                is_boot_device,
+            });
+        }
+    }
+
+    /// Mounts the file system on the block device, or on the first partition that has one
+    pub fn mount_block_device(name: &str, path: &str) -> Result<(), FileError> {
+        let entry = Self::block_devices()
+            .iter()
+            .find(|v| v.name == name)
+            .ok_or(FileError::NotFound)?;
+        let fs = crate::fs::open_volume(&entry.device, entry.geometry)?;
+        Self::vfs().mount(path, fs)
+    }
+
+    /// Mounts the boot device on the root, or on `/boot` if the initrd is already there
+    fn mount_boot_device() {
+        let Some(entry) = Self::boot_device() else {
+            return;
+        };
+        let path = if Self::vfs().is_mounted("/") {
+            "/boot"
+        } else {
+            "/"
+        };
+        // The boot device may have no file system, such as a CD-ROM
+        let _ = Self::mount_block_device(&entry.name, path);
+    }
+
+    #[inline]
+    pub fn block_devices<'a>() -> &'a [BlockDeviceEntry] {
+        let shared = Self::shared();
+        &shared.block_devices
+    }
+
+    /// Returns the block device the system was booted from
+    #[inline]
+    pub fn boot_device<'a>() -> Option<&'a BlockDeviceEntry> {
+        Self::block_devices().iter().find(|v| v.is_boot_device)
+    }
+
     #[inline]
     pub fn device_tree<'a>() -> Option<&'a fdt::DeviceTree<'a>> {
         let shared = Self::shared();
@@ -175,6 +331,12 @@ impl System {
         unsafe {
             // let shared = Self::shared_mut();
 
+            // Pending writes would be lost with minios
+            let _ = Self::vfs().sync();
+            for entry in Self::block_devices() {
+                let _ = entry.device.lock().flush();
+            }
+
             Platform::exit();
 
             *(&mut *(&raw mut SYSTEM)) = MaybeUninit::zeroed();
@@ -213,64 +375,18 @@ impl System {
         }
     }
 
-    pub fn line_input(max_len: usize) -> Option<String> {
-        let mut buf = Vec::with_capacity(max_len);
-        let stdin = Self::stdin();
-        let stdout = Self::stdout();
-
-        loop {
-            stdout.enable_cursor(true);
-            match stdin.read_key_stroke() {
-                Some(key) => {
-                    stdout.enable_cursor(false);
-                    let key = key.get();
-                    let c = key.unicode_char as u8 as char;
-                    match c {
-                        // ctrl-c
-                        '\x03' => {
-                            return None;
-                        }
-                        // backspace
-                        '\x08' | '\x7f' => match buf.pop() {
-                            Some(c) => {
-                                if c < ' ' {
-                                    stdout.write_str("\x08\x08  \x08\x08").unwrap();
-                                } else {
-                                    stdout.write_str("\x08 \x08").unwrap();
-                                }
-                            }
-                            None => {}
-                        },
-                        // enter
-                        '\x0a' | '\x0d' => {
-                            stdout.write_str("\r\n").unwrap();
-                            break;
-                        }
-                        _ => {
-                            if buf.len() < max_len {
-                                if c < ' ' {
-                                    // control char
-                                    stdout.write_char('^').unwrap();
-                                    stdout.write_char((c as u8 | 0x40) as char).unwrap();
-                                    buf.push(c);
-                                } else if c <= '\x7E' {
-                                    // printable char
-                                    let _ = stdout.write_char(c);
-                                    buf.push(c);
-                                } else {
-                                    // TODO: unprintable char
-                                }
-                            }
-                        }
-                    }
-                }
-                None => {
-                    // assert!(unsafe { Hal::cpu().is_interrupt_enabled() });
-                    Hal::cpu().wait_for_interrupt();
-                }
-            }
+    #[inline]
+    pub fn line_editor<'a>() -> &'a mut LineEditor {
+        unsafe {
+            let shared = Self::shared_mut();
+            &mut shared.line_editor
         }
-        Some(buf.into_iter().collect())
+    }
+
+    /// Reads a line with the shared [`LineEditor`], returns `None` if Ctrl-C is pressed
+    #[inline]
+    pub fn line_input(max_len: usize) -> Option<String> {
+        Self::line_editor().read_line("", max_len, None)
     }
 
     #[inline]
@@ -354,6 +470,9 @@ pub struct SsblInfo {
     pub reserved_memory_size: u32,
     pub start_conventional_memory: u32,
     pub conventional_memory_size: u32,
+    /// Initial ramdisk appended to the kernel image, zero if not present
+    pub initrd_base: u32,
+    pub initrd_size: u32,
 }
 
 impl SsblInfo {
@@ -439,6 +558,7 @@ pub struct ConsoleController {
     text_out: NonNull<dyn SimpleTextOutput>,
     graphics_out: Option<Box<dyn GraphicsOutputDevice>>,
     fbcon: Option<FbCon>,
+    scrollback_lines: usize,
 }
 
 impl ConsoleController {
@@ -449,6 +569,7 @@ impl ConsoleController {
             text_out: NonNull::new(&raw mut NULL).unwrap(),
             graphics_out: None,
             fbcon: None,
+            scrollback_lines: FbCon::DEFAULT_SCROLLBACK_LINES,
         }
     }
 
@@ -484,6 +605,14 @@ impl ConsoleController {
         }
     }
 
+    /// Returns the list of modes supported by the graphics device
+    pub fn graphics_modes(&self) -> &[io::graphics::ModeInfo] {
+        self.graphics_out
+            .as_ref()
+            .map(|v| v.modes())
+            .unwrap_or_default()
+    }
+
     /// Returns current graphics mode if in graphics mode
     pub fn current_graphics_mode(&self) -> Option<&io::graphics::CurrentMode> {
         (self.is_graphics_mode())
@@ -538,7 +667,9 @@ impl ConsoleController {
                 current_mode.info.width as u32,
                 current_mode.info.height as u32,
             );
-            self.fbcon = FbCon::new(display, font).into();
+            let mut fbcon = FbCon::new(display, font);
+            fbcon.set_scrollback_limit(self.scrollback_lines);
+            self.fbcon = fbcon.into();
 
             // SAFETY: to avoid lifetime
             System::_set_stdout(core::mem::transmute(
@@ -553,6 +684,36 @@ impl ConsoleController {
         Ok(())
     }
 
+    /// Sets the number of lines kept in the scrollback buffer of the framebuffer console
+    pub fn set_scrollback_lines(&mut self, lines: usize) {
+        self.scrollback_lines = lines;
+        if let Some(fbcon) = self.fbcon.as_mut() {
+            fbcon.set_scrollback_limit(lines);
+        }
+    }
+
+    /// Handles console control keys such as Shift+PageUp/PageDown
+    ///
+    /// Returns `None` if the key was consumed by the console.
+    pub fn handle_key(
+        &mut self,
+        key: NonZeroInputKey,
+        modifier: Modifier,
+    ) -> Option<NonZeroInputKey> {
+        if self.is_text_mode() || !modifier.has_shift() {
+            return Some(key);
+        }
+        let Some(fbcon) = self.fbcon.as_mut() else {
+            return Some(key);
+        };
+        match key.get().scan_code {
+            InputKey::SCAN_PAGE_UP => fbcon.page_up(),
+            InputKey::SCAN_PAGE_DOWN => fbcon.page_down(),
+            _ => return Some(key),
+        }
+        None
+    }
+
     pub fn find_graphics_mode(
         &self,
         width: u16,
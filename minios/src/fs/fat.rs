@@ -0,0 +1,850 @@
+//! FAT12/16/32 File System
+
+mod bpb;
+mod dir;
+
+pub use bpb::{Bpb, FatType};
+
+use super::*;
+use crate::sync::spin::SpinMutex;
+use crate::*;
+use alloc::vec;
+use dir::*;
+
+/// FAT file system on a block device
+pub struct FatFs {
+    volume: Arc<SpinMutex<Volume>>,
+    fat_type: FatType,
+    volume_label: [u8; 11],
+    read_only: bool,
+}
+
+impl FatFs {
+    /// Mounts the volume, `device` must start with the boot sector
+    pub fn new(
+        mut device: Box<dyn BlockDevice + Send>,
+        read_only: bool,
+    ) -> Result<Self, FileError> {
+        let block_size = device.media_info().block_size as usize;
+        if block_size == 0 {
+            return Err(FileError::NotSupported);
+        }
+        let mut boot_sector = vec![0; Bpb::MIN_SIZE.next_multiple_of(block_size)];
+        device.read(LBA(0), &mut boot_sector)?;
+        let bpb = Bpb::parse(&boot_sector)?;
+        if !(bpb.bytes_per_sector as usize).is_multiple_of(block_size) {
+            return Err(FileError::NotSupported);
+        }
+
+        let fat_type = bpb.fat_type;
+        let volume_label = bpb.volume_label;
+        let volume = Volume {
+            blocks_per_sector: (bpb.bytes_per_sector as usize / block_size) as u64,
+            buf: vec![0; bpb.bytes_per_sector as usize],
+            fat_buf: vec![0; bpb.bytes_per_sector as usize],
+            fat_sector: None,
+            fat_dirty: false,
+            next_free: 2,
+            fs_info_dirty: false,
+            read_only,
+            device,
+            bpb,
+        };
+
+        Ok(Self {
+            volume: Arc::new(SpinMutex::new(volume)),
+            fat_type,
+            volume_label,
+            read_only,
+        })
+    }
+
+    #[inline]
+    pub const fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    /// Returns the volume label in the boot sector
+    pub fn volume_label(&self) -> String {
+        let len = self
+            .volume_label
+            .iter()
+            .rposition(|v| *v != 0x20)
+            .map_or(0, |v| v + 1);
+        self.volume_label[..len]
+            .iter()
+            .map(|v| *v as char)
+            .collect()
+    }
+
+    /// Returns the cluster size and the number of clusters
+    pub fn cluster_info(&self) -> (u32, u32) {
+        let volume = self.volume.lock();
+        (volume.bpb.bytes_per_cluster(), volume.bpb.cluster_count)
+    }
+}
+
+impl FileSystem for FatFs {
+    fn fs_type(&self) -> &str {
+        match self.fat_type {
+            FatType::Fat12 => "fat12",
+            FatType::Fat16 => "fat16",
+            FatType::Fat32 => "fat32",
+        }
+    }
+
+    #[inline]
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata, FileError> {
+        let mut volume = self.volume.lock();
+        match volume.lookup(path)? {
+            Some(item) => Ok(item.metadata()),
+            None => Ok(Metadata {
+                file_type: FileType::Directory,
+                len: 0,
+            }),
+        }
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn File>, FileError> {
+        let item = {
+            let mut volume = self.volume.lock();
+            volume.lookup(path)?.ok_or(FileError::IsADirectory)?
+        };
+        if item.entry.is_dir() {
+            return Err(FileError::IsADirectory);
+        }
+        let read_only = self.read_only || item.entry.attr.contains(DosAttributes::READONLY);
+        Ok(Box::new(FatFile::new(
+            self.volume.clone(),
+            &item,
+            read_only,
+        )))
+    }
+
+    fn create(&self, path: &str) -> Result<Box<dyn File>, FileError> {
+        if self.read_only {
+            return Err(FileError::ReadOnly);
+        }
+        let (parent, name) = vfs::split_path(path);
+        validate_name(name)?;
+        let item = {
+            let mut volume = self.volume.lock();
+            let dir = volume.lookup_dir(parent)?;
+            let item = match volume.find(dir, name)? {
+                Some(mut item) => {
+                    if item.entry.is_dir() {
+                        return Err(FileError::IsADirectory);
+                    }
+                    if item.entry.attr.contains(DosAttributes::READONLY) {
+                        return Err(FileError::ReadOnly);
+                    }
+                    volume.free_chain(item.entry.first_cluster)?;
+                    item.entry.first_cluster = 0;
+                    item.entry.file_size = 0;
+                    volume.write_entry(item.slot, &item.entry)?;
+                    item
+                }
+                None => volume.create_entry(dir, name)?,
+            };
+            volume.flush()?;
+            item
+        };
+        Ok(Box::new(FatFile::new(self.volume.clone(), &item, false)))
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>, FileError> {
+        let mut volume = self.volume.lock();
+        let dir = volume.lookup_dir(path)?;
+        Ok(volume
+            .read_dir_items(dir)?
+            .into_iter()
+            .map(|item| DirEntry::new(item.name.clone(), item.metadata()))
+            .collect())
+    }
+
+    fn flush(&self) -> Result<(), FileError> {
+        self.volume.lock().flush()
+    }
+}
+
+/// Location of a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DirLocation {
+    /// Fixed root directory of FAT12/16
+    FixedRoot,
+    Cluster(u32),
+}
+
+/// Location of a directory entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Slot {
+    sector: u32,
+    offset: usize,
+}
+
+struct DirItem {
+    name: String,
+    entry: DosDirEnt,
+    slot: Slot,
+}
+
+impl DirItem {
+    #[inline]
+    fn metadata(&self) -> Metadata {
+        if self.entry.is_dir() {
+            Metadata {
+                file_type: FileType::Directory,
+                len: 0,
+            }
+        } else {
+            Metadata {
+                file_type: FileType::File,
+                len: self.entry.file_size as u64,
+            }
+        }
+    }
+
+    #[inline]
+    fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name) || self.entry.short_name().eq_ignore_ascii_case(name)
+    }
+}
+
+struct Volume {
+    device: Box<dyn BlockDevice + Send>,
+    bpb: Bpb,
+    blocks_per_sector: u64,
+    read_only: bool,
+    /// Buffer for sectors other than the FAT
+    buf: Vec<u8>,
+    fat_buf: Vec<u8>,
+    fat_sector: Option<u32>,
+    fat_dirty: bool,
+    /// Hint for the next cluster allocation
+    next_free: u32,
+    fs_info_dirty: bool,
+}
+
+impl Volume {
+    fn read_sector(&mut self, sector: u32, buf: &mut [u8]) -> Result<(), FileError> {
+        self.device
+            .read(LBA(sector as u64 * self.blocks_per_sector), buf)
+            .map_err(Into::into)
+    }
+
+    fn write_sector(&mut self, sector: u32, buf: &[u8]) -> Result<(), FileError> {
+        if self.read_only {
+            return Err(FileError::ReadOnly);
+        }
+        self.device
+            .write(LBA(sector as u64 * self.blocks_per_sector), buf)
+            .map_err(Into::into)
+    }
+
+    /// Reads a part of the sector
+    fn read_partial(
+        &mut self,
+        sector: u32,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<(), FileError> {
+        if offset == 0 && buf.len() == self.buf.len() {
+            return self.read_sector(sector, buf);
+        }
+        let mut temp = core::mem::take(&mut self.buf);
+        let result = self.read_sector(sector, &mut temp);
+        if result.is_ok() {
+            buf.copy_from_slice(&temp[offset..offset + buf.len()]);
+        }
+        self.buf = temp;
+        result
+    }
+
+    /// Writes a part of the sector
+    fn write_partial(&mut self, sector: u32, offset: usize, buf: &[u8]) -> Result<(), FileError> {
+        if offset == 0 && buf.len() == self.buf.len() {
+            return self.write_sector(sector, buf);
+        }
+        let mut temp = core::mem::take(&mut self.buf);
+        let result = self.read_sector(sector, &mut temp).and_then(|_| {
+            temp[offset..offset + buf.len()].copy_from_slice(buf);
+            self.write_sector(sector, &temp)
+        });
+        self.buf = temp;
+        result
+    }
+
+    fn load_fat_sector(&mut self, sector: u32) -> Result<(), FileError> {
+        if self.fat_sector == Some(sector) {
+            return Ok(());
+        }
+        self.flush_fat()?;
+        self.fat_sector = None;
+        let mut temp = core::mem::take(&mut self.fat_buf);
+        let result = self.read_sector(sector, &mut temp);
+        self.fat_buf = temp;
+        result?;
+        self.fat_sector = Some(sector);
+        Ok(())
+    }
+
+    /// Writes back the cached FAT sector to all FATs
+    fn flush_fat(&mut self) -> Result<(), FileError> {
+        let Some(sector) = self.fat_sector.filter(|_| self.fat_dirty) else {
+            return Ok(());
+        };
+        let index = sector - self.bpb.first_fat_sector() - self.fat_offset();
+        let temp = core::mem::take(&mut self.fat_buf);
+        let mut result = Ok(());
+        for fat in 0..self.bpb.n_fats {
+            if self.bpb.active_fat.is_some_and(|v| v != fat) {
+                continue;
+            }
+            let sector = self.bpb.first_fat_sector() + fat * self.bpb.sectors_per_fat + index;
+            result = result.and(self.write_sector(sector, &temp));
+        }
+        self.fat_buf = temp;
+        self.fat_dirty = false;
+        result
+    }
+
+    /// Offset of the FAT to read in sectors
+    #[inline]
+    fn fat_offset(&self) -> u32 {
+        self.bpb.active_fat.unwrap_or(0) * self.bpb.sectors_per_fat
+    }
+
+    fn fat_byte(&mut self, offset: u32) -> Result<u8, FileError> {
+        let bytes_per_sector = self.bpb.bytes_per_sector;
+        self.load_fat_sector(
+            self.bpb.first_fat_sector() + self.fat_offset() + offset / bytes_per_sector,
+        )?;
+        Ok(self.fat_buf[(offset % bytes_per_sector) as usize])
+    }
+
+    fn set_fat_byte(&mut self, offset: u32, value: u8) -> Result<(), FileError> {
+        let bytes_per_sector = self.bpb.bytes_per_sector;
+        self.load_fat_sector(
+            self.bpb.first_fat_sector() + self.fat_offset() + offset / bytes_per_sector,
+        )?;
+        self.fat_buf[(offset % bytes_per_sector) as usize] = value;
+        self.fat_dirty = true;
+        Ok(())
+    }
+
+    /// Returns the FAT entry of the cluster
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32, FileError> {
+        let (offset, len) = self.fat_entry_position(cluster);
+        let mut value = 0;
+        for index in 0..len {
+            value |= (self.fat_byte(offset + index)? as u32) << (index * 8);
+        }
+        Ok(match self.bpb.fat_type {
+            FatType::Fat12 if cluster & 1 != 0 => value >> 4,
+            FatType::Fat12 => value & 0x0fff,
+            FatType::Fat16 => value,
+            FatType::Fat32 => value & 0x0fff_ffff,
+        })
+    }
+
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), FileError> {
+        let (offset, len) = self.fat_entry_position(cluster);
+        let mut raw = 0;
+        for index in 0..len {
+            raw |= (self.fat_byte(offset + index)? as u32) << (index * 8);
+        }
+        let raw = match self.bpb.fat_type {
+            FatType::Fat12 if cluster & 1 != 0 => (raw & 0x000f) | (value << 4),
+            FatType::Fat12 => (raw & 0xf000) | (value & 0x0fff),
+            FatType::Fat16 => value,
+            FatType::Fat32 => (raw & 0xf000_0000) | (value & 0x0fff_ffff),
+        };
+        for index in 0..len {
+            self.set_fat_byte(offset + index, (raw >> (index * 8)) as u8)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the offset and the size of the FAT entry in bytes
+    #[inline]
+    fn fat_entry_position(&self, cluster: u32) -> (u32, u32) {
+        match self.bpb.fat_type {
+            FatType::Fat12 => (cluster + cluster / 2, 2),
+            FatType::Fat16 => (cluster * 2, 2),
+            FatType::Fat32 => (cluster * 4, 4),
+        }
+    }
+
+    /// Returns the next cluster in the chain, `None` at the end of the chain
+    fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, FileError> {
+        let next = self.fat_entry(cluster)?;
+        if next >= self.bpb.fat_type.min_end_of_chain() {
+            Ok(None)
+        } else if self.bpb.is_valid_cluster(next) {
+            Ok(Some(next))
+        } else {
+            Err(FileError::InvalidData)
+        }
+    }
+
+    /// Allocates a cluster and links it after `prev`
+    fn allocate_cluster(&mut self, prev: Option<u32>) -> Result<u32, FileError> {
+        let count = self.bpb.cluster_count;
+        let start = if self.bpb.is_valid_cluster(self.next_free) {
+            self.next_free
+        } else {
+            2
+        };
+        for index in 0..count {
+            let cluster = 2 + (start - 2 + index) % count;
+            if self.fat_entry(cluster)? == 0 {
+                self.set_fat_entry(cluster, self.bpb.fat_type.end_of_chain())?;
+                if let Some(prev) = prev {
+                    self.set_fat_entry(prev, cluster)?;
+                }
+                self.next_free = cluster + 1;
+                self.fs_info_dirty = true;
+                return Ok(cluster);
+            }
+        }
+        Err(FileError::NoSpace)
+    }
+
+    /// Releases the cluster chain
+    fn free_chain(&mut self, first_cluster: u32) -> Result<(), FileError> {
+        let mut cluster = first_cluster;
+        // Limits the length to avoid an infinite loop on a broken chain
+        for _ in 0..self.bpb.cluster_count {
+            if !self.bpb.is_valid_cluster(cluster) {
+                break;
+            }
+            let next = self.fat_entry(cluster)?;
+            self.set_fat_entry(cluster, 0)?;
+            self.fs_info_dirty = true;
+            if next >= self.bpb.fat_type.min_end_of_chain() {
+                break;
+            }
+            cluster = next;
+        }
+        Ok(())
+    }
+
+    /// Fills the cluster with zero
+    fn clear_cluster(&mut self, cluster: u32) -> Result<(), FileError> {
+        let first_sector = self.bpb.cluster_to_sector(cluster);
+        let zero = vec![0; self.bpb.bytes_per_sector as usize];
+        for index in 0..self.bpb.sectors_per_cluster {
+            self.write_sector(first_sector + index, &zero)?;
+        }
+        Ok(())
+    }
+
+    /// Writes back the FAT, the FSInfo and the blocks cached by the device
+    fn flush(&mut self) -> Result<(), FileError> {
+        self.flush_fat()?;
+        self.flush_fs_info()?;
+        self.device.flush()?;
+        Ok(())
+    }
+
+    /// Invalidates the free cluster count in FSInfo
+    fn flush_fs_info(&mut self) -> Result<(), FileError> {
+        let fs_info_sector = self.bpb.fs_info_sector;
+        if !self.fs_info_dirty || self.bpb.fat_type != FatType::Fat32 {
+            return Ok(());
+        }
+        self.fs_info_dirty = false;
+        if fs_info_sector == 0 || fs_info_sector >= self.bpb.reserved_sectors {
+            return Ok(());
+        }
+        let mut temp = core::mem::take(&mut self.buf);
+        let result = self.read_sector(fs_info_sector, &mut temp).and_then(|_| {
+            if temp[0..4] != *b"RRaA" || temp[484..488] != *b"rrAa" {
+                return Ok(());
+            }
+            temp[488..492].copy_from_slice(&u32::MAX.to_le_bytes());
+            temp[492..496].copy_from_slice(&self.next_free.to_le_bytes());
+            self.write_sector(fs_info_sector, &temp)
+        });
+        self.buf = temp;
+        result
+    }
+
+    /// Returns all sectors of the directory
+    fn dir_sectors(&mut self, dir: DirLocation) -> Result<Vec<u32>, FileError> {
+        match dir {
+            DirLocation::FixedRoot => Ok((0..self.bpb.root_sectors)
+                .map(|v| self.bpb.first_root_sector + v)
+                .collect()),
+            DirLocation::Cluster(first_cluster) => {
+                let mut sectors = Vec::new();
+                let mut cluster = Some(first_cluster);
+                while let Some(current) = cluster {
+                    if !self.bpb.is_valid_cluster(current)
+                        || sectors.len() as u32 >= self.bpb.cluster_count
+                    {
+                        return Err(FileError::InvalidData);
+                    }
+                    let first_sector = self.bpb.cluster_to_sector(current);
+                    sectors.extend((0..self.bpb.sectors_per_cluster).map(|v| first_sector + v));
+                    cluster = self.next_cluster(current)?;
+                }
+                Ok(sectors)
+            }
+        }
+    }
+
+    fn read_dir_items(&mut self, dir: DirLocation) -> Result<Vec<DirItem>, FileError> {
+        let mut items = Vec::new();
+        let mut lfn = LfnBuilder::default();
+        let mut buf = vec![0; self.bpb.bytes_per_sector as usize];
+        for sector in self.dir_sectors(dir)? {
+            self.read_sector(sector, &mut buf)?;
+            for (index, bytes) in buf.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+                match bytes[0] {
+                    0 => return Ok(items),
+                    0xe5 => {
+                        lfn.reset();
+                        continue;
+                    }
+                    _ => {}
+                }
+                if bytes[11] == DosAttributes::LFN_ENTRY.0 {
+                    lfn.push(bytes);
+                    continue;
+                }
+                let entry = DosDirEnt::from_bytes(bytes);
+                let long_name = lfn.finish(&entry);
+                if entry.attr.contains(DosAttributes::LABEL) || entry.is_dot_entry() {
+                    continue;
+                }
+                items.push(DirItem {
+                    name: long_name.unwrap_or_else(|| entry.short_name()),
+                    entry,
+                    slot: Slot {
+                        sector,
+                        offset: index * DIR_ENTRY_SIZE,
+                    },
+                });
+            }
+        }
+        Ok(items)
+    }
+
+    fn root_dir(&self) -> DirLocation {
+        match self.bpb.fat_type {
+            FatType::Fat32 => DirLocation::Cluster(self.bpb.root_cluster),
+            FatType::Fat12 | FatType::Fat16 => DirLocation::FixedRoot,
+        }
+    }
+
+    fn find(&mut self, dir: DirLocation, name: &str) -> Result<Option<DirItem>, FileError> {
+        Ok(self
+            .read_dir_items(dir)?
+            .into_iter()
+            .find(|item| item.matches(name)))
+    }
+
+    /// Finds the entry of the path, `None` for the root directory
+    fn lookup(&mut self, path: &str) -> Result<Option<DirItem>, FileError> {
+        let mut dir = self.root_dir();
+        let mut result = None;
+        for name in vfs::components(path) {
+            if let Some(item) = result.take() {
+                dir = Self::subdir(&item)?;
+            }
+            result = Some(self.find(dir, name)?.ok_or(FileError::NotFound)?);
+        }
+        Ok(result)
+    }
+
+    fn lookup_dir(&mut self, path: &str) -> Result<DirLocation, FileError> {
+        match self.lookup(path)? {
+            Some(item) => Self::subdir(&item),
+            None => Ok(self.root_dir()),
+        }
+    }
+
+    fn subdir(item: &DirItem) -> Result<DirLocation, FileError> {
+        if !item.entry.is_dir() {
+            return Err(FileError::NotADirectory);
+        }
+        match item.entry.first_cluster {
+            // Some implementations use cluster 0 for the root directory
+            0 => Err(FileError::InvalidData),
+            cluster => Ok(DirLocation::Cluster(cluster)),
+        }
+    }
+
+    fn write_entry(&mut self, slot: Slot, entry: &DosDirEnt) -> Result<(), FileError> {
+        let mut bytes = [0; DIR_ENTRY_SIZE];
+        self.read_partial(slot.sector, slot.offset, &mut bytes)?;
+        entry.write_to(&mut bytes);
+        self.write_partial(slot.sector, slot.offset, &bytes)
+    }
+
+    /// Finds the consecutive free entries, the directory is extended if needed
+    fn allocate_slots(&mut self, dir: DirLocation, count: usize) -> Result<Vec<Slot>, FileError> {
+        loop {
+            let sectors = self.dir_sectors(dir)?;
+            let mut buf = vec![0; self.bpb.bytes_per_sector as usize];
+            let mut slots = Vec::with_capacity(count);
+            for &sector in &sectors {
+                self.read_sector(sector, &mut buf)?;
+                for (index, bytes) in buf.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+                    if matches!(bytes[0], 0 | 0xe5) {
+                        slots.push(Slot {
+                            sector,
+                            offset: index * DIR_ENTRY_SIZE,
+                        });
+                        if slots.len() == count {
+                            return Ok(slots);
+                        }
+                    } else {
+                        slots.clear();
+                    }
+                }
+            }
+
+            let DirLocation::Cluster(first_cluster) = dir else {
+                return Err(FileError::NoSpace);
+            };
+            let mut last_cluster = first_cluster;
+            while let Some(next) = self.next_cluster(last_cluster)? {
+                last_cluster = next;
+            }
+            let cluster = self.allocate_cluster(Some(last_cluster))?;
+            self.clear_cluster(cluster)?;
+        }
+    }
+
+    /// Creates an empty file in the directory
+    fn create_entry(&mut self, dir: DirLocation, name: &str) -> Result<DirItem, FileError> {
+        let (entry, long_name) = match DosDirEnt::exact_short_name(name) {
+            Some((short_name, nt_reserved)) => (
+                DosDirEnt::new(short_name, nt_reserved, DosAttributes::ARCHIVE),
+                None,
+            ),
+            None => {
+                let items = self.read_dir_items(dir)?;
+                let short_name = (1..1_000_000)
+                    .map(|n| DosDirEnt::generated_short_name(name, n))
+                    .find(|v| items.iter().all(|item| item.entry.name != *v))
+                    .ok_or(FileError::NoSpace)?;
+                (
+                    DosDirEnt::new(short_name, 0, DosAttributes::ARCHIVE),
+                    Some(name),
+                )
+            }
+        };
+
+        let lfn_count = long_name.map_or(0, lfn_entry_count);
+        let slots = self.allocate_slots(dir, lfn_count + 1)?;
+        if let Some(long_name) = long_name {
+            let checksum = entry.checksum();
+            for (index, slot) in slots[..lfn_count].iter().enumerate() {
+                let mut bytes = [0; DIR_ENTRY_SIZE];
+                write_lfn_entry(&mut bytes, long_name, lfn_count - index, checksum);
+                self.write_partial(slot.sector, slot.offset, &bytes)?;
+            }
+        }
+        let slot = slots[lfn_count];
+        let mut bytes = [0; DIR_ENTRY_SIZE];
+        entry.write_to(&mut bytes);
+        self.write_partial(slot.sector, slot.offset, &bytes)?;
+
+        Ok(DirItem {
+            name: name.to_owned(),
+            entry,
+            slot,
+        })
+    }
+}
+
+/// File opened from the FAT file system
+pub struct FatFile {
+    volume: Arc<SpinMutex<Volume>>,
+    slot: Slot,
+    entry: DosDirEnt,
+    position: u32,
+    /// Index and number of the last accessed cluster
+    current: Option<(u32, u32)>,
+    is_dirty: bool,
+    read_only: bool,
+}
+
+impl FatFile {
+    fn new(volume: Arc<SpinMutex<Volume>>, item: &DirItem, read_only: bool) -> Self {
+        Self {
+            volume,
+            slot: item.slot,
+            entry: item.entry,
+            position: 0,
+            current: None,
+            is_dirty: false,
+            read_only,
+        }
+    }
+
+    /// Returns the cluster at the index in the chain, allocates it if `allocate` is true
+    fn cluster_at(
+        &mut self,
+        volume: &mut Volume,
+        index: u32,
+        allocate: bool,
+    ) -> Result<Option<u32>, FileError> {
+        let (mut current_index, mut cluster) = match self.current {
+            Some((current_index, cluster)) if current_index <= index => (current_index, cluster),
+            _ => {
+                if self.entry.first_cluster == 0 {
+                    if !allocate {
+                        return Ok(None);
+                    }
+                    self.entry.first_cluster = volume.allocate_cluster(None)?;
+                    self.is_dirty = true;
+                }
+                (0, self.entry.first_cluster)
+            }
+        };
+        while current_index < index {
+            cluster = match volume.next_cluster(cluster)? {
+                Some(next) => next,
+                None if allocate => volume.allocate_cluster(Some(cluster))?,
+                None => return Ok(None),
+            };
+            current_index += 1;
+        }
+        self.current = Some((index, cluster));
+        Ok(Some(cluster))
+    }
+
+    /// Transfers the data at the current position within a cluster
+    fn transfer_chunk(
+        &mut self,
+        volume: &mut Volume,
+        len: usize,
+        allocate: bool,
+    ) -> Result<Option<(u32, usize, usize)>, FileError> {
+        let bytes_per_cluster = volume.bpb.bytes_per_cluster();
+        let bytes_per_sector = volume.bpb.bytes_per_sector;
+        let Some(cluster) = self.cluster_at(volume, self.position / bytes_per_cluster, allocate)?
+        else {
+            return Ok(None);
+        };
+        let offset = self.position % bytes_per_cluster;
+        let sector = volume.bpb.cluster_to_sector(cluster) + offset / bytes_per_sector;
+        let offset = (offset % bytes_per_sector) as usize;
+        let len = len.min(bytes_per_sector as usize - offset);
+        Ok(Some((sector, offset, len)))
+    }
+
+    fn write_at_position(&mut self, buf: &[u8]) -> Result<usize, FileError> {
+        let volume = self.volume.clone();
+        let mut volume = volume.lock();
+        let max_len = (u32::MAX - self.position) as usize;
+        let buf = &buf[..buf.len().min(max_len)];
+        let mut written = 0;
+        while written < buf.len() {
+            let Some((sector, offset, len)) =
+                self.transfer_chunk(&mut volume, buf.len() - written, true)?
+            else {
+                break;
+            };
+            let chunk = &buf[written..written + len];
+            if offset == 0 && len < volume.buf.len() && self.position >= self.entry.file_size {
+                // Data beyond the end of the file does not need to be preserved
+                let mut temp = vec![0; volume.buf.len()];
+                temp[..len].copy_from_slice(chunk);
+                volume.write_sector(sector, &temp)?;
+            } else {
+                volume.write_partial(sector, offset, chunk)?;
+            }
+            written += len;
+            self.position += len as u32;
+            if self.position > self.entry.file_size {
+                self.entry.file_size = self.position;
+            }
+            self.is_dirty = true;
+        }
+        Ok(written)
+    }
+}
+
+impl File for FatFile {
+    #[inline]
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            file_type: FileType::File,
+            len: self.entry.file_size as u64,
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FileError> {
+        let volume = self.volume.clone();
+        let mut volume = volume.lock();
+        let remain = self.entry.file_size.saturating_sub(self.position) as usize;
+        let buf_len = buf.len().min(remain);
+        let mut read = 0;
+        while read < buf_len {
+            let Some((sector, offset, len)) =
+                self.transfer_chunk(&mut volume, buf_len - read, false)?
+            else {
+                return Err(FileError::InvalidData);
+            };
+            volume.read_partial(sector, offset, &mut buf[read..read + len])?;
+            read += len;
+            self.position += len as u32;
+        }
+        Ok(read)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FileError> {
+        // Rejected before any cluster is allocated
+        if self.read_only {
+            return Err(FileError::ReadOnly);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // Fills the gap after the end of the file with zero
+        while self.position > self.entry.file_size {
+            let position = self.position;
+            let gap = (position - self.entry.file_size).min(512) as usize;
+            self.position = self.entry.file_size;
+            let result = self.write_at_position(&[0; 512][..gap]);
+            self.position = position;
+            result?;
+        }
+        self.write_at_position(buf)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, FileError> {
+        let position = pos
+            .position(self.position as u64, self.entry.file_size as u64)
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or(FileError::InvalidParameter)?;
+        self.position = position;
+        Ok(position as u64)
+    }
+
+    fn flush(&mut self) -> Result<(), FileError> {
+        let mut volume = self.volume.lock();
+        if self.is_dirty {
+            volume.write_entry(self.slot, &self.entry)?;
+            self.is_dirty = false;
+        }
+        volume.flush()
+    }
+}
+
+impl Drop for FatFile {
+    fn drop(&mut self) {
+        if self.is_dirty {
+            let _ = self.flush();
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! BIOS Parameter Block
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// Value written at the end of a cluster chain
+    #[inline]
+    pub const fn end_of_chain(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 0x0fff,
+            FatType::Fat16 => 0xffff,
+            FatType::Fat32 => 0x0fff_ffff,
+        }
+    }
+
+    /// Values greater than or equal to this are the end of a cluster chain
+    #[inline]
+    pub const fn min_end_of_chain(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 0x0ff8,
+            FatType::Fat16 => 0xfff8,
+            FatType::Fat32 => 0x0fff_fff8,
+        }
+    }
+}
+
+impl core::fmt::Display for FatType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FatType::Fat12 => f.write_str("FAT12"),
+            FatType::Fat16 => f.write_str("FAT16"),
+            FatType::Fat32 => f.write_str("FAT32"),
+        }
+    }
+}
+
+/// Volume layout parsed from the boot sector
+#[derive(Debug, Clone)]
+pub struct Bpb {
+    pub bytes_per_sector: u32,
+    pub sectors_per_cluster: u32,
+    pub reserved_sectors: u32,
+    pub n_fats: u32,
+    pub root_entries: u32,
+    pub total_sectors: u32,
+    pub media_descriptor: u8,
+    pub sectors_per_fat: u32,
+    /// The only FAT in use if mirroring is disabled (FAT32)
+    pub active_fat: Option<u32>,
+    /// First cluster of the root directory (FAT32)
+    pub root_cluster: u32,
+    /// Sector of the FSInfo structure (FAT32)
+    pub fs_info_sector: u32,
+    pub volume_label: [u8; 11],
+    pub fat_type: FatType,
+    pub first_root_sector: u32,
+    pub root_sectors: u32,
+    pub first_data_sector: u32,
+    pub cluster_count: u32,
+}
+
+impl Bpb {
+    /// Minimum size of the boot sector to parse
+    pub const MIN_SIZE: usize = 512;
+
+    const EXTENDED_BOOT_SIGN: u8 = 0x29;
+
+    pub fn parse(sector: &[u8]) -> Result<Self, FileError> {
+        let sector = sector.get(..Self::MIN_SIZE).ok_or(FileError::InvalidData)?;
+        let u8_at = |offset: usize| sector[offset];
+        let u16_at = |offset: usize| u16::from_le_bytes([sector[offset], sector[offset + 1]]);
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                sector[offset],
+                sector[offset + 1],
+                sector[offset + 2],
+                sector[offset + 3],
+            ])
+        };
+
+        let bytes_per_sector = u16_at(0x0b) as u32;
+        let sectors_per_cluster = u8_at(0x0d) as u32;
+        let reserved_sectors = u16_at(0x0e) as u32;
+        let n_fats = u8_at(0x10) as u32;
+        let root_entries = u16_at(0x11) as u32;
+        let media_descriptor = u8_at(0x15);
+        let total_sectors = match u16_at(0x13) {
+            0 => u32_at(0x20),
+            v => v as u32,
+        };
+        let sectors_per_fat16 = u16_at(0x16) as u32;
+
+        if !bytes_per_sector.is_power_of_two()
+            || !(128..=4096).contains(&bytes_per_sector)
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || n_fats == 0
+            || total_sectors == 0
+            || media_descriptor < 0xf0
+        {
+            return Err(FileError::InvalidData);
+        }
+
+        let is_fat32 = sectors_per_fat16 == 0;
+        let (sectors_per_fat, active_fat, root_cluster, fs_info_sector, label_offset) = if is_fat32
+        {
+            let ext_flags = u16_at(0x28);
+            let active_fat = (ext_flags & 0x80 != 0).then_some((ext_flags & 0x0f) as u32);
+            (
+                u32_at(0x24),
+                active_fat,
+                u32_at(0x2c),
+                u16_at(0x30) as u32,
+                0x42,
+            )
+        } else {
+            (sectors_per_fat16, None, 0, 0, 0x26)
+        };
+        let volume_label = if u8_at(label_offset) == Self::EXTENDED_BOOT_SIGN {
+            sector[label_offset + 5..label_offset + 16]
+                .try_into()
+                .unwrap_or([0x20; 11])
+        } else {
+            [0x20; 11]
+        };
+
+        let first_root_sector = reserved_sectors + n_fats * sectors_per_fat;
+        let root_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+        let first_data_sector = first_root_sector + root_sectors;
+        let cluster_count = total_sectors
+            .checked_sub(first_data_sector)
+            .ok_or(FileError::InvalidData)?
+            / sectors_per_cluster;
+
+        let fat_type = if cluster_count < 4085 {
+            FatType::Fat12
+        } else if cluster_count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+        if is_fat32 != (fat_type == FatType::Fat32)
+            || (is_fat32 && (root_entries != 0 || root_cluster < 2))
+            || active_fat.is_some_and(|v| v >= n_fats)
+        {
+            return Err(FileError::InvalidData);
+        }
+
+        // The FAT must be able to hold all clusters
+        let fat_bits = match fat_type {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32,
+        };
+        if (cluster_count as u64 + 2) * fat_bits
+            > sectors_per_fat as u64 * bytes_per_sector as u64 * 8
+        {
+            return Err(FileError::InvalidData);
+        }
+
+        Ok(Self {
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors,
+            n_fats,
+            root_entries,
+            total_sectors,
+            media_descriptor,
+            sectors_per_fat,
+            active_fat,
+            root_cluster,
+            fs_info_sector,
+            volume_label,
+            fat_type,
+            first_root_sector,
+            root_sectors,
+            first_data_sector,
+            cluster_count,
+        })
+    }
+
+    #[inline]
+    pub const fn bytes_per_cluster(&self) -> u32 {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    #[inline]
+    pub const fn first_fat_sector(&self) -> u32 {
+        self.reserved_sectors
+    }
+
+    /// Returns the first sector of the cluster
+    #[inline]
+    pub const fn cluster_to_sector(&self, cluster: u32) -> u32 {
+        self.first_data_sector + (cluster - 2) * self.sectors_per_cluster
+    }
+
+    /// Returns whether the cluster number is in the data area
+    #[inline]
+    pub const fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.cluster_count + 2
+    }
+}
@@ -0,0 +1,303 @@
+//! Directory Entries
+
+use super::*;
+
+/// Size of a directory entry
+pub const DIR_ENTRY_SIZE: usize = 32;
+
+/// Number of UTF-16 code units in a long file name entry
+const LFN_CHARS: usize = 13;
+
+/// Offsets of the UTF-16 code units in a long file name entry
+const LFN_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// Maximum length of a long file name in UTF-16 code units
+const MAX_LFN_LEN: usize = 255;
+
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DosAttributes(pub u8);
+
+impl DosAttributes {
+    pub const READONLY: Self = Self(0b0000_0001);
+    pub const LABEL: Self = Self(0b0000_1000);
+    pub const SUBDIR: Self = Self(0b0001_0000);
+    pub const ARCHIVE: Self = Self(0b0010_0000);
+
+    pub const LFN_ENTRY: Self = Self(0b0000_1111);
+
+    #[inline]
+    pub const fn contains(&self, other: Self) -> bool {
+        (self.0 & other.0) == other.0
+    }
+}
+
+/// 8.3 directory entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DosDirEnt {
+    pub name: [u8; 11],
+    pub attr: DosAttributes,
+    /// Lower case flags of the name (bit 3) and the extension (bit 4)
+    pub nt_reserved: u8,
+    pub first_cluster: u32,
+    pub file_size: u32,
+}
+
+impl DosDirEnt {
+    const LOWER_NAME: u8 = 0x08;
+    const LOWER_EXT: u8 = 0x10;
+
+    /// Same date and time as the DOS epoch, 1980-01-01 00:00:00
+    const DEFAULT_DATE: u16 = 0x0021;
+
+    pub const fn new(name: [u8; 11], nt_reserved: u8, attr: DosAttributes) -> Self {
+        Self {
+            name,
+            attr,
+            nt_reserved,
+            first_cluster: 0,
+            file_size: 0,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let mut name = [0; 11];
+        name.copy_from_slice(&bytes[..11]);
+        Self {
+            name,
+            attr: DosAttributes(bytes[11]),
+            nt_reserved: bytes[12],
+            first_cluster: ((u16_at(20) as u32) << 16) | u16_at(26) as u32,
+            file_size: u32::from_le_bytes([bytes[28], bytes[29], bytes[30], bytes[31]]),
+        }
+    }
+
+    /// Writes the entry, the timestamps are kept if `bytes` already has them
+    pub fn write_to(&self, bytes: &mut [u8]) {
+        bytes[..11].copy_from_slice(&self.name);
+        bytes[11] = self.attr.0;
+        bytes[12] = self.nt_reserved;
+        for offset in [16, 18, 24] {
+            if bytes[offset..offset + 2] == [0, 0] {
+                bytes[offset..offset + 2].copy_from_slice(&Self::DEFAULT_DATE.to_le_bytes());
+            }
+        }
+        bytes[20..22].copy_from_slice(&((self.first_cluster >> 16) as u16).to_le_bytes());
+        bytes[26..28].copy_from_slice(&(self.first_cluster as u16).to_le_bytes());
+        bytes[28..32].copy_from_slice(&self.file_size.to_le_bytes());
+    }
+
+    #[inline]
+    pub const fn is_dir(&self) -> bool {
+        self.attr.contains(DosAttributes::SUBDIR)
+    }
+
+    #[inline]
+    pub fn is_dot_entry(&self) -> bool {
+        self.name == *b".          " || self.name == *b"..         "
+    }
+
+    /// Returns the 8.3 name such as `README.TXT`
+    pub fn short_name(&self) -> String {
+        let mut result = String::new();
+        let mut push_part = |part: &[u8], is_lower: bool| {
+            let len = part.iter().rposition(|v| *v != 0x20).map_or(0, |v| v + 1);
+            for (index, &ch) in part[..len].iter().enumerate() {
+                let ch = if index == 0 && ch == 0x05 { 0xe5 } else { ch };
+                let ch = if is_lower {
+                    ch.to_ascii_lowercase()
+                } else {
+                    ch
+                };
+                // Non ASCII characters depend on the OEM code page
+                result.push(ch as char);
+            }
+        };
+        push_part(&self.name[..8], self.nt_reserved & Self::LOWER_NAME != 0);
+        if self.name[8..] != [0x20; 3] {
+            push_part(b".", false);
+            push_part(&self.name[8..], self.nt_reserved & Self::LOWER_EXT != 0);
+        }
+        result
+    }
+
+    /// Checksum of the 8.3 name stored in the long file name entries
+    pub fn checksum(&self) -> u8 {
+        self.name
+            .iter()
+            .fold(0u8, |acc, &v| acc.rotate_right(1).wrapping_add(v))
+    }
+
+    fn validate_shortname_char(c: char) -> Option<u8> {
+        match c {
+            '!' | '#'..=')' | '-' | '0'..='9' | 'A'..='Z' | '^' | '_' | '{' | '}' | '~' => {
+                Some(c as u8)
+            }
+            'a'..='z' => Some(c.to_ascii_uppercase() as u8),
+            _ => None,
+        }
+    }
+
+    /// Converts the name to the 8.3 name and its case flags if it fits without the long file name
+    pub fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+        let (base, ext) = match name.rsplit_once('.') {
+            Some((base, ext)) => (base, Some(ext)),
+            None => (name, None),
+        };
+        let mut result = [0x20; 11];
+        let mut nt_reserved = 0;
+        for (part, range, lower_flag) in [
+            (base, 0..8, Self::LOWER_NAME),
+            (ext.unwrap_or_default(), 8..11, Self::LOWER_EXT),
+        ] {
+            if part.len() > range.len() {
+                return None;
+            }
+            let has_upper = part.chars().any(|v| v.is_ascii_uppercase());
+            let has_lower = part.chars().any(|v| v.is_ascii_lowercase());
+            if has_upper && has_lower {
+                return None;
+            }
+            if has_lower {
+                nt_reserved |= lower_flag;
+            }
+            for (index, ch) in range.zip(part.chars()) {
+                result[index] = Self::validate_shortname_char(ch)?;
+            }
+        }
+        if base.is_empty() || ext.is_some_and(|v| v.is_empty()) {
+            return None;
+        }
+        Some((result, nt_reserved))
+    }
+
+    /// Makes the basis of the generated 8.3 name with the numeric tail `~N`
+    pub fn generated_short_name(name: &str, n: usize) -> [u8; 11] {
+        let (base, ext) = match name.trim_start_matches('.').rsplit_once('.') {
+            Some((base, ext)) => (base, ext),
+            None => (name.trim_start_matches('.'), ""),
+        };
+        let convert = |part: &str| {
+            part.chars()
+                .filter(|v| *v != ' ' && *v != '.')
+                .map(|v| Self::validate_shortname_char(v).unwrap_or(b'_'))
+                .collect::<Vec<_>>()
+        };
+        let base = convert(base);
+        let ext = convert(ext);
+
+        let mut tail = [0u8; 8];
+        let mut tail_len = 0;
+        let mut n = n;
+        while n > 0 && tail_len < 7 {
+            tail_len += 1;
+            tail[8 - tail_len] = b'0' + (n % 10) as u8;
+            n /= 10;
+        }
+        tail_len += 1;
+        tail[8 - tail_len] = b'~';
+
+        let mut result = [0x20; 11];
+        let base_len = base.len().min(8 - tail_len);
+        result[..base_len].copy_from_slice(&base[..base_len]);
+        result[base_len..base_len + tail_len].copy_from_slice(&tail[8 - tail_len..]);
+        for (index, ch) in (8..11).zip(ext) {
+            result[index] = ch;
+        }
+        result
+    }
+}
+
+/// Validates the name of a new file
+pub fn validate_name(name: &str) -> Result<(), FileError> {
+    if name.is_empty()
+        || name.ends_with('.')
+        || name.ends_with(' ')
+        || name.encode_utf16().count() > MAX_LFN_LEN
+        || name
+            .chars()
+            .any(|v| v < ' ' || matches!(v, '"' | '*' | '/' | ':' | '<' | '>' | '?' | '\\' | '|'))
+    {
+        return Err(FileError::InvalidPath);
+    }
+    Ok(())
+}
+
+/// Returns the number of entries needed for the long file name
+#[inline]
+pub fn lfn_entry_count(name: &str) -> usize {
+    name.encode_utf16().count().div_ceil(LFN_CHARS)
+}
+
+/// Writes the long file name entry of the sequence number (1-based)
+pub fn write_lfn_entry(bytes: &mut [u8], name: &str, seq: usize, checksum: u8) {
+    let units = name.encode_utf16().collect::<Vec<_>>();
+    let is_last = seq * LFN_CHARS >= units.len();
+    bytes[..DIR_ENTRY_SIZE].fill(0);
+    bytes[0] = seq as u8 | if is_last { 0x40 } else { 0 };
+    bytes[11] = DosAttributes::LFN_ENTRY.0;
+    bytes[13] = checksum;
+    for (index, offset) in LFN_OFFSETS.into_iter().enumerate() {
+        let position = (seq - 1) * LFN_CHARS + index;
+        let unit = match position.cmp(&units.len()) {
+            core::cmp::Ordering::Less => units[position],
+            core::cmp::Ordering::Equal => 0,
+            core::cmp::Ordering::Greater => 0xffff,
+        };
+        bytes[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+    }
+}
+
+/// Collects the long file name entries preceding an 8.3 entry
+#[derive(Default)]
+pub struct LfnBuilder {
+    units: Vec<u16>,
+    checksum: u8,
+    next_seq: u8,
+}
+
+impl LfnBuilder {
+    #[inline]
+    pub fn reset(&mut self) {
+        self.units.clear();
+        self.next_seq = 0;
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        let seq = bytes[0] & 0x1f;
+        let checksum = bytes[13];
+        if bytes[0] & 0x40 != 0 {
+            if seq == 0 || seq as usize * LFN_CHARS > MAX_LFN_LEN + LFN_CHARS {
+                self.reset();
+                return;
+            }
+            self.units.clear();
+            self.units.resize(seq as usize * LFN_CHARS, 0xffff);
+            self.checksum = checksum;
+        } else if seq == 0 || seq != self.next_seq || checksum != self.checksum {
+            self.reset();
+            return;
+        }
+        let base = (seq as usize - 1) * LFN_CHARS;
+        for (index, offset) in LFN_OFFSETS.into_iter().enumerate() {
+            self.units[base + index] = u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        }
+        self.next_seq = seq - 1;
+    }
+
+    /// Returns the long file name if all entries are collected for the 8.3 entry
+    pub fn finish(&mut self, entry: &DosDirEnt) -> Option<String> {
+        let is_complete = self.next_seq == 0 && !self.units.is_empty();
+        let units = core::mem::take(&mut self.units);
+        self.reset();
+        if !is_complete || self.checksum != entry.checksum() {
+            return None;
+        }
+        let len = units.iter().position(|v| *v == 0).unwrap_or(units.len());
+        let name = char::decode_utf16(units[..len].iter().copied())
+            .map(|v| v.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect::<String>();
+        (!name.is_empty()).then_some(name)
+    }
+}
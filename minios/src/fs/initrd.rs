@@ -0,0 +1,249 @@
+//! Read-only file system over the initrd archive
+
+use super::*;
+use crate::*;
+use alloc::collections::btree_map;
+use alloc::vec;
+use myos_archive::{ArchiveReader, Entry};
+
+/// Initial ramdisk in the MAR archive format
+pub struct Initrd {
+    image: &'static [u8],
+    root: Node,
+}
+
+enum Node {
+    File(&'static [u8]),
+    Directory(BTreeMap<&'static str, Node>),
+}
+
+impl Node {
+    #[inline]
+    fn metadata(&self) -> Metadata {
+        match self {
+            Node::File(content) => Metadata {
+                file_type: FileType::File,
+                len: content.len() as u64,
+            },
+            Node::Directory(_) => Metadata {
+                file_type: FileType::Directory,
+                len: 0,
+            },
+        }
+    }
+}
+
+impl Initrd {
+    /// Parses the archive and builds the directory tree
+    pub fn from_slice(image: &'static [u8]) -> Result<Self, FileError> {
+        let mut root = BTreeMap::new();
+        let mut namespace = Vec::new();
+        let mut reader = ArchiveReader::from_slice(image).map_err(|_| FileError::InvalidData)?;
+        loop {
+            // A truncated archive or an unknown entry is reported as an error
+            match reader.read_entry().map_err(|_| FileError::InvalidData)? {
+                Entry::End => break,
+                Entry::Namespace(path, _) => {
+                    namespace = path.split('/').filter(|v| !v.is_empty()).collect();
+                    Self::make_dir(&mut root, &namespace)?;
+                }
+                Entry::File(name, _, content) => {
+                    Self::make_dir(&mut root, &namespace)?.insert(name, Node::File(content));
+                }
+                _ => {}
+            }
+        }
+        Ok(Self {
+            image,
+            root: Node::Directory(root),
+        })
+    }
+
+    /// # Safety
+    ///
+    /// The range must be valid and must not be changed while the system is running.
+    #[inline]
+    pub unsafe fn from_static(base: *const u8, len: usize) -> Result<Self, FileError> {
+        let image = unsafe { core::slice::from_raw_parts(base, len) };
+        Self::from_slice(image)
+    }
+
+    fn make_dir<'a>(
+        root: &'a mut BTreeMap<&'static str, Node>,
+        path: &[&'static str],
+    ) -> Result<&'a mut BTreeMap<&'static str, Node>, FileError> {
+        let mut dir = root;
+        for name in path {
+            dir = match dir
+                .entry(*name)
+                .or_insert_with(|| Node::Directory(BTreeMap::new()))
+            {
+                Node::Directory(children) => children,
+                Node::File(_) => return Err(FileError::InvalidData),
+            };
+        }
+        Ok(dir)
+    }
+
+    /// Returns the whole archive image
+    #[inline]
+    pub const fn as_bytes(&self) -> &'static [u8] {
+        self.image
+    }
+
+    /// Finds the node, `.` and `..` are resolved and paths are always absolute
+    fn lookup(&self, path: &str) -> Result<&Node, FileError> {
+        let mut stack = vec![&self.root];
+        for name in path.split('/') {
+            match name {
+                "" | "." => {}
+                ".." => {
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                }
+                _ => {
+                    let Node::Directory(children) = stack[stack.len() - 1] else {
+                        return Err(FileError::NotADirectory);
+                    };
+                    stack.push(children.get(name).ok_or(FileError::NotFound)?);
+                }
+            }
+        }
+        Ok(stack[stack.len() - 1])
+    }
+
+    pub fn metadata(&self, path: &str) -> Result<Metadata, FileError> {
+        self.lookup(path).map(|v| v.metadata())
+    }
+
+    pub fn open(&self, path: &str) -> Result<InitrdFile, FileError> {
+        match self.lookup(path)? {
+            Node::File(content) => Ok(InitrdFile {
+                content,
+                position: 0,
+            }),
+            Node::Directory(_) => Err(FileError::IsADirectory),
+        }
+    }
+
+    pub fn read_dir(&self, path: &str) -> Result<ReadDir<'_>, FileError> {
+        match self.lookup(path)? {
+            Node::Directory(children) => Ok(ReadDir(children.iter())),
+            Node::File(_) => Err(FileError::NotADirectory),
+        }
+    }
+}
+
+impl FileSystem for Initrd {
+    #[inline]
+    fn fs_type(&self) -> &str {
+        "initrd"
+    }
+
+    #[inline]
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn metadata(&self, path: &str) -> Result<Metadata, FileError> {
+        Initrd::metadata(self, path)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn File>, FileError> {
+        Initrd::open(self, path).map(|v| Box::new(v) as Box<dyn File>)
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<super::DirEntry>, FileError> {
+        Initrd::read_dir(self, path).map(|iter| {
+            iter.map(|v| super::DirEntry::new(v.name().to_owned(), v.metadata()))
+                .collect()
+        })
+    }
+}
+
+/// File opened from the initrd
+pub struct InitrdFile {
+    content: &'static [u8],
+    position: usize,
+}
+
+impl InitrdFile {
+    /// Returns the whole content of the file
+    #[inline]
+    pub const fn as_bytes(&self) -> &'static [u8] {
+        self.content
+    }
+
+    #[inline]
+    pub const fn len(&self) -> u64 {
+        self.content.len() as u64
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.content.is_empty()
+    }
+
+    #[inline]
+    pub const fn position(&self) -> u64 {
+        self.position as u64
+    }
+}
+
+impl File for InitrdFile {
+    #[inline]
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            file_type: FileType::File,
+            len: self.len(),
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FileError> {
+        let remain = self.content.get(self.position..).unwrap_or_default();
+        let len = remain.len().min(buf.len());
+        buf[..len].copy_from_slice(&remain[..len]);
+        self.position += len;
+        Ok(len)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, FileError> {
+        let position = pos
+            .position(self.position(), self.len())
+            .and_then(|v| usize::try_from(v).ok())
+            .ok_or(FileError::InvalidParameter)?;
+        self.position = position;
+        Ok(position as u64)
+    }
+}
+
+/// Iterator over the entries of a directory in the initrd
+pub struct ReadDir<'a>(btree_map::Iter<'a, &'static str, Node>);
+
+impl<'a> Iterator for ReadDir<'a> {
+    type Item = DirEntry<'a>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(name, node)| DirEntry { name, node })
+    }
+}
+
+pub struct DirEntry<'a> {
+    name: &'static str,
+    node: &'a Node,
+}
+
+impl DirEntry<'_> {
+    #[inline]
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline]
+    pub fn metadata(&self) -> Metadata {
+        self.node.metadata()
+    }
+}
@@ -0,0 +1,186 @@
+//! File Systems
+
+pub mod fat;
+pub mod initrd;
+pub mod vfs;
+
+use crate::io::media::partition::{ChsGeometry, Partition, SharedBlockDevice};
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileError {
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    AlreadyExists,
+    InvalidPath,
+    InvalidData,
+    InvalidParameter,
+    ReadOnly,
+    NoSpace,
+    NotSupported,
+    DeviceError(BlockIoError),
+}
+
+impl From<BlockIoError> for FileError {
+    #[inline]
+    fn from(value: BlockIoError) -> Self {
+        Self::DeviceError(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Directory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub file_type: FileType,
+    pub len: u64,
+}
+
+impl Metadata {
+    #[inline]
+    pub const fn is_dir(&self) -> bool {
+        matches!(self.file_type, FileType::Directory)
+    }
+
+    #[inline]
+    pub const fn is_file(&self) -> bool {
+        matches!(self.file_type, FileType::File)
+    }
+
+    #[inline]
+    pub const fn len(&self) -> u64 {
+        self.len
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+impl SeekFrom {
+    /// Returns the new position in the file of the length, or `None` if it is out of range
+    pub fn position(&self, current: u64, len: u64) -> Option<u64> {
+        match *self {
+            Self::Start(offset) => Some(offset),
+            Self::End(offset) => len.checked_add_signed(offset),
+            Self::Current(offset) => current.checked_add_signed(offset),
+        }
+    }
+}
+
+/// File system that can be mounted on the [`vfs::Vfs`]
+///
+/// Paths passed to the file system are absolute within the file system,
+/// already normalized and never contain `.` or `..`.
+pub trait FileSystem {
+    /// Name of the file system type, such as `fat`
+    fn fs_type(&self) -> &str;
+
+    fn is_read_only(&self) -> bool;
+
+    fn metadata(&self, path: &str) -> Result<Metadata, FileError>;
+
+    fn open(&self, path: &str) -> Result<Box<dyn File>, FileError>;
+
+    /// Creates a new file or truncates the existing file
+    fn create(&self, path: &str) -> Result<Box<dyn File>, FileError> {
+        let _ = path;
+        Err(FileError::ReadOnly)
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>, FileError>;
+
+    /// Writes back the pending changes to the device
+    fn flush(&self) -> Result<(), FileError> {
+        Ok(())
+    }
+}
+
+/// Opened file
+pub trait File {
+    fn metadata(&self) -> Metadata;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FileError>;
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FileError> {
+        let _ = buf;
+        Err(FileError::ReadOnly)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, FileError>;
+
+    fn flush(&mut self) -> Result<(), FileError> {
+        Ok(())
+    }
+
+    /// Reads all bytes until the end of the file
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize, FileError> {
+        let start = buf.len();
+        let mut chunk = [0u8; 512];
+        loop {
+            match self.read(&mut chunk)? {
+                0 => break,
+                len => buf.extend_from_slice(&chunk[..len]),
+            }
+        }
+        Ok(buf.len() - start)
+    }
+}
+
+/// Entry of a directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    name: String,
+    metadata: Metadata,
+}
+
+impl DirEntry {
+    #[inline]
+    pub const fn new(name: String, metadata: Metadata) -> Self {
+        Self { name, metadata }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub const fn metadata(&self) -> Metadata {
+        self.metadata
+    }
+}
+
+/// Opens the file system on the device, or on the first partition that has one
+///
+/// Bootable partitions are tried first.
+pub fn open_volume(
+    device: &SharedBlockDevice,
+    geometry: Option<ChsGeometry>,
+) -> Result<Arc<dyn FileSystem>, FileError> {
+    let read_only = device.lock().media_info().is_read_only();
+    let Some(mut partitions) = Partition::from_device(device.clone(), geometry)? else {
+        let fs = fat::FatFs::new(Box::new(device.clone()), read_only)?;
+        return Ok(Arc::new(fs));
+    };
+    partitions.sort_by_key(|(info, _)| !info.is_bootable);
+    for (_, partition) in partitions {
+        if let Ok(fs) = fat::FatFs::new(Box::new(partition), read_only) {
+            return Ok(Arc::new(fs));
+        }
+    }
+    Err(FileError::NotSupported)
+}
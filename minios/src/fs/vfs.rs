@@ -0,0 +1,261 @@
+//! Virtual File System
+
+use super::*;
+use crate::*;
+
+/// Mount table and the single namespace of the mounted file systems
+pub struct Vfs {
+    /// Mount points, the longer paths come first
+    mounts: Vec<MountPoint>,
+    current_dir: String,
+}
+
+pub struct MountPoint {
+    path: String,
+    fs: Arc<dyn FileSystem>,
+}
+
+impl MountPoint {
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[inline]
+    pub fn fs(&self) -> &Arc<dyn FileSystem> {
+        &self.fs
+    }
+}
+
+impl Vfs {
+    pub const SEPARATOR: char = '/';
+
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            mounts: Vec::new(),
+            current_dir: String::new(),
+        }
+    }
+
+    /// Mounts the file system on the path
+    pub fn mount(&mut self, path: &str, fs: Arc<dyn FileSystem>) -> Result<(), FileError> {
+        let path = self.canonicalize(path)?;
+        if self.mounts.iter().any(|v| v.path == path) {
+            return Err(FileError::AlreadyExists);
+        }
+        let index = self
+            .mounts
+            .iter()
+            .position(|v| v.path.len() < path.len())
+            .unwrap_or(self.mounts.len());
+        self.mounts.insert(index, MountPoint { path, fs });
+        Ok(())
+    }
+
+    /// Unmounts the file system on the path and returns it
+    pub fn unmount(&mut self, path: &str) -> Result<Arc<dyn FileSystem>, FileError> {
+        let path = self.canonicalize(path)?;
+        let index = self
+            .mounts
+            .iter()
+            .position(|v| v.path == path)
+            .ok_or(FileError::NotFound)?;
+        let mount = self.mounts.remove(index);
+        let _ = mount.fs.flush();
+        Ok(mount.fs)
+    }
+
+    #[inline]
+    pub fn mounts(&self) -> impl Iterator<Item = &MountPoint> {
+        self.mounts.iter()
+    }
+
+    #[inline]
+    pub fn is_mounted(&self, path: &str) -> bool {
+        self.canonicalize(path)
+            .is_ok_and(|path| self.mounts.iter().any(|v| v.path == path))
+    }
+
+    #[inline]
+    pub fn current_dir(&self) -> &str {
+        if self.current_dir.is_empty() {
+            "/"
+        } else {
+            &self.current_dir
+        }
+    }
+
+    pub fn set_current_dir(&mut self, path: &str) -> Result<(), FileError> {
+        let path = self.canonicalize(path)?;
+        if !self.metadata(&path)?.is_dir() {
+            return Err(FileError::NotADirectory);
+        }
+        self.current_dir = path;
+        Ok(())
+    }
+
+    /// Returns the absolute path without `.` and `..`
+    ///
+    /// Relative paths are resolved from the current directory.
+    pub fn canonicalize(&self, path: &str) -> Result<String, FileError> {
+        if path.is_empty() || path.contains('\0') {
+            return Err(FileError::InvalidPath);
+        }
+        let mut components = Vec::new();
+        if !path.starts_with(Self::SEPARATOR) {
+            components.extend(
+                self.current_dir
+                    .split(Self::SEPARATOR)
+                    .filter(|v| !v.is_empty()),
+            );
+        }
+        for name in path.split(Self::SEPARATOR) {
+            match name {
+                "" | "." => {}
+                ".." => {
+                    components.pop();
+                }
+                _ => components.push(name),
+            }
+        }
+        let mut result = String::new();
+        for name in components {
+            result.push(Self::SEPARATOR);
+            result.push_str(name);
+        }
+        if result.is_empty() {
+            result.push(Self::SEPARATOR);
+        }
+        Ok(result)
+    }
+
+    /// Finds the file system for the path and the path within it
+    pub fn resolve(&self, path: &str) -> Result<(&Arc<dyn FileSystem>, String), FileError> {
+        let path = self.canonicalize(path)?;
+        for mount in &self.mounts {
+            if let Some(rest) = Self::strip_mount_point(&path, &mount.path) {
+                let rest = if rest.is_empty() { "/" } else { rest };
+                return Ok((&mount.fs, rest.to_owned()));
+            }
+        }
+        Err(FileError::NotFound)
+    }
+
+    fn strip_mount_point<'a>(path: &'a str, mount_point: &str) -> Option<&'a str> {
+        if mount_point == "/" {
+            return Some(path);
+        }
+        let rest = path.strip_prefix(mount_point)?;
+        (rest.is_empty() || rest.starts_with(Self::SEPARATOR)).then_some(rest)
+    }
+
+    pub fn metadata(&self, path: &str) -> Result<Metadata, FileError> {
+        let (fs, path) = self.resolve(path)?;
+        fs.metadata(&path)
+    }
+
+    pub fn open(&self, path: &str) -> Result<Box<dyn File>, FileError> {
+        let (fs, path) = self.resolve(path)?;
+        fs.open(&path)
+    }
+
+    pub fn create(&self, path: &str) -> Result<Box<dyn File>, FileError> {
+        let (fs, path) = self.resolve(path)?;
+        if fs.is_read_only() {
+            return Err(FileError::ReadOnly);
+        }
+        fs.create(&path)
+    }
+
+    /// Reads the entries of the directory, including the mount points just under it
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>, FileError> {
+        let path = self.canonicalize(path)?;
+        let mut entries = match self.resolve(&path) {
+            Ok((fs, fs_path)) => fs.read_dir(&fs_path)?,
+            Err(FileError::NotFound) => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        for mount in &self.mounts {
+            let Some(rest) = Self::strip_mount_point(&mount.path, &path) else {
+                continue;
+            };
+            let Some(name) = rest.strip_prefix(Self::SEPARATOR) else {
+                continue;
+            };
+            if name.is_empty()
+                || name.contains(Self::SEPARATOR)
+                || entries.iter().any(|v| v.name() == name)
+            {
+                continue;
+            }
+            entries.push(DirEntry::new(
+                name.to_owned(),
+                Metadata {
+                    file_type: FileType::Directory,
+                    len: 0,
+                },
+            ));
+        }
+        if entries.is_empty() && self.resolve(&path).is_err() {
+            return Err(FileError::NotFound);
+        }
+        Ok(entries)
+    }
+
+    /// Reads the whole content of the file
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, FileError> {
+        let mut file = self.open(path)?;
+        let mut buf = Vec::new();
+        if let Ok(len) = usize::try_from(file.metadata().len()) {
+            buf.reserve(len);
+        }
+        file.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Creates the file and writes the whole content
+    pub fn write(&self, path: &str, content: &[u8]) -> Result<(), FileError> {
+        let mut file = self.create(path)?;
+        let mut content = content;
+        while !content.is_empty() {
+            match file.write(content)? {
+                0 => return Err(FileError::NoSpace),
+                len => content = &content[len..],
+            }
+        }
+        file.flush()
+    }
+
+    /// Writes back the pending changes of all file systems
+    pub fn sync(&self) -> Result<(), FileError> {
+        let mut result = Ok(());
+        for mount in &self.mounts {
+            if let Err(err) = mount.fs.flush() {
+                result = Err(err);
+            }
+        }
+        result
+    }
+}
+
+impl Default for Vfs {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Splits the path into the parent directory and the file name
+pub fn split_path(path: &str) -> (&str, &str) {
+    match path.rfind(Vfs::SEPARATOR) {
+        Some(0) => ("/", &path[1..]),
+        Some(index) => (&path[..index], &path[index + 1..]),
+        None => ("", path),
+    }
+}
+
+/// Returns the components of the normalized path
+pub fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split(Vfs::SEPARATOR).filter(|v| !v.is_empty())
+}
@@ -60,6 +60,23 @@ impl FbDisplay8 {
             }
         }
     }
+
+    /// Copies the area to the destination, the source and destination may overlap.
+    ///
+    /// Nothing is drawn if either rectangle is not entirely inside the display.
+    pub fn copy_rect(&mut self, area: &Rectangle, dest: Point) {
+        let bounds = self.bounding_box();
+        let dest_area = Rectangle::new(dest, area.size);
+        if area.is_zero_sized()
+            || bounds.intersection(area) != *area
+            || bounds.intersection(&dest_area) != dest_area
+        {
+            return;
+        }
+        unsafe {
+            self.0.copy_rect_fast(area, dest);
+        }
+    }
 }
 
 impl Dimensions for FbDisplay8 {
@@ -189,6 +206,33 @@ pub trait FrameBuffer {
     ///
     /// This function does not check bounds.
     unsafe fn fill_fast(&mut self, origin: Point, length: u32, color: IndexedColor);
+
+    /// # Safety
+    ///
+    /// This function does not check bounds.
+    unsafe fn copy_rect_fast(&mut self, area: &Rectangle, dest: Point);
+}
+
+/// Copies a rectangle within a linear framebuffer, taking care of overlapping
+#[inline]
+unsafe fn copy_rect_linear<T: Copy>(fb: *mut T, stride: usize, area: &Rectangle, dest: Point) {
+    let width = area.size.width as usize;
+    let height = area.size.height as usize;
+    unsafe {
+        let src = fb.add(area.top_left.y as usize * stride + area.top_left.x as usize);
+        let dst = fb.add(dest.y as usize * stride + dest.x as usize);
+        if width == stride {
+            src.copy_to(dst, width * height);
+        } else if dest.y <= area.top_left.y {
+            for y in 0..height {
+                src.add(y * stride).copy_to(dst.add(y * stride), width);
+            }
+        } else {
+            for y in (0..height).rev() {
+                src.add(y * stride).copy_to(dst.add(y * stride), width);
+            }
+        }
+    }
 }
 
 struct Fb8 {
@@ -303,6 +347,12 @@ impl FrameBuffer for Fb8 {
                 .write_bytes(color.0, length as usize);
         }
     }
+
+    unsafe fn copy_rect_fast(&mut self, area: &Rectangle, dest: Point) {
+        unsafe {
+            copy_rect_linear(self.fb, self.stride, area, dest);
+        }
+    }
 }
 
 /// Special implementation for fixed width framebuffer
@@ -338,6 +388,12 @@ impl<const WIDTH: usize> FrameBuffer for Fb8Fixed<WIDTH> {
                 .write_bytes(color.0, length as usize);
         }
     }
+
+    unsafe fn copy_rect_fast(&mut self, area: &Rectangle, dest: Point) {
+        unsafe {
+            copy_rect_linear(self.fb, WIDTH, area, dest);
+        }
+    }
 }
 
 struct Fb32 {
@@ -424,4 +480,10 @@ impl FrameBuffer for Fb32 {
             slice.fill(color);
         }
     }
+
+    unsafe fn copy_rect_fast(&mut self, area: &Rectangle, dest: Point) {
+        unsafe {
+            copy_rect_linear(self.fb, self.stride, area, dest);
+        }
+    }
 }
@@ -2,7 +2,10 @@
 
 use super::color::IndexedColor;
 use super::display::FbDisplay8;
+use crate::io::tty::ansi::{AnsiScreen, AnsiState};
 use crate::*;
+use alloc::{collections::VecDeque, vec};
+use core::ops::Range;
 use embedded_graphics::prelude::*;
 use embedded_graphics::primitives::Rectangle;
 use simple_font::SimpleFont;
@@ -15,9 +18,31 @@ pub struct FbCon {
     font_height: usize,
     fg_color: IndexedColor,
     bg_color: IndexedColor,
+    cells: Vec<Cell>,
+    ansi: AnsiState,
+    scrollback: VecDeque<Box<[Cell]>>,
+    scrollback_limit: usize,
+    view_offset: usize,
+}
+
+/// A character cell of the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    ch: char,
+    attribute: u8,
+}
+
+impl Cell {
+    #[inline]
+    const fn blank(attribute: u8) -> Self {
+        Self { ch: ' ', attribute }
+    }
 }
 
 impl FbCon {
+    /// Default number of lines kept in the scrollback buffer
+    pub const DEFAULT_SCROLLBACK_LINES: usize = 256;
+
     pub fn new(fb: FbDisplay8, font: SimpleFont<'static>) -> Self {
         let display_width = fb.bounding_box().size.width as usize;
         let display_height = fb.bounding_box().size.height as usize;
@@ -35,6 +60,11 @@ impl FbCon {
             font_height,
             fg_color: IndexedColor::BLACK,
             bg_color: IndexedColor::BLACK,
+            cells: vec![Cell::blank(0); cols as usize * rows as usize],
+            ansi: AnsiState::new(),
+            scrollback: VecDeque::new(),
+            scrollback_limit: Self::DEFAULT_SCROLLBACK_LINES,
+            view_offset: 0,
         }
     }
 
@@ -43,6 +73,216 @@ impl FbCon {
         &mut self.fb
     }
 
+    /// Sets the number of lines kept in the scrollback buffer, zero disables it
+    pub fn set_scrollback_limit(&mut self, lines: usize) {
+        self.scrollback_limit = lines;
+        while self.scrollback.len() > lines {
+            self.scrollback.pop_front();
+        }
+        if self.view_offset > self.scrollback.len() {
+            self.set_view_offset(self.scrollback.len());
+        }
+    }
+
+    #[inline]
+    pub fn scrollback_limit(&self) -> usize {
+        self.scrollback_limit
+    }
+
+    /// Sets the scroll region to the rows `top..bottom`, or resets it to the whole screen if it is invalid
+    #[inline]
+    pub fn set_scroll_region(&mut self, top: u8, bottom: u8) {
+        self.ansi.set_scroll_region(top, bottom, self.mode.rows);
+    }
+
+    #[inline]
+    pub fn scroll_region(&self) -> Range<u8> {
+        self.ansi.scroll_region(self.mode.rows)
+    }
+
+    /// Scrolls the scroll region up by the specified number of lines
+    ///
+    /// Lines leaving the top of the screen are saved to the scrollback buffer.
+    #[inline]
+    pub fn scroll_up(&mut self, lines: u8) {
+        self.scroll_region_up(self.scroll_region(), lines);
+    }
+
+    /// Scrolls the scroll region down by the specified number of lines
+    #[inline]
+    pub fn scroll_down(&mut self, lines: u8) {
+        self.scroll_region_down(self.scroll_region(), lines);
+    }
+
+    fn scroll_rows_up(&mut self, region: Range<u8>, lines: u8) {
+        let Range {
+            start: top,
+            end: bottom,
+        } = region;
+        if top >= bottom || bottom > self.mode.rows {
+            return;
+        }
+        let lines = lines.min(bottom - top);
+        if lines == 0 {
+            return;
+        }
+        self.reset_view();
+        let cols = self.mode.columns as usize;
+
+        if top == 0 && self.scrollback_limit > 0 {
+            for row in 0..lines as usize {
+                if self.scrollback.len() >= self.scrollback_limit {
+                    self.scrollback.pop_front();
+                }
+                self.scrollback
+                    .push_back(self.cells[row * cols..(row + 1) * cols].into());
+            }
+        }
+
+        let blank = Cell::blank(self.mode.attribute);
+        self.cells.copy_within(
+            (top + lines) as usize * cols..bottom as usize * cols,
+            top as usize * cols,
+        );
+        self.cells[(bottom - lines) as usize * cols..bottom as usize * cols].fill(blank);
+
+        self.fb.copy_rect(
+            &self.rows_rect(top + lines..bottom),
+            Point::new(0, (top as usize * self.font_height) as i32),
+        );
+        self.fb
+            .fill_solid(&self.rows_rect(bottom - lines..bottom), self.bg_color)
+            .unwrap();
+    }
+
+    fn scroll_rows_down(&mut self, region: Range<u8>, lines: u8) {
+        let Range {
+            start: top,
+            end: bottom,
+        } = region;
+        if top >= bottom || bottom > self.mode.rows {
+            return;
+        }
+        let lines = lines.min(bottom - top);
+        if lines == 0 {
+            return;
+        }
+        self.reset_view();
+        let cols = self.mode.columns as usize;
+
+        let blank = Cell::blank(self.mode.attribute);
+        self.cells.copy_within(
+            top as usize * cols..(bottom - lines) as usize * cols,
+            (top + lines) as usize * cols,
+        );
+        self.cells[top as usize * cols..(top + lines) as usize * cols].fill(blank);
+
+        self.fb.copy_rect(
+            &self.rows_rect(top..bottom - lines),
+            Point::new(0, ((top + lines) as usize * self.font_height) as i32),
+        );
+        self.fb
+            .fill_solid(&self.rows_rect(top..top + lines), self.bg_color)
+            .unwrap();
+    }
+
+    /// Shows older lines from the scrollback buffer by one page
+    pub fn page_up(&mut self) {
+        let offset = (self.view_offset + self.mode.rows as usize).min(self.scrollback.len());
+        self.set_view_offset(offset);
+    }
+
+    /// Shows newer lines by one page, returning to the live screen at the end
+    pub fn page_down(&mut self) {
+        let offset = self.view_offset.saturating_sub(self.mode.rows as usize);
+        self.set_view_offset(offset);
+    }
+
+    /// Returns to the live screen if the scrollback buffer is being viewed
+    #[inline]
+    pub fn reset_view(&mut self) {
+        if self.view_offset > 0 {
+            self.set_view_offset(0);
+        }
+    }
+
+    fn set_view_offset(&mut self, offset: usize) {
+        if offset == self.view_offset {
+            return;
+        }
+        let cursor_visible = self.mode.is_cursor_visible();
+        if cursor_visible && self.view_offset == 0 {
+            self.draw_cursor(self.mode.cursor_column, self.mode.cursor_row, false);
+        }
+        self.view_offset = offset;
+        self.redraw();
+        if cursor_visible && offset == 0 {
+            self.draw_cursor(self.mode.cursor_column, self.mode.cursor_row, true);
+        }
+    }
+
+    /// Redraws the whole screen from the cells
+    fn redraw(&mut self) {
+        let cols = self.mode.columns as usize;
+        let history = self.scrollback.len();
+        for row in 0..self.mode.rows {
+            let line = history - self.view_offset + row as usize;
+            for col in 0..cols {
+                let cell = match self.scrollback.get(line) {
+                    Some(line) => line.get(col).copied().unwrap_or(Cell::blank(0)),
+                    None => self.cells[(line - history) * cols + col],
+                };
+                self.draw_cell(col as u8, row, cell);
+            }
+        }
+    }
+
+    fn rows_rect(&self, rows: Range<u8>) -> Rectangle {
+        Rectangle::new(
+            Point::new(0, (rows.start as usize * self.font_height) as i32),
+            Size::new(
+                (self.mode.columns as usize * self.font_width) as u32,
+                (rows.len() * self.font_height) as u32,
+            ),
+        )
+    }
+
+    fn draw_cell(&mut self, col: u8, row: u8, cell: Cell) {
+        let attribute = if cell.attribute == 0 {
+            System::DEFAULT_STDOUT_ATTRIBUTE
+        } else {
+            cell.attribute
+        };
+        let fg_color = IndexedColor(attribute & 0x0F);
+        let bg_color = IndexedColor((attribute & 0xF0) >> 4);
+        let origin = Point::new(
+            (col as usize * self.font_width) as i32,
+            (row as usize * self.font_height) as i32,
+        );
+        match self.font.glyph_for_char(cell.ch) {
+            Some(glyph) => self.fb.draw_glyph(origin, glyph, fg_color, bg_color),
+            None => self
+                .fb
+                .fill_solid(
+                    &Rectangle::new(
+                        origin,
+                        Size::new(self.font_width as u32, self.font_height as u32),
+                    ),
+                    bg_color,
+                )
+                .unwrap(),
+        }
+    }
+
+    fn put_char(&mut self, col: u8, row: u8, ch: char) {
+        let cell = Cell {
+            ch,
+            attribute: self.mode.attribute,
+        };
+        self.cells[row as usize * self.mode.columns as usize + col as usize] = cell;
+        self.draw_cell(col, row, cell);
+    }
+
     fn draw_cursor(&mut self, col: u8, row: u8, state: bool) {
         self.fb
             .fill_solid(
@@ -57,34 +297,29 @@ impl FbCon {
             )
             .unwrap();
     }
+}
 
-    fn adjust_coords(&mut self, col: u8, row: u8, wrap_around: bool) -> Option<(u8, u8)> {
-        if row < self.mode.rows && (!wrap_around || col < self.mode.columns) {
-            return None;
-        }
-
-        let mut col = col;
-        let mut row = row;
-
-        if wrap_around {
-            while col >= self.mode.columns {
-                col -= self.mode.columns;
-                row += 1;
-            }
-        }
+impl core::fmt::Write for FbCon {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.reset_view();
+        AnsiState::write_str(self, s);
+        Ok(())
+    }
+}
 
-        while row >= self.mode.rows {
-            // TODO: scroll
-            row -= 1;
-        }
+impl AnsiScreen for FbCon {
+    #[inline]
+    fn ansi_state(&mut self) -> &mut AnsiState {
+        &mut self.ansi
+    }
 
-        Some((col, row))
+    #[inline]
+    fn mode_mut(&mut self) -> &mut SimpleTextOutputMode {
+        &mut self.mode
     }
-}
 
-impl core::fmt::Write for FbCon {
-    fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        let old_cursor_visible = self.enable_cursor(false);
+    fn put_str(&mut self, s: &str) {
         let mut col = self.mode.cursor_column;
         let mut row = self.mode.cursor_row;
 
@@ -92,11 +327,7 @@ impl core::fmt::Write for FbCon {
             match ch {
                 '\n' => {
                     col = 0;
-                    row += 1;
-                    if let Some((new_col, new_row)) = self.adjust_coords(col, row, false) {
-                        col = new_col;
-                        row = new_row;
-                    }
+                    row = self.line_feed(row);
                 }
                 '\r' => {
                     col = 0;
@@ -107,38 +338,54 @@ impl core::fmt::Write for FbCon {
                     }
                 }
                 _ => {
-                    if let Some((new_col, new_row)) = self.adjust_coords(col, row, true) {
-                        col = new_col;
-                        row = new_row;
-                    }
-
-                    if let Some(glyph) = self.font.glyph_for_char(ch) {
-                        self.fb.draw_glyph(
-                            Point::new(
-                                (col as usize * self.font_width) as i32,
-                                (row as usize * self.font_height) as i32,
-                            ),
-                            glyph,
-                            self.fg_color,
-                            self.bg_color,
-                        );
+                    if col >= self.mode.columns {
+                        col = 0;
+                        row = self.line_feed(row);
                     }
-
+                    self.put_char(col, row, ch);
                     col += 1;
                 }
             }
         }
 
-        if let Some((new_col, new_row)) = self.adjust_coords(col, row, false) {
-            col = new_col;
-            row = new_row;
-        }
         self.mode.cursor_column = col;
         self.mode.cursor_row = row;
-        if old_cursor_visible {
-            self.enable_cursor(old_cursor_visible);
+    }
+
+    fn erase(&mut self, row: u8, cols: Range<u8>) {
+        let cols = cols.start..cols.end.min(self.mode.columns);
+        if row >= self.mode.rows || cols.is_empty() {
+            return;
         }
-        Ok(())
+        self.reset_view();
+        let base = row as usize * self.mode.columns as usize;
+        self.cells[base + cols.start as usize..base + cols.end as usize]
+            .fill(Cell::blank(self.mode.attribute));
+        self.fb
+            .fill_solid(
+                &Rectangle::new(
+                    Point::new(
+                        (cols.start as usize * self.font_width) as i32,
+                        (row as usize * self.font_height) as i32,
+                    ),
+                    Size::new(
+                        (cols.len() * self.font_width) as u32,
+                        self.font_height as u32,
+                    ),
+                ),
+                self.bg_color,
+            )
+            .unwrap();
+    }
+
+    #[inline]
+    fn scroll_region_up(&mut self, region: Range<u8>, lines: u8) {
+        self.scroll_rows_up(region, lines);
+    }
+
+    #[inline]
+    fn scroll_region_down(&mut self, region: Range<u8>, lines: u8) {
+        self.scroll_rows_down(region, lines);
     }
 }
 
@@ -146,6 +393,9 @@ impl SimpleTextOutput for FbCon {
     fn reset(&mut self) {
         self.set_attribute(0);
         self.mode.set_cursor_visible(false);
+        self.ansi.reset();
+        self.scrollback.clear();
+        self.view_offset = 0;
         self.clear_screen();
     }
 
@@ -161,19 +411,12 @@ impl SimpleTextOutput for FbCon {
     }
 
     fn clear_screen(&mut self) {
+        self.reset_view();
         let old_cursor_visible = self.enable_cursor(false);
 
+        self.cells.fill(Cell::blank(self.mode.attribute));
         self.fb
-            .fill_solid(
-                &Rectangle::new(
-                    Point::zero(),
-                    Size::new(
-                        self.mode.columns as u32 * self.font_width as u32,
-                        self.mode.rows as u32 * self.font_height as u32,
-                    ),
-                ),
-                self.bg_color,
-            )
+            .fill_solid(&self.rows_rect(0..self.mode.rows), self.bg_color)
             .unwrap();
 
         self.mode.cursor_column = 0;
@@ -184,21 +427,18 @@ impl SimpleTextOutput for FbCon {
     }
 
     fn set_cursor_position(&mut self, col: u32, row: u32) {
+        self.reset_view();
         let old_cursor_visible = self.enable_cursor(false);
         self.mode.cursor_column = (self.mode.columns as u32).min(col) as u8;
-        self.mode.cursor_row = (self.mode.rows as u32).min(row) as u8;
+        self.mode.cursor_row = (self.mode.rows as u32 - 1).min(row) as u8;
         if old_cursor_visible {
-            self.draw_cursor(
-                self.mode.cursor_column,
-                self.mode.cursor_row,
-                old_cursor_visible,
-            );
+            self.enable_cursor(old_cursor_visible);
         }
     }
 
     fn enable_cursor(&mut self, visible: bool) -> bool {
         let old_value = self.mode.is_cursor_visible();
-        if visible != old_value {
+        if visible != old_value && self.view_offset == 0 {
             self.draw_cursor(self.mode.cursor_column, self.mode.cursor_row, visible);
         }
         self.mode.set_cursor_visible(visible);
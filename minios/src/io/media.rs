@@ -1,3 +1,8 @@
+pub mod cache;
+pub mod partition;
+
+use crate::*;
+
 pub trait BlockDevice {
     fn reset(&mut self) -> Result<(), BlockIoError>;
 
@@ -6,6 +11,20 @@ pub trait BlockDevice {
     fn write(&mut self, block: LBA, buf: &[u8]) -> Result<(), BlockIoError>;
 
     fn media_info(&self) -> MediaInfo;
+
+    /// Writes back the blocks held by the device, if any
+    fn flush(&mut self) -> Result<(), BlockIoError> {
+        Ok(())
+    }
+}
+
+/// Block device registered in the system
+pub struct BlockDeviceEntry {
+    pub name: String,
+    pub device: partition::SharedBlockDevice,
+    /// Geometry to locate the PC-98 partitions
+    pub geometry: Option<partition::ChsGeometry>,
+    pub is_boot_device: bool,
 }
 
 #[repr(transparent)]
@@ -33,3 +52,20 @@ pub struct MediaInfo {
     pub io_align: u32,
     pub block_count: LBA,
 }
+
+impl MediaInfo {
+    /// The media cannot be written
+    pub const FLAG_READ_ONLY: u32 = 0x0000_0001;
+    /// `block_count` is an upper bound, as the device does not report the capacity
+    pub const FLAG_UNKNOWN_CAPACITY: u32 = 0x0000_0002;
+
+    #[inline]
+    pub const fn is_read_only(&self) -> bool {
+        self.flags & Self::FLAG_READ_ONLY != 0
+    }
+
+    #[inline]
+    pub const fn is_capacity_known(&self) -> bool {
+        self.flags & Self::FLAG_UNKNOWN_CAPACITY == 0
+    }
+}
@@ -0,0 +1,323 @@
+//! Block Cache
+//!
+//! Keeps recently used blocks in memory to reduce slow accesses to the underlying device.
+
+use super::*;
+use crate::*;
+use alloc::vec;
+
+/// Block device wrapper with an LRU block cache, sequential read-ahead and write-back
+///
+/// Written blocks stay in the cache until they are evicted or [`BlockDevice::flush`] is called.
+pub struct CachedBlockDevice {
+    device: Box<dyn BlockDevice + Send>,
+    /// Media the cached blocks belong to
+    media_info: MediaInfo,
+    blocks: BTreeMap<u64, CachedBlock>,
+    capacity: usize,
+    read_ahead: usize,
+    /// Incremented on every access to order the blocks by recency
+    clock: u64,
+    /// Block following the last read, to detect sequential reads
+    next_sequential: Option<u64>,
+}
+
+struct CachedBlock {
+    data: Box<[u8]>,
+    is_dirty: bool,
+    last_access: u64,
+}
+
+impl CachedBlockDevice {
+    /// Default size of the cache in bytes
+    pub const DEFAULT_CACHE_SIZE: usize = 64 * 1024;
+
+    /// Default number of blocks read ahead on sequential reads
+    pub const DEFAULT_READ_AHEAD: usize = 8;
+
+    const MIN_CAPACITY: usize = 4;
+
+    #[inline]
+    pub fn new(device: Box<dyn BlockDevice + Send>) -> Self {
+        let block_size = (device.media_info().block_size as usize).max(1);
+        let capacity = (Self::DEFAULT_CACHE_SIZE / block_size).max(Self::MIN_CAPACITY);
+        Self::with_capacity(device, capacity, Self::DEFAULT_READ_AHEAD)
+    }
+
+    /// Creates a cache holding up to `capacity` blocks, reading ahead `read_ahead` blocks
+    pub fn with_capacity(
+        device: Box<dyn BlockDevice + Send>,
+        capacity: usize,
+        read_ahead: usize,
+    ) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            media_info: device.media_info(),
+            device,
+            blocks: BTreeMap::new(),
+            capacity,
+            read_ahead: read_ahead.min(capacity - 1),
+            clock: 0,
+            next_sequential: None,
+        }
+    }
+
+    /// Returns the maximum number of cached blocks
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of blocks currently cached
+    #[inline]
+    pub fn cached_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns the number of blocks not yet written back
+    #[inline]
+    pub fn dirty_blocks(&self) -> usize {
+        self.blocks.values().filter(|v| v.is_dirty).count()
+    }
+
+    /// Discards all cached blocks, including ones not yet written back
+    pub fn invalidate(&mut self) {
+        self.blocks.clear();
+        self.next_sequential = None;
+    }
+
+    #[inline]
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Invalidates the cache if the device reports that the media has been changed
+    fn handle_error(&mut self, error: BlockIoError) -> BlockIoError {
+        if error == BlockIoError::MediaChanged {
+            self.invalidate();
+            self.media_info = self.device.media_info();
+        }
+        error
+    }
+
+    /// Invalidates the cache if the device now has different media
+    fn check_media(&mut self) {
+        let current = self.device.media_info();
+        if current.media_id != self.media_info.media_id
+            || current.block_size != self.media_info.block_size
+            || current.block_count != self.media_info.block_count
+        {
+            self.invalidate();
+            self.media_info = current;
+        }
+    }
+
+    fn check_range(&self, block: LBA, len: usize) -> Result<usize, BlockIoError> {
+        let block_size = self.media_info.block_size as usize;
+        if block_size == 0 || !len.is_multiple_of(block_size) {
+            return Err(BlockIoError::InvalidParameter);
+        }
+        let count = len / block_size;
+        match block.0.checked_add(count as u64) {
+            Some(end) if end <= self.media_info.block_count.0 => Ok(count),
+            _ => Err(BlockIoError::InvalidParameter),
+        }
+    }
+
+    /// Makes room for a new block, writing back the least recently used block if it is dirty
+    fn evict(&mut self) -> Result<(), BlockIoError> {
+        while self.blocks.len() >= self.capacity {
+            let Some(lba) = self
+                .blocks
+                .iter()
+                .min_by_key(|(_, v)| v.last_access)
+                .map(|(k, _)| *k)
+            else {
+                break;
+            };
+            if let Some(entry) = self.blocks.get(&lba)
+                && entry.is_dirty
+            {
+                let result = self.device.write(LBA(lba), &entry.data);
+                result.map_err(|err| self.handle_error(err))?;
+            }
+            self.blocks.remove(&lba);
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, lba: u64, data: &[u8], is_dirty: bool) -> Result<(), BlockIoError> {
+        let last_access = self.tick();
+        if let Some(entry) = self.blocks.get_mut(&lba) {
+            entry.data.copy_from_slice(data);
+            entry.is_dirty |= is_dirty;
+            entry.last_access = last_access;
+            return Ok(());
+        }
+        self.evict()?;
+        self.blocks.insert(
+            lba,
+            CachedBlock {
+                data: data.into(),
+                is_dirty,
+                last_access,
+            },
+        );
+        Ok(())
+    }
+}
+
+impl BlockDevice for CachedBlockDevice {
+    fn reset(&mut self) -> Result<(), BlockIoError> {
+        let _ = self.flush();
+        self.invalidate();
+        let result = self.device.reset();
+        self.media_info = self.device.media_info();
+        result
+    }
+
+    fn read(&mut self, block: LBA, buf: &mut [u8]) -> Result<(), BlockIoError> {
+        self.check_media();
+        let count = self.check_range(block, buf.len())?;
+        let block_size = self.media_info.block_size as usize;
+        let is_sequential = self.next_sequential == Some(block.0);
+        let end = block.0 + count as u64;
+
+        let mut lba = block.0;
+        while lba < end {
+            let offset = (lba - block.0) as usize * block_size;
+            let last_access = self.tick();
+            if let Some(entry) = self.blocks.get_mut(&lba) {
+                buf[offset..offset + block_size].copy_from_slice(&entry.data);
+                entry.last_access = last_access;
+                lba += 1;
+                continue;
+            }
+
+            // Run of blocks missing in the cache
+            let mut run_end = lba + 1;
+            while run_end < end && !self.blocks.contains_key(&run_end) {
+                run_end += 1;
+            }
+            let run_len = (run_end - lba) as usize;
+            if run_len >= self.capacity {
+                // Too large to be cached
+                let result = self
+                    .device
+                    .read(LBA(lba), &mut buf[offset..offset + run_len * block_size]);
+                result.map_err(|err| self.handle_error(err))?;
+                lba = run_end;
+                continue;
+            }
+
+            let mut fill_end = run_end;
+            // The end of the media is unknown, so it might be read beyond
+            if is_sequential && run_end == end && self.media_info.is_capacity_known() {
+                let limit = (end + self.read_ahead as u64)
+                    .min(self.media_info.block_count.0)
+                    .min(lba + self.capacity as u64);
+                while fill_end < limit && !self.blocks.contains_key(&fill_end) {
+                    fill_end += 1;
+                }
+            }
+
+            let mut temp = vec![0; (fill_end - lba) as usize * block_size];
+            let result = match self.device.read(LBA(lba), &mut temp) {
+                // Errors in the blocks read ahead do not fail the requested blocks
+                Err(err) if err != BlockIoError::MediaChanged && fill_end > run_end => {
+                    temp.truncate(run_len * block_size);
+                    self.device.read(LBA(lba), &mut temp)
+                }
+                result => result,
+            };
+            result.map_err(|err| self.handle_error(err))?;
+            buf[offset..offset + run_len * block_size]
+                .copy_from_slice(&temp[..run_len * block_size]);
+            for (index, data) in temp.chunks_exact(block_size).enumerate() {
+                self.insert(lba + index as u64, data, false)?;
+            }
+            lba = run_end;
+        }
+
+        self.next_sequential = Some(end);
+        Ok(())
+    }
+
+    fn write(&mut self, block: LBA, buf: &[u8]) -> Result<(), BlockIoError> {
+        self.check_media();
+        if self.media_info.is_read_only() {
+            return Err(BlockIoError::WriteProtected);
+        }
+        let count = self.check_range(block, buf.len())?;
+        let block_size = self.media_info.block_size as usize;
+
+        if count >= self.capacity {
+            // Too large to be cached, writes through and updates the cached copies
+            let result = self.device.write(block, buf);
+            result.map_err(|err| self.handle_error(err))?;
+            for (index, data) in buf.chunks_exact(block_size).enumerate() {
+                if let Some(entry) = self.blocks.get_mut(&(block.0 + index as u64)) {
+                    entry.data.copy_from_slice(data);
+                    entry.is_dirty = false;
+                }
+            }
+            return Ok(());
+        }
+
+        for (index, data) in buf.chunks_exact(block_size).enumerate() {
+            self.insert(block.0 + index as u64, data, true)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn media_info(&self) -> MediaInfo {
+        self.device.media_info()
+    }
+
+    /// Writes back all dirty blocks, merging consecutive blocks into a single write
+    fn flush(&mut self) -> Result<(), BlockIoError> {
+        let dirty = self
+            .blocks
+            .iter()
+            .filter(|(_, v)| v.is_dirty)
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>();
+
+        let mut index = 0;
+        while index < dirty.len() {
+            let start = dirty[index];
+            let mut run_len = 1;
+            while dirty
+                .get(index + run_len)
+                .is_some_and(|&v| v == start + run_len as u64)
+            {
+                run_len += 1;
+            }
+
+            let mut temp = Vec::new();
+            for lba in start..start + run_len as u64 {
+                if let Some(entry) = self.blocks.get(&lba) {
+                    temp.extend_from_slice(&entry.data);
+                }
+            }
+            let result = self.device.write(LBA(start), &temp);
+            result.map_err(|err| self.handle_error(err))?;
+            for lba in start..start + run_len as u64 {
+                if let Some(entry) = self.blocks.get_mut(&lba) {
+                    entry.is_dirty = false;
+                }
+            }
+            index += run_len;
+        }
+
+        self.device.flush()
+    }
+}
+
+impl Drop for CachedBlockDevice {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
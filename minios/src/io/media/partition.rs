@@ -0,0 +1,485 @@
+//! Partition Tables
+//!
+//! Supports MBR with extended partitions, GPT and the PC-98 IPL partition table.
+
+use super::*;
+use crate::sync::spin::SpinMutex;
+use crate::*;
+use alloc::vec;
+use guid::{Guid, guid};
+
+/// Block device shared by its partitions
+pub type SharedBlockDevice = Arc<SpinMutex<Box<dyn BlockDevice + Send>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionTableType {
+    Mbr,
+    Gpt,
+    Pc98,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionType {
+    /// System ID of the MBR
+    Mbr(u8),
+    Gpt {
+        type_guid: Guid,
+        unique_guid: Guid,
+    },
+    /// System IDs of the PC-98 IPL partition table, without the bootable and active flags
+    Pc98 {
+        mid: u8,
+        sid: u8,
+    },
+}
+
+/// Entry of the partition table
+#[derive(Debug, Clone)]
+pub struct PartitionInfo {
+    /// Index in the table, logical partitions of the MBR start from 4
+    pub index: usize,
+    pub start: LBA,
+    pub block_count: u64,
+    pub partition_type: PartitionType,
+    pub is_bootable: bool,
+    /// Name of the GPT or PC-98 partition
+    pub name: String,
+}
+
+/// Geometry of the disk, needed to locate the PC-98 partitions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChsGeometry {
+    pub heads: u32,
+    pub sectors_per_track: u32,
+}
+
+pub struct PartitionTable {
+    table_type: PartitionTableType,
+    partitions: Vec<PartitionInfo>,
+}
+
+impl PartitionTable {
+    pub const EFI_SYSTEM_PARTITION: Guid = guid!("c12a7328-f81f-11d2-ba4b-00a0c93ec93b");
+    pub const BASIC_DATA_PARTITION: Guid = guid!("ebd0a0a2-b9e5-4433-87c0-68b6b72699c7");
+    pub const LINUX_FILESYSTEM_DATA: Guid = guid!("0fc63daf-8483-4772-8e79-3d69d8477de4");
+
+    const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+    const MBR_TABLE_OFFSET: usize = 0x1be;
+    const MBR_TYPE_PROTECTIVE: u8 = 0xee;
+    const MBR_TYPE_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+    /// Limit of the logical partitions to avoid an infinite loop on a broken chain
+    const MAX_LOGICAL_PARTITIONS: usize = 128;
+
+    const GPT_SIGNATURE: [u8; 8] = *b"EFI PART";
+    const GPT_MIN_HEADER_SIZE: usize = 92;
+    const GPT_MAX_ENTRIES: u32 = 1024;
+
+    const PC98_IPL_SIGNATURE: [u8; 4] = *b"IPL1";
+    const PC98_ENTRY_SIZE: usize = 32;
+    const PC98_TABLE_SIZE: usize = 512;
+
+    /// Reads the partition table, returns `None` if the media has no partition table
+    ///
+    /// The PC-98 partition table is ignored if `geometry` is `None`.
+    pub fn read(
+        device: &mut dyn BlockDevice,
+        geometry: Option<ChsGeometry>,
+    ) -> Result<Option<Self>, BlockIoError> {
+        let media_info = device.media_info();
+        let block_size = media_info.block_size as usize;
+        if block_size == 0 {
+            return Err(BlockIoError::InvalidParameter);
+        }
+
+        let sector0 = Self::read_bytes(device, 0, 512)?;
+        if sector0[4..8] == Self::PC98_IPL_SIGNATURE {
+            return match geometry {
+                Some(geometry) => Self::read_pc98(device, geometry).map(Some),
+                None => Ok(None),
+            };
+        }
+
+        if sector0[510..512] != Self::MBR_SIGNATURE {
+            return Ok(None);
+        }
+        let entries = Self::mbr_entries(&sector0);
+        // Boot sectors of FAT volumes also have the signature
+        if entries.iter().any(|v| v.0 & 0x7f != 0) || entries.iter().all(|v| v.1 == 0 || v.3 == 0) {
+            return Ok(None);
+        }
+        if entries.iter().any(|v| v.1 == Self::MBR_TYPE_PROTECTIVE)
+            && let Some(table) = Self::read_gpt(device)?
+        {
+            return Ok(Some(table));
+        }
+        Self::read_mbr(device, &entries).map(Some)
+    }
+
+    #[inline]
+    pub const fn table_type(&self) -> PartitionTableType {
+        self.table_type
+    }
+
+    #[inline]
+    pub fn partitions(&self) -> &[PartitionInfo] {
+        &self.partitions
+    }
+
+    /// Reads bytes from the start of the block, the size is rounded up to the block size
+    fn read_bytes(
+        device: &mut dyn BlockDevice,
+        block: u64,
+        len: usize,
+    ) -> Result<Vec<u8>, BlockIoError> {
+        let block_size = device.media_info().block_size as usize;
+        let mut buf = vec![0; len.next_multiple_of(block_size)];
+        device.read(LBA(block), &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Returns (status, type, start, count) of the primary entries
+    fn mbr_entries(sector: &[u8]) -> [(u8, u8, u32, u32); 4] {
+        core::array::from_fn(|index| {
+            let entry = &sector[Self::MBR_TABLE_OFFSET + index * 16..][..16];
+            (
+                entry[0],
+                entry[4],
+                u32::from_le_bytes(entry[8..12].try_into().unwrap()),
+                u32::from_le_bytes(entry[12..16].try_into().unwrap()),
+            )
+        })
+    }
+
+    fn read_mbr(
+        device: &mut dyn BlockDevice,
+        entries: &[(u8, u8, u32, u32); 4],
+    ) -> Result<Self, BlockIoError> {
+        let mut partitions = Vec::new();
+        let mut extended = None;
+        for (index, &(status, partition_type, start, count)) in entries.iter().enumerate() {
+            if partition_type == 0 || count == 0 {
+                continue;
+            }
+            if Self::MBR_TYPE_EXTENDED.contains(&partition_type) {
+                extended.get_or_insert(start);
+                continue;
+            }
+            partitions.push(PartitionInfo {
+                index,
+                start: LBA(start as u64),
+                block_count: count as u64,
+                partition_type: PartitionType::Mbr(partition_type),
+                is_bootable: status & 0x80 != 0,
+                name: String::new(),
+            });
+        }
+
+        // Logical partitions are linked from the extended partition
+        if let Some(extended_start) = extended {
+            let mut ebr = extended_start;
+            for index in 4..4 + Self::MAX_LOGICAL_PARTITIONS {
+                let sector = Self::read_bytes(device, ebr as u64, 512)?;
+                if sector[510..512] != Self::MBR_SIGNATURE {
+                    break;
+                }
+                let entries = Self::mbr_entries(&sector);
+                let (status, partition_type, start, count) = entries[0];
+                if partition_type != 0 && count != 0 {
+                    partitions.push(PartitionInfo {
+                        index,
+                        start: LBA(ebr as u64 + start as u64),
+                        block_count: count as u64,
+                        partition_type: PartitionType::Mbr(partition_type),
+                        is_bootable: status & 0x80 != 0,
+                        name: String::new(),
+                    });
+                }
+                let (_, next_type, next_start, _) = entries[1];
+                if !Self::MBR_TYPE_EXTENDED.contains(&next_type) || next_start == 0 {
+                    break;
+                }
+                ebr = extended_start + next_start;
+            }
+        }
+
+        Ok(Self {
+            table_type: PartitionTableType::Mbr,
+            partitions,
+        })
+    }
+
+    /// Reads the primary GPT, or the backup GPT if the primary one is broken
+    ///
+    /// The backup GPT is not probed if the capacity of the device is unknown.
+    fn read_gpt(device: &mut dyn BlockDevice) -> Result<Option<Self>, BlockIoError> {
+        if let Some(table) = Self::read_gpt_at(device, 1)? {
+            return Ok(Some(table));
+        }
+        let media_info = device.media_info();
+        if !media_info.is_capacity_known() {
+            return Ok(None);
+        }
+        Self::read_gpt_at(device, media_info.block_count.0.saturating_sub(1))
+    }
+
+    fn read_gpt_at(
+        device: &mut dyn BlockDevice,
+        header_lba: u64,
+    ) -> Result<Option<Self>, BlockIoError> {
+        let block_size = device.media_info().block_size as usize;
+        let mut header = Self::read_bytes(device, header_lba, Self::GPT_MIN_HEADER_SIZE)?;
+        let u32_at = |bytes: &[u8], offset: usize| {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+        let u64_at = |bytes: &[u8], offset: usize| {
+            u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+        };
+
+        if header[0..8] != Self::GPT_SIGNATURE {
+            return Ok(None);
+        }
+        let header_size = u32_at(&header, 12) as usize;
+        if !(Self::GPT_MIN_HEADER_SIZE..=block_size).contains(&header_size)
+            || u64_at(&header, 24) != header_lba
+        {
+            return Ok(None);
+        }
+        let header_crc = u32_at(&header, 16);
+        header[16..20].fill(0);
+        if crc32(&header[..header_size]) != header_crc {
+            return Ok(None);
+        }
+
+        let entries_lba = u64_at(&header, 72);
+        let n_entries = u32_at(&header, 80);
+        let entry_size = u32_at(&header, 84) as usize;
+        if n_entries > Self::GPT_MAX_ENTRIES
+            || !(128..=block_size).contains(&entry_size)
+            || !entry_size.is_multiple_of(8)
+        {
+            return Ok(None);
+        }
+        let Some(entries_len) = (n_entries as usize).checked_mul(entry_size) else {
+            return Ok(None);
+        };
+        let entries = Self::read_bytes(device, entries_lba, entries_len)?;
+        let entries = &entries[..entries_len];
+        if crc32(entries) != u32_at(&header, 88) {
+            return Ok(None);
+        }
+
+        let mut partitions = Vec::new();
+        for (index, entry) in entries.chunks_exact(entry_size).enumerate() {
+            let type_guid = Guid::from_raw(entry[0..16].try_into().unwrap());
+            if type_guid == Guid::NULL {
+                continue;
+            }
+            let unique_guid = Guid::from_raw(entry[16..32].try_into().unwrap());
+            let first_lba = u64_at(entry, 32);
+            let last_lba = u64_at(entry, 40);
+            if last_lba < first_lba {
+                continue;
+            }
+            let attributes = u64_at(entry, 48);
+            let name = entry[56..128]
+                .chunks_exact(2)
+                .map(|v| u16::from_le_bytes([v[0], v[1]]))
+                .take_while(|v| *v != 0);
+            let name = char::decode_utf16(name)
+                .map(|v| v.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect();
+            partitions.push(PartitionInfo {
+                index,
+                start: LBA(first_lba),
+                block_count: last_lba - first_lba + 1,
+                partition_type: PartitionType::Gpt {
+                    type_guid,
+                    unique_guid,
+                },
+                // Legacy BIOS bootable
+                is_bootable: attributes & 0x04 != 0,
+                name,
+            });
+        }
+
+        Ok(Some(Self {
+            table_type: PartitionTableType::Gpt,
+            partitions,
+        }))
+    }
+
+    fn read_pc98(
+        device: &mut dyn BlockDevice,
+        geometry: ChsGeometry,
+    ) -> Result<Self, BlockIoError> {
+        // The table follows the IPL in the second sector
+        let table = Self::read_bytes(device, 1, Self::PC98_TABLE_SIZE)?;
+        let cylinder_size = geometry.heads as u64 * geometry.sectors_per_track as u64;
+        let chs_to_lba = |cylinder: u16, head: u8, sector: u8| {
+            cylinder as u64 * cylinder_size
+                + head as u64 * geometry.sectors_per_track as u64
+                + sector as u64
+        };
+
+        let mut partitions = Vec::new();
+        for (index, entry) in table[..Self::PC98_TABLE_SIZE]
+            .chunks_exact(Self::PC98_ENTRY_SIZE)
+            .enumerate()
+        {
+            let mid = entry[0];
+            let sid = entry[1];
+            if mid == 0 || sid == 0 {
+                continue;
+            }
+            let start = chs_to_lba(
+                u16::from_le_bytes([entry[10], entry[11]]),
+                entry[9],
+                entry[8],
+            );
+            // The end cylinder is used entirely
+            let end = (u16::from_le_bytes([entry[14], entry[15]]) as u64 + 1) * cylinder_size;
+            if end <= start {
+                continue;
+            }
+            let name = entry[16..32]
+                .iter()
+                .map(|v| {
+                    if v.is_ascii_graphic() {
+                        *v as char
+                    } else {
+                        ' '
+                    }
+                })
+                .collect::<String>()
+                .trim_end()
+                .to_owned();
+            partitions.push(PartitionInfo {
+                index,
+                start: LBA(start),
+                block_count: end - start,
+                partition_type: PartitionType::Pc98 {
+                    mid: mid & 0x7f,
+                    sid: sid & 0x7f,
+                },
+                is_bootable: mid & 0x80 != 0,
+                name,
+            });
+        }
+
+        Ok(Self {
+            table_type: PartitionTableType::Pc98,
+            partitions,
+        })
+    }
+}
+
+/// Partition as a block device
+pub struct Partition {
+    device: SharedBlockDevice,
+    start: u64,
+    media_info: MediaInfo,
+}
+
+impl Partition {
+    pub fn new(device: SharedBlockDevice, info: &PartitionInfo) -> Self {
+        let mut media_info = device.lock().media_info();
+        media_info.block_count = LBA(info.block_count);
+        media_info.flags &= !MediaInfo::FLAG_UNKNOWN_CAPACITY;
+        Self {
+            device,
+            start: info.start.0,
+            media_info,
+        }
+    }
+
+    /// Returns the partitions of the device, or `None` if the media has no partition table
+    pub fn from_device(
+        device: SharedBlockDevice,
+        geometry: Option<ChsGeometry>,
+    ) -> Result<Option<Vec<(PartitionInfo, Self)>>, BlockIoError> {
+        let table = {
+            let mut device = device.lock();
+            PartitionTable::read(device.as_mut(), geometry)?
+        };
+        Ok(table.map(|table| {
+            table
+                .partitions
+                .into_iter()
+                .map(|info| {
+                    let partition = Self::new(device.clone(), &info);
+                    (info, partition)
+                })
+                .collect()
+        }))
+    }
+
+    fn translate(&self, block: LBA, len: usize) -> Result<LBA, BlockIoError> {
+        let block_size = self.media_info.block_size as usize;
+        if block_size == 0 || !len.is_multiple_of(block_size) {
+            return Err(BlockIoError::InvalidParameter);
+        }
+        let count = (len / block_size) as u64;
+        match block.0.checked_add(count) {
+            Some(end) if end <= self.media_info.block_count.0 => Ok(LBA(self.start + block.0)),
+            _ => Err(BlockIoError::InvalidParameter),
+        }
+    }
+}
+
+impl BlockDevice for Partition {
+    fn reset(&mut self) -> Result<(), BlockIoError> {
+        self.device.lock().reset()
+    }
+
+    fn read(&mut self, block: LBA, buf: &mut [u8]) -> Result<(), BlockIoError> {
+        let block = self.translate(block, buf.len())?;
+        self.device.lock().read(block, buf)
+    }
+
+    fn write(&mut self, block: LBA, buf: &[u8]) -> Result<(), BlockIoError> {
+        let block = self.translate(block, buf.len())?;
+        self.device.lock().write(block, buf)
+    }
+
+    #[inline]
+    fn media_info(&self) -> MediaInfo {
+        self.media_info
+    }
+
+    fn flush(&mut self) -> Result<(), BlockIoError> {
+        self.device.lock().flush()
+    }
+}
+
+/// The whole device shared with its partitions
+impl BlockDevice for SharedBlockDevice {
+    fn reset(&mut self) -> Result<(), BlockIoError> {
+        self.lock().reset()
+    }
+
+    fn read(&mut self, block: LBA, buf: &mut [u8]) -> Result<(), BlockIoError> {
+        self.lock().read(block, buf)
+    }
+
+    fn write(&mut self, block: LBA, buf: &[u8]) -> Result<(), BlockIoError> {
+        self.lock().write(block, buf)
+    }
+
+    #[inline]
+    fn media_info(&self) -> MediaInfo {
+        self.lock().media_info()
+    }
+
+    fn flush(&mut self) -> Result<(), BlockIoError> {
+        self.lock().flush()
+    }
+}
+
+/// CRC-32 used by GPT
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
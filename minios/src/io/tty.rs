@@ -1,25 +1,41 @@
 //! Simple Console I/O
 
+pub mod ansi;
+pub mod line_editor;
 pub mod null;
 pub mod vt100;
 
-use core::num::NonZero;
+use crate::platform::{Platform, PlatformTrait};
+use crate::*;
+use core::{num::NonZero, time::Duration};
+use libhid::Modifier;
 
 pub trait SimpleTextInput {
     fn reset(&mut self);
 
     fn read_key_stroke(&mut self) -> Option<NonZeroInputKey>;
 
-    fn wait_for_key(&mut self, timeout: usize) -> Option<NonZeroInputKey> {
+    /// Returns the current state of the modifier keys, if the device can tell
+    fn modifiers(&mut self) -> Modifier {
+        Modifier::empty()
+    }
+
+    /// Waits for a key stroke until the timeout expires, or forever if `timeout` is `None`
+    fn wait_for_key(&mut self, timeout: Option<Duration>) -> Option<NonZeroInputKey> {
+        // Polled devices cannot wake up the processor, so it is woken up at least every interval
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+        let deadline = timeout.map(|v| System::monotonic() + v);
         loop {
             if let Some(key) = self.read_key_stroke() {
                 return Some(key);
             }
-            if timeout == 0 {
-                return None;
-            } else {
-                // TODO:
-            }
+            let now = System::monotonic();
+            let deadline = match deadline {
+                Some(deadline) if now >= deadline => return None,
+                Some(deadline) => deadline,
+                None => Duration::MAX,
+            };
+            Platform::idle(deadline.min(now + POLL_INTERVAL));
         }
     }
 }
@@ -31,6 +47,39 @@ pub struct InputKey {
     pub unicode_char: u16,
 }
 
+/// Scan codes of the special keys, as reported by the PC BIOS (INT 16h)
+impl InputKey {
+    pub const SCAN_ESC: u16 = 0x01;
+    pub const SCAN_BACKSPACE: u16 = 0x0E;
+    pub const SCAN_TAB: u16 = 0x0F;
+    pub const SCAN_ENTER: u16 = 0x1C;
+    pub const SCAN_F1: u16 = 0x3B;
+    pub const SCAN_F2: u16 = 0x3C;
+    pub const SCAN_F3: u16 = 0x3D;
+    pub const SCAN_F4: u16 = 0x3E;
+    pub const SCAN_F5: u16 = 0x3F;
+    pub const SCAN_F6: u16 = 0x40;
+    pub const SCAN_F7: u16 = 0x41;
+    pub const SCAN_F8: u16 = 0x42;
+    pub const SCAN_F9: u16 = 0x43;
+    pub const SCAN_F10: u16 = 0x44;
+    pub const SCAN_HOME: u16 = 0x47;
+    pub const SCAN_UP: u16 = 0x48;
+    pub const SCAN_PAGE_UP: u16 = 0x49;
+    pub const SCAN_LEFT: u16 = 0x4B;
+    pub const SCAN_RIGHT: u16 = 0x4D;
+    pub const SCAN_END: u16 = 0x4F;
+    pub const SCAN_DOWN: u16 = 0x50;
+    pub const SCAN_PAGE_DOWN: u16 = 0x51;
+    pub const SCAN_INSERT: u16 = 0x52;
+    pub const SCAN_DELETE: u16 = 0x53;
+    pub const SCAN_F11: u16 = 0x85;
+    pub const SCAN_F12: u16 = 0x86;
+
+    /// Scan code used for characters that do not come from a physical key
+    pub const SCAN_CHAR: u16 = 0xFFFF;
+}
+
 pub trait SimpleTextOutput: core::fmt::Write {
     fn reset(&mut self);
 
@@ -0,0 +1,529 @@
+//! ANSI/VT100 Escape Sequence Interpreter for local text outputs
+
+use super::*;
+use crate::System;
+use core::ops::Range;
+
+/// ANSI color index (RGB) to PC attribute color (BGR)
+const COLOR_TABLE: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+const MAX_PARAMS: usize = 16;
+
+/// Primitive operations of a character cell screen used to execute escape sequences
+pub trait AnsiScreen: SimpleTextOutput {
+    fn ansi_state(&mut self) -> &mut AnsiState;
+
+    fn mode_mut(&mut self) -> &mut SimpleTextOutputMode;
+
+    /// Writes text that does not contain any escape sequences
+    fn put_str(&mut self, s: &str);
+
+    /// Fills the columns of the row with blanks in the current attribute
+    fn erase(&mut self, row: u8, cols: Range<u8>);
+
+    /// Moves the rows of the region up, filling the exposed rows with blanks
+    fn scroll_region_up(&mut self, region: Range<u8>, lines: u8);
+
+    /// Moves the rows of the region down, filling the exposed rows with blanks
+    fn scroll_region_down(&mut self, region: Range<u8>, lines: u8);
+
+    /// Returns the row after a line feed, scrolling if the row is at the bottom of the scroll region
+    fn line_feed(&mut self, row: u8) -> u8 {
+        let rows = self.mode_mut().rows;
+        let region = self.ansi_state().scroll_region(rows);
+        if row + 1 == region.end {
+            self.scroll_region_up(region, 1);
+            row
+        } else if row + 1 >= rows {
+            row
+        } else {
+            row + 1
+        }
+    }
+}
+
+/// Parser and terminal state shared by the local text outputs
+pub struct AnsiState {
+    parser: AnsiParser,
+    saved_cursor: Option<(u8, u8, u8)>,
+    scroll_region: Option<(u8, u8)>,
+    is_reversed: bool,
+}
+
+impl AnsiState {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            parser: AnsiParser::new(),
+            saved_cursor: None,
+            scroll_region: None,
+            is_reversed: false,
+        }
+    }
+
+    #[inline]
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Returns the current scroll region, or the whole screen if it is not set
+    #[inline]
+    pub fn scroll_region(&self, rows: u8) -> Range<u8> {
+        match self.scroll_region {
+            Some((top, bottom)) if top < bottom && bottom <= rows => top..bottom,
+            _ => 0..rows,
+        }
+    }
+
+    /// Sets the scroll region to the rows `top..bottom`, or resets it to the whole screen if it is invalid
+    #[inline]
+    pub fn set_scroll_region(&mut self, top: u8, bottom: u8, rows: u8) {
+        self.scroll_region = (top < bottom && bottom <= rows).then_some((top, bottom));
+    }
+
+    /// Writes the string to the screen, interpreting the escape sequences
+    pub fn write_str<T: AnsiScreen + ?Sized>(screen: &mut T, s: &str) {
+        let mut cursor_visible = screen.enable_cursor(false);
+
+        let mut run_start = 0;
+        for (index, ch) in s.char_indices() {
+            if screen.ansi_state().parser.is_ground() && ch != '\x1b' {
+                continue;
+            }
+            if run_start < index {
+                screen.put_str(&s[run_start..index]);
+            }
+            run_start = index + ch.len_utf8();
+
+            match screen.ansi_state().parser.advance(ch) {
+                Some(Sequence::Control) => {
+                    screen.put_str(&s[index..run_start]);
+                }
+                Some(sequence) => {
+                    Self::execute(screen, sequence, &mut cursor_visible);
+                }
+                None => {}
+            }
+        }
+        if run_start < s.len() && screen.ansi_state().parser.is_ground() {
+            screen.put_str(&s[run_start..]);
+        }
+
+        if cursor_visible {
+            screen.enable_cursor(true);
+        }
+    }
+
+    fn execute<T: AnsiScreen + ?Sized>(
+        screen: &mut T,
+        sequence: Sequence,
+        cursor_visible: &mut bool,
+    ) {
+        let mode = screen.mode_mut();
+        let (cols, rows) = (mode.columns, mode.rows);
+        let (col, row) = (mode.cursor_column.min(cols - 1), mode.cursor_row);
+        let region = screen.ansi_state().scroll_region(rows);
+
+        match sequence {
+            Sequence::Control => {}
+            Sequence::Esc(ch) => match ch {
+                // RIS
+                'c' => {
+                    screen.ansi_state().reset();
+                    screen.reset();
+                    *cursor_visible = screen.enable_cursor(false);
+                }
+                // DECSC
+                '7' => Self::save_cursor(screen),
+                // DECRC
+                '8' => Self::restore_cursor(screen),
+                // IND
+                'D' => {
+                    let row = screen.line_feed(row);
+                    Self::move_to(screen, col, row);
+                }
+                // NEL
+                'E' => {
+                    let row = screen.line_feed(row);
+                    Self::move_to(screen, 0, row);
+                }
+                // RI
+                'M' => {
+                    if row == region.start {
+                        screen.scroll_region_down(region, 1);
+                    } else if row > 0 {
+                        Self::move_to(screen, col, row - 1);
+                    }
+                }
+                _ => {}
+            },
+            Sequence::Csi(csi) => {
+                let n = csi.param_or(0, 1);
+                match (csi.private, csi.final_byte) {
+                    (0, 'A') => Self::move_to(screen, col, row.saturating_sub(n)),
+                    (0, 'B') => {
+                        Self::move_to(screen, col, Self::clamp(row as u16 + n as u16, rows))
+                    }
+                    (0, 'C') => {
+                        Self::move_to(screen, Self::clamp(col as u16 + n as u16, cols), row)
+                    }
+                    (0, 'D') => Self::move_to(screen, col.saturating_sub(n), row),
+                    (0, 'E') => Self::move_to(screen, 0, Self::clamp(row as u16 + n as u16, rows)),
+                    (0, 'F') => Self::move_to(screen, 0, row.saturating_sub(n)),
+                    (0, 'G') | (0, '`') => {
+                        Self::move_to(screen, Self::clamp(n as u16 - 1, cols), row)
+                    }
+                    (0, 'd') => Self::move_to(screen, col, Self::clamp(n as u16 - 1, rows)),
+                    (0, 'H') | (0, 'f') => {
+                        let new_row = Self::clamp(csi.param_or(0, 1) as u16 - 1, rows);
+                        let new_col = Self::clamp(csi.param_or(1, 1) as u16 - 1, cols);
+                        Self::move_to(screen, new_col, new_row);
+                    }
+                    // ED
+                    (0, 'J') => match csi.param(0) {
+                        0 => {
+                            screen.erase(row, col..cols);
+                            for row in row + 1..rows {
+                                screen.erase(row, 0..cols);
+                            }
+                        }
+                        1 => {
+                            for row in 0..row {
+                                screen.erase(row, 0..cols);
+                            }
+                            screen.erase(row, 0..col + 1);
+                        }
+                        2 | 3 => {
+                            for row in 0..rows {
+                                screen.erase(row, 0..cols);
+                            }
+                        }
+                        _ => {}
+                    },
+                    // EL
+                    (0, 'K') => match csi.param(0) {
+                        0 => screen.erase(row, col..cols),
+                        1 => screen.erase(row, 0..col + 1),
+                        2 => screen.erase(row, 0..cols),
+                        _ => {}
+                    },
+                    // ECH
+                    (0, 'X') => {
+                        let end = (col as u16 + n as u16).min(cols as u16) as u8;
+                        screen.erase(row, col..end);
+                    }
+                    // IL
+                    (0, 'L') => {
+                        if region.contains(&row) {
+                            screen.scroll_region_down(row..region.end, n);
+                        }
+                    }
+                    // DL
+                    (0, 'M') => {
+                        if region.contains(&row) {
+                            screen.scroll_region_up(row..region.end, n);
+                        }
+                    }
+                    // SU
+                    (0, 'S') => screen.scroll_region_up(region, n),
+                    // SD
+                    (0, 'T') => screen.scroll_region_down(region, n),
+                    // SGR
+                    (0, 'm') => Self::select_graphic_rendition(screen, &csi),
+                    // DECSTBM
+                    (0, 'r') => {
+                        let top = csi.param_or(0, 1) - 1;
+                        let bottom = csi.param_or(1, rows as u16).min(rows);
+                        screen.ansi_state().set_scroll_region(top, bottom, rows);
+                        Self::move_to(screen, 0, 0);
+                    }
+                    (0, 's') => Self::save_cursor(screen),
+                    (0, 'u') => Self::restore_cursor(screen),
+                    // DECTCEM
+                    (b'?', 'h') | (b'?', 'l') => {
+                        if csi.params().contains(&25) {
+                            *cursor_visible = csi.final_byte == 'h';
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn clamp(value: u16, limit: u8) -> u8 {
+        value.min(limit.saturating_sub(1) as u16) as u8
+    }
+
+    #[inline]
+    fn move_to<T: AnsiScreen + ?Sized>(screen: &mut T, col: u8, row: u8) {
+        let mode = screen.mode_mut();
+        mode.cursor_column = col;
+        mode.cursor_row = row;
+    }
+
+    fn save_cursor<T: AnsiScreen + ?Sized>(screen: &mut T) {
+        let mode = screen.mode_mut();
+        let saved = (mode.cursor_column, mode.cursor_row, mode.attribute);
+        screen.ansi_state().saved_cursor = Some(saved);
+    }
+
+    fn restore_cursor<T: AnsiScreen + ?Sized>(screen: &mut T) {
+        let (col, row, attribute) = screen.ansi_state().saved_cursor.unwrap_or((0, 0, 0));
+        let mode = screen.mode_mut();
+        let col = col.min(mode.columns);
+        let row = row.min(mode.rows - 1);
+        Self::move_to(screen, col, row);
+        screen.set_attribute(attribute);
+    }
+
+    fn select_graphic_rendition<T: AnsiScreen + ?Sized>(screen: &mut T, csi: &CsiSequence) {
+        let mut attribute = match screen.mode_mut().attribute {
+            0 => System::DEFAULT_STDOUT_ATTRIBUTE,
+            v => v,
+        };
+        let mut is_reversed = screen.ansi_state().is_reversed;
+        if is_reversed {
+            attribute = attribute.rotate_left(4);
+        }
+
+        let mut params = csi.params().iter().copied();
+        if csi.params().is_empty() {
+            attribute = System::DEFAULT_STDOUT_ATTRIBUTE;
+            is_reversed = false;
+        }
+        while let Some(param) = params.next() {
+            match param {
+                0 => {
+                    attribute = System::DEFAULT_STDOUT_ATTRIBUTE;
+                    is_reversed = false;
+                }
+                1 => attribute |= 0x08,
+                22 => attribute &= !0x08,
+                7 => is_reversed = true,
+                27 => is_reversed = false,
+                30..=37 => attribute = (attribute & 0xF8) | COLOR_TABLE[param as usize - 30],
+                39 => attribute = (attribute & 0xF0) | (System::DEFAULT_STDOUT_ATTRIBUTE & 0x0F),
+                40..=47 => attribute = (attribute & 0x0F) | COLOR_TABLE[param as usize - 40] << 4,
+                49 => attribute = (attribute & 0x0F) | (System::DEFAULT_STDOUT_ATTRIBUTE & 0xF0),
+                90..=97 => attribute = (attribute & 0xF0) | COLOR_TABLE[param as usize - 90] | 0x08,
+                100..=107 => {
+                    attribute = (attribute & 0x0F) | (COLOR_TABLE[param as usize - 100] | 0x08) << 4
+                }
+                38 | 48 => {
+                    // 256 colors (5;n) or true color (2;r;g;b)
+                    let color = match params.next() {
+                        Some(5) => params
+                            .next()
+                            .filter(|&v| v < 16)
+                            .map(|v| COLOR_TABLE[v as usize & 7] | if v >= 8 { 0x08 } else { 0 }),
+                        Some(2) => {
+                            params.nth(2);
+                            None
+                        }
+                        _ => None,
+                    };
+                    if let Some(color) = color {
+                        attribute = if param == 38 {
+                            (attribute & 0xF0) | color
+                        } else {
+                            (attribute & 0x0F) | color << 4
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if is_reversed {
+            attribute = attribute.rotate_left(4);
+        }
+        screen.ansi_state().is_reversed = is_reversed;
+        screen.set_attribute(attribute);
+    }
+}
+
+/// Escape sequence parser
+pub struct AnsiParser {
+    state: ParserState,
+    params: [u16; MAX_PARAMS],
+    len: usize,
+    private: u8,
+    has_intermediate: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParserState {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    Csi,
+}
+
+/// A complete sequence recognized by the [`AnsiParser`]
+#[derive(Debug, Clone, Copy)]
+pub enum Sequence {
+    /// A control character received in the middle of a sequence, to be executed as is
+    Control,
+    /// `ESC` followed by a final character
+    Esc(char),
+    /// Control Sequence Introducer
+    Csi(CsiSequence),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CsiSequence {
+    params: [u16; MAX_PARAMS],
+    len: usize,
+    /// Private parameter marker such as `?`, or zero
+    pub private: u8,
+    pub final_byte: char,
+}
+
+impl CsiSequence {
+    #[inline]
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.len]
+    }
+
+    /// Returns the parameter, or zero if omitted
+    #[inline]
+    pub fn param(&self, index: usize) -> u16 {
+        self.params().get(index).copied().unwrap_or(0)
+    }
+
+    /// Returns the parameter, or the default value if omitted or zero
+    #[inline]
+    pub fn param_or(&self, index: usize, default: u16) -> u8 {
+        match self.param(index) {
+            0 => default,
+            v => v,
+        }
+        .min(u8::MAX as u16) as u8
+    }
+}
+
+impl AnsiParser {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            state: ParserState::Ground,
+            params: [0; MAX_PARAMS],
+            len: 0,
+            private: 0,
+            has_intermediate: false,
+        }
+    }
+
+    /// Returns whether the parser is outside of any escape sequence
+    #[inline]
+    pub fn is_ground(&self) -> bool {
+        self.state == ParserState::Ground
+    }
+
+    /// Feeds a character, returns a sequence when it is complete
+    pub fn advance(&mut self, ch: char) -> Option<Sequence> {
+        match ch {
+            '\x1b' => {
+                self.state = ParserState::Escape;
+                return None;
+            }
+            // CAN, SUB
+            '\x18' | '\x1a' => {
+                self.state = ParserState::Ground;
+                return None;
+            }
+            '\0'..='\x1f' => {
+                return (!self.is_ground()).then_some(Sequence::Control);
+            }
+            _ => {}
+        }
+
+        match self.state {
+            ParserState::Ground => None,
+            ParserState::Escape => match ch {
+                '[' => {
+                    self.params = [0; MAX_PARAMS];
+                    self.len = 0;
+                    self.private = 0;
+                    self.has_intermediate = false;
+                    self.state = ParserState::Csi;
+                    None
+                }
+                ' '..='/' => {
+                    self.state = ParserState::EscapeIntermediate;
+                    None
+                }
+                _ => {
+                    self.state = ParserState::Ground;
+                    Some(Sequence::Esc(ch))
+                }
+            },
+            ParserState::EscapeIntermediate => {
+                if !matches!(ch, ' '..='/') {
+                    // designations of character sets are not supported
+                    self.state = ParserState::Ground;
+                }
+                None
+            }
+            ParserState::Csi => match ch {
+                '0'..='9' => {
+                    if self.len == 0 {
+                        self.len = 1;
+                    }
+                    if let Some(param) = self.params.get_mut(self.len - 1) {
+                        *param = param
+                            .saturating_mul(10)
+                            .saturating_add(ch as u16 - '0' as u16);
+                    }
+                    None
+                }
+                ';' | ':' => {
+                    self.len = (self.len.max(1) + 1).min(MAX_PARAMS + 1);
+                    None
+                }
+                '<'..='?' => {
+                    if self.len == 0 && self.private == 0 {
+                        self.private = ch as u8;
+                    }
+                    None
+                }
+                ' '..='/' => {
+                    self.has_intermediate = true;
+                    None
+                }
+                '@'..='~' => {
+                    self.state = ParserState::Ground;
+                    if self.has_intermediate {
+                        return None;
+                    }
+                    Some(Sequence::Csi(CsiSequence {
+                        params: self.params,
+                        len: self.len.min(MAX_PARAMS),
+                        private: self.private,
+                        final_byte: ch,
+                    }))
+                }
+                _ => {
+                    self.state = ParserState::Ground;
+                    None
+                }
+            },
+        }
+    }
+}
+
+impl Default for AnsiParser {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for AnsiState {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,395 @@
+//! Line Editor
+
+use super::*;
+use crate::System;
+use alloc::collections::VecDeque;
+
+/// Supplies candidates for tab completion
+pub trait Completer {
+    /// Returns the candidates that replace the last word of `line`, the text before the cursor
+    fn complete(&mut self, line: &str) -> Vec<String>;
+}
+
+impl<F: FnMut(&str) -> Vec<String>> Completer for F {
+    #[inline]
+    fn complete(&mut self, line: &str) -> Vec<String> {
+        self(line)
+    }
+}
+
+/// Line editor with a history ring
+pub struct LineEditor {
+    history: VecDeque<String>,
+    history_limit: usize,
+    is_overwrite: bool,
+}
+
+impl LineEditor {
+    /// Default number of lines kept in the history
+    pub const DEFAULT_HISTORY_LIMIT: usize = 32;
+
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            history: VecDeque::new(),
+            history_limit: Self::DEFAULT_HISTORY_LIMIT,
+            is_overwrite: false,
+        }
+    }
+
+    /// Sets the number of lines kept in the history, zero disables it
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        while self.history.len() > limit {
+            self.history.pop_front();
+        }
+    }
+
+    #[inline]
+    pub fn history(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.history.iter().map(|v| v.as_str())
+    }
+
+    #[inline]
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Reads a line from the standard input, returns `None` if Ctrl-C is pressed
+    ///
+    /// The prompt is redrawn after the completion candidates are listed.
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        max_len: usize,
+        mut completer: Option<&mut dyn Completer>,
+    ) -> Option<String> {
+        let stdin = System::stdin();
+        let stdout = System::stdout();
+
+        let _ = stdout.write_str(prompt);
+        let mode = stdout.current_mode();
+        let mut line = EditLine {
+            stdout,
+            buf: Vec::new(),
+            cursor: 0,
+            offset: mode.cursor_column as usize,
+            columns: mode.columns as usize,
+        };
+        let mut history_index = None;
+        let mut editing_line = Vec::new();
+        let mut high_surrogate = None;
+
+        loop {
+            line.stdout.enable_cursor(true);
+            let key = stdin
+                .read_key_stroke()
+                .and_then(|key| System::conctl().handle_key(key, stdin.modifiers()));
+            let Some(key) = key else {
+                Hal::cpu().wait_for_interrupt();
+                continue;
+            };
+            line.stdout.enable_cursor(false);
+            let key = key.get();
+
+            if key.unicode_char == 0 {
+                match key.scan_code {
+                    InputKey::SCAN_LEFT => line.move_to(line.cursor.saturating_sub(1)),
+                    InputKey::SCAN_RIGHT => line.move_to(line.cursor + 1),
+                    InputKey::SCAN_HOME => line.move_to(0),
+                    InputKey::SCAN_END => line.move_to(line.buf.len()),
+                    InputKey::SCAN_DELETE => line.delete(line.cursor, line.cursor + 1),
+                    InputKey::SCAN_INSERT => self.is_overwrite = !self.is_overwrite,
+                    InputKey::SCAN_UP => {
+                        self.recall(&mut line, &mut history_index, &mut editing_line, true)
+                    }
+                    InputKey::SCAN_DOWN => {
+                        self.recall(&mut line, &mut history_index, &mut editing_line, false)
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            let ch = match (high_surrogate.take(), key.unicode_char) {
+                (None, 0xD800..=0xDBFF) => {
+                    high_surrogate = Some(key.unicode_char);
+                    continue;
+                }
+                (Some(high), low @ 0xDC00..=0xDFFF) => {
+                    char::decode_utf16([high, low]).next().and_then(|v| v.ok())
+                }
+                (_, unit) => char::from_u32(unit as u32),
+            }
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+
+            match ch {
+                // ctrl-c
+                '\x03' => return None,
+                // enter
+                '\x0a' | '\x0d' => break,
+                // ctrl-a
+                '\x01' => line.move_to(0),
+                // ctrl-b
+                '\x02' => line.move_to(line.cursor.saturating_sub(1)),
+                // ctrl-d
+                '\x04' => line.delete(line.cursor, line.cursor + 1),
+                // ctrl-e
+                '\x05' => line.move_to(line.buf.len()),
+                // ctrl-f
+                '\x06' => line.move_to(line.cursor + 1),
+                // backspace
+                '\x08' | '\x7f' => {
+                    if line.cursor > 0 {
+                        line.delete(line.cursor - 1, line.cursor);
+                    }
+                }
+                // tab
+                '\x09' => {
+                    if let Some(completer) = completer.as_deref_mut() {
+                        line.complete(prompt, max_len, completer);
+                    }
+                }
+                // ctrl-k
+                '\x0b' => line.delete(line.cursor, line.buf.len()),
+                // ctrl-n
+                '\x0e' => self.recall(&mut line, &mut history_index, &mut editing_line, false),
+                // ctrl-p
+                '\x10' => self.recall(&mut line, &mut history_index, &mut editing_line, true),
+                // ctrl-u
+                '\x15' => line.delete(0, line.cursor),
+                // ctrl-w
+                '\x17' => {
+                    let end = line.cursor;
+                    let mut start = end;
+                    while start > 0 && line.buf[start - 1] == ' ' {
+                        start -= 1;
+                    }
+                    while start > 0 && line.buf[start - 1] != ' ' {
+                        start -= 1;
+                    }
+                    line.delete(start, end);
+                }
+                // esc
+                '\x1b' => line.replace(0, line.buf.len(), &[], 0),
+                _ => {
+                    let end = if self.is_overwrite && line.cursor < line.buf.len() {
+                        line.cursor + 1
+                    } else if line.buf.len() < max_len {
+                        line.cursor
+                    } else {
+                        continue;
+                    };
+                    line.replace(line.cursor, end, &[ch], line.cursor + 1);
+                }
+            }
+        }
+
+        line.move_to(line.buf.len());
+        let _ = line.stdout.write_str("\r\n");
+
+        let result: String = line.buf.into_iter().collect();
+        if self.history_limit > 0
+            && !result.trim().is_empty()
+            && self.history.back() != Some(&result)
+        {
+            if self.history.len() >= self.history_limit {
+                self.history.pop_front();
+            }
+            self.history.push_back(result.clone());
+        }
+        Some(result)
+    }
+
+    /// Replaces the line with an older (`backward`) or newer entry of the history
+    fn recall(
+        &self,
+        line: &mut EditLine<'_>,
+        history_index: &mut Option<usize>,
+        editing_line: &mut Vec<char>,
+        backward: bool,
+    ) {
+        let new_index = match (*history_index, backward) {
+            (None, true) => self.history.len().checked_sub(1),
+            (None, false) => return,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) => (index + 1 < self.history.len()).then_some(index + 1),
+        };
+        if history_index.is_none() {
+            *editing_line = line.buf.clone();
+        }
+        let new_line = match new_index {
+            Some(index) => self.history[index].chars().collect(),
+            None if history_index.is_some() => core::mem::take(editing_line),
+            None => return,
+        };
+        *history_index = new_index;
+        let len = new_line.len();
+        line.replace(0, line.buf.len(), &new_line, len);
+    }
+}
+
+impl Default for LineEditor {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The line being edited and its presentation
+///
+/// The cursor is moved with backspaces so that it works on any [`SimpleTextOutput`],
+/// except when it has to go back across a line wrap.
+struct EditLine<'a> {
+    stdout: &'a mut dyn SimpleTextOutput,
+    buf: Vec<char>,
+    cursor: usize,
+    /// Cells from the beginning of the row where the line starts to the cursor
+    offset: usize,
+    columns: usize,
+}
+
+impl EditLine<'_> {
+    /// Returns the number of cells of the character, control characters are shown as `^X`
+    #[inline]
+    fn char_width(ch: char) -> usize {
+        if ch < ' ' { 2 } else { 1 }
+    }
+
+    #[inline]
+    fn width(chars: &[char]) -> usize {
+        chars.iter().map(|&ch| Self::char_width(ch)).sum()
+    }
+
+    fn put_chars(&mut self, chars: &[char]) {
+        for &ch in chars {
+            if ch < ' ' {
+                let _ = self.stdout.write_char('^');
+                let _ = self.stdout.write_char((ch as u8 | 0x40) as char);
+            } else {
+                let _ = self.stdout.write_char(ch);
+            }
+        }
+        self.offset += Self::width(chars);
+    }
+
+    fn put_spaces(&mut self, width: usize) {
+        for _ in 0..width {
+            let _ = self.stdout.write_char(' ');
+        }
+        self.offset += width;
+    }
+
+    fn back(&mut self, width: usize) {
+        let width = width.min(self.offset);
+        let target = self.offset - width;
+        let columns = self.columns;
+        if columns == 0 || (self.offset % columns != 0 && target / columns == self.offset / columns)
+        {
+            for _ in 0..width {
+                let _ = self.stdout.write_char('\x08');
+            }
+        } else if width > 0 {
+            // Backspaces do not go up to the previous row, so the cursor is moved explicitly
+            let mode = self.stdout.current_mode();
+            let mut row = self.offset / columns;
+            if self.offset % columns == 0 && mode.cursor_column as usize == columns - 1 {
+                // The wrap is deferred until the next character is written
+                row -= 1;
+            }
+            let rows_up = row - target / columns;
+            self.stdout.set_cursor_position(
+                (target % columns) as u32,
+                (mode.cursor_row as usize).saturating_sub(rows_up) as u32,
+            );
+        }
+        self.offset = target;
+    }
+
+    fn move_to(&mut self, position: usize) {
+        let position = position.min(self.buf.len());
+        if position < self.cursor {
+            self.back(Self::width(&self.buf[position..self.cursor]));
+        } else if position > self.cursor {
+            let chars = self.buf[self.cursor..position].to_vec();
+            self.put_chars(&chars);
+        }
+        self.cursor = position;
+    }
+
+    #[inline]
+    fn delete(&mut self, start: usize, end: usize) {
+        let end = end.min(self.buf.len());
+        if start < end {
+            self.replace(start, end, &[], start);
+        }
+    }
+
+    /// Replaces `buf[start..end]` with the characters and moves the cursor to the new position
+    fn replace(&mut self, start: usize, end: usize, chars: &[char], new_cursor: usize) {
+        self.move_to(start);
+        let old_width = Self::width(&self.buf[start..]);
+        self.buf.splice(start..end, chars.iter().copied());
+
+        let tail = self.buf[start..].to_vec();
+        self.put_chars(&tail);
+        let new_width = Self::width(&tail);
+        let padding = old_width.saturating_sub(new_width);
+        self.put_spaces(padding);
+
+        let new_cursor = new_cursor.clamp(start, self.buf.len());
+        self.back(padding + Self::width(&self.buf[new_cursor..]));
+        self.cursor = new_cursor;
+    }
+
+    fn redraw(&mut self, prompt: &str) {
+        let _ = self.stdout.write_str(prompt);
+        self.offset = self.stdout.current_mode().cursor_column as usize;
+        let chars = self.buf.clone();
+        self.put_chars(&chars);
+        self.back(Self::width(&self.buf[self.cursor..]));
+    }
+
+    fn complete(&mut self, prompt: &str, max_len: usize, completer: &mut dyn Completer) {
+        let end = self.cursor;
+        let start = self.buf[..end]
+            .iter()
+            .rposition(|&ch| ch == ' ')
+            .map(|v| v + 1)
+            .unwrap_or(0);
+        let line: String = self.buf[..end].iter().collect();
+        let candidates = completer.complete(&line);
+
+        let replacement: Vec<char> = match candidates.as_slice() {
+            [] => return,
+            [candidate] => candidate.chars().chain(Some(' ')).collect(),
+            [first, rest @ ..] => {
+                let mut common: Vec<char> = first.chars().collect();
+                for candidate in rest {
+                    let len = common
+                        .iter()
+                        .zip(candidate.chars())
+                        .take_while(|(a, b)| **a == *b)
+                        .count();
+                    common.truncate(len);
+                }
+                if common.len() <= end - start {
+                    // list the candidates and redraw the line
+                    let _ = self.stdout.write_str("\r\n");
+                    for candidate in candidates.iter() {
+                        let _ = write!(self.stdout, "{}  ", candidate);
+                    }
+                    let _ = self.stdout.write_str("\r\n");
+                    self.redraw(prompt);
+                    return;
+                }
+                common
+            }
+        };
+        if self.buf.len() - (end - start) + replacement.len() > max_len {
+            return;
+        }
+        let new_cursor = start + replacement.len();
+        self.replace(start, end, &replacement, new_cursor);
+    }
+}
@@ -2,13 +2,45 @@
 
 use super::*;
 use crate::System;
-use core::fmt::Write;
+use core::{fmt::Write, time::Duration};
+use libhid::Modifier;
 
 const COLOR_TABLE: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
 
+/// Scan codes of the keys with (none, shift, ctrl, alt) modifiers
+#[rustfmt::skip]
+const MODIFIED_SCAN_CODES: [[u16; 4]; 22] = [
+    [InputKey::SCAN_UP, 0x48, 0x8D, 0x98],
+    [InputKey::SCAN_DOWN, 0x50, 0x91, 0xA0],
+    [InputKey::SCAN_LEFT, 0x4B, 0x73, 0x9B],
+    [InputKey::SCAN_RIGHT, 0x4D, 0x74, 0x9D],
+    [InputKey::SCAN_HOME, 0x47, 0x77, 0x97],
+    [InputKey::SCAN_END, 0x4F, 0x75, 0x9F],
+    [InputKey::SCAN_PAGE_UP, 0x49, 0x84, 0x99],
+    [InputKey::SCAN_PAGE_DOWN, 0x51, 0x76, 0xA1],
+    [InputKey::SCAN_INSERT, 0x52, 0x92, 0xA2],
+    [InputKey::SCAN_DELETE, 0x53, 0x93, 0xA3],
+    [InputKey::SCAN_F1, 0x54, 0x5E, 0x68],
+    [InputKey::SCAN_F2, 0x55, 0x5F, 0x69],
+    [InputKey::SCAN_F3, 0x56, 0x60, 0x6A],
+    [InputKey::SCAN_F4, 0x57, 0x61, 0x6B],
+    [InputKey::SCAN_F5, 0x58, 0x62, 0x6C],
+    [InputKey::SCAN_F6, 0x59, 0x63, 0x6D],
+    [InputKey::SCAN_F7, 0x5A, 0x64, 0x6E],
+    [InputKey::SCAN_F8, 0x5B, 0x65, 0x6F],
+    [InputKey::SCAN_F9, 0x5C, 0x66, 0x70],
+    [InputKey::SCAN_F10, 0x5D, 0x67, 0x71],
+    [InputKey::SCAN_F11, 0x87, 0x89, 0x8B],
+    [InputKey::SCAN_F12, 0x88, 0x8A, 0x8C],
+];
+
 pub struct VT100<'a> {
     inner: VT100Inner<'a>,
     mode: SimpleTextOutputMode,
+    is_responsive: bool,
+    pending_byte: Option<u8>,
+    pending_surrogate: Option<u16>,
+    key_modifier: Modifier,
 }
 
 struct VT100Inner<'a>(&'a mut dyn SerialIo);
@@ -19,9 +51,19 @@ impl<'a> VT100<'a> {
         Self {
             inner: VT100Inner(inner),
             mode: SimpleTextOutputMode::default(),
+            is_responsive: true,
+            pending_byte: None,
+            pending_surrogate: None,
+            key_modifier: Modifier::empty(),
         }
     }
 
+    /// Maximum time to wait for a response to a query
+    pub const RESPONSE_TIMEOUT: Duration = Duration::from_millis(200);
+
+    /// Time to wait for the rest of an escape sequence before taking it as the Esc key
+    pub const ESCAPE_TIMEOUT: Duration = Duration::from_millis(50);
+
     #[inline]
     pub fn wait_response(&mut self, expected: &[u8]) -> Option<u8> {
         while let Some(ch) = self.inner.0.read_byte() {
@@ -32,22 +74,36 @@ impl<'a> VT100<'a> {
         None
     }
 
-    pub fn wait_byte(&mut self) -> u8 {
+    /// Waits for a byte until the deadline measured by [`System::monotonic`]
+    pub fn wait_byte(&mut self, deadline: Duration) -> Option<u8> {
         loop {
             if let Some(ch) = self.inner.0.read_byte() {
-                return ch;
+                return Some(ch);
+            }
+            if System::monotonic() >= deadline {
+                return None;
             }
+            Hal::cpu().no_op();
         }
     }
 
+    /// Queries the cursor position with `ESC[6n`
+    ///
+    /// Once the terminal fails to answer, further queries are skipped until [`SimpleTextOutput::reset`].
     pub fn get_cursor_position(&mut self) -> Option<(u8, u8)> {
-        // TODO: timeout
+        if !self.is_responsive {
+            return None;
+        }
         self.inner.0.flush_input();
         let _ = self.inner.write_str("\x1b[6n");
+        let deadline = System::monotonic() + Self::RESPONSE_TIMEOUT;
         let mut buf = [0u8; 16];
         let mut i = 0;
         while i < buf.len() {
-            let b = self.wait_byte();
+            let Some(b) = self.wait_byte(deadline) else {
+                self.is_responsive = false;
+                return None;
+            };
             buf[i] = b;
             i += 1;
             if b == b'R' {
@@ -98,6 +154,7 @@ impl Write for VT100Inner<'_> {
 
 impl SimpleTextOutput for VT100<'_> {
     fn reset(&mut self) {
+        self.is_responsive = true;
         let _ = self.inner.write_str("\x1bc\x1b[255;255H");
         if let Some((col, row)) = self.get_cursor_position() {
             self.mode.columns = col.saturating_add(1);
@@ -170,15 +227,194 @@ impl SimpleTextOutput for VT100<'_> {
     }
 }
 
+impl VT100<'_> {
+    /// Decodes the rest of the sequence following `ESC`
+    fn read_escape_sequence(&mut self) -> Option<NonZeroInputKey> {
+        let deadline = System::monotonic() + Self::ESCAPE_TIMEOUT;
+        let Some(ch) = self.wait_byte(deadline) else {
+            return NonZeroInputKey::new(InputKey::SCAN_ESC, 0x1b);
+        };
+        match ch {
+            b'[' => self.read_csi(deadline),
+            // SS3
+            b'O' => match self.wait_byte(deadline) {
+                Some(ch @ b'P'..=b'S') => {
+                    self.special_key(InputKey::SCAN_F1 + (ch - b'P') as u16, 1)
+                }
+                Some(ch) => self.cursor_key(ch, 1),
+                None => {
+                    self.key_modifier = Modifier::LEFT_ALT;
+                    NonZeroInputKey::new(InputKey::SCAN_CHAR, b'O' as u16)
+                }
+            },
+            0x20..=0x7e => {
+                // Meta sends escape
+                self.key_modifier = Modifier::LEFT_ALT;
+                NonZeroInputKey::new(InputKey::SCAN_CHAR, ch as u16)
+            }
+            _ => {
+                self.pending_byte = Some(ch);
+                NonZeroInputKey::new(InputKey::SCAN_ESC, 0x1b)
+            }
+        }
+    }
+
+    /// Decodes a UTF-8 sequence, characters outside the BMP are returned as surrogate pairs
+    fn read_utf8(&mut self, lead: u8) -> Option<NonZeroInputKey> {
+        let len = match lead {
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => return NonZeroInputKey::new(InputKey::SCAN_CHAR, 0xfffd),
+        };
+        let deadline = System::monotonic() + Self::ESCAPE_TIMEOUT;
+        let mut buf = [lead, 0, 0, 0];
+        for i in 1..len {
+            match self.wait_byte(deadline) {
+                Some(ch @ 0x80..=0xbf) => buf[i] = ch,
+                Some(ch) => {
+                    self.pending_byte = Some(ch);
+                    return NonZeroInputKey::new(InputKey::SCAN_CHAR, 0xfffd);
+                }
+                None => return NonZeroInputKey::new(InputKey::SCAN_CHAR, 0xfffd),
+            }
+        }
+        let ch = core::str::from_utf8(&buf[..len])
+            .ok()
+            .and_then(|v| v.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        let mut units = [0; 2];
+        let units = ch.encode_utf16(&mut units);
+        if let [high, low] = units {
+            self.pending_surrogate = Some(*low);
+            NonZeroInputKey::new(InputKey::SCAN_CHAR, *high)
+        } else {
+            NonZeroInputKey::new(InputKey::SCAN_CHAR, units[0])
+        }
+    }
+
+    /// Decodes `CSI`, such as `ESC [ 1 ; 5 A` or `ESC [ 5 ~`
+    fn read_csi(&mut self, deadline: Duration) -> Option<NonZeroInputKey> {
+        let mut params = [0u16; 2];
+        let mut index = 0;
+        loop {
+            let ch = self.wait_byte(deadline)?;
+            match ch {
+                b'0'..=b'9' => {
+                    if let Some(param) = params.get_mut(index) {
+                        *param = param.saturating_mul(10).saturating_add((ch - b'0') as u16);
+                    }
+                }
+                b';' => index += 1,
+                // Linux console: ESC [ [ A .. ESC [ [ E
+                b'[' if index == 0 && params[0] == 0 => {
+                    return match self.wait_byte(deadline)? {
+                        ch @ b'A'..=b'E' => {
+                            self.special_key(InputKey::SCAN_F1 + (ch - b'A') as u16, 1)
+                        }
+                        _ => None,
+                    };
+                }
+                b'~' => {
+                    let scan_code = match params[0] {
+                        1 | 7 => InputKey::SCAN_HOME,
+                        2 => InputKey::SCAN_INSERT,
+                        3 => InputKey::SCAN_DELETE,
+                        4 | 8 => InputKey::SCAN_END,
+                        5 => InputKey::SCAN_PAGE_UP,
+                        6 => InputKey::SCAN_PAGE_DOWN,
+                        11..=15 => InputKey::SCAN_F1 + params[0] - 11,
+                        17..=21 => InputKey::SCAN_F6 + params[0] - 17,
+                        23 => InputKey::SCAN_F11,
+                        24 => InputKey::SCAN_F12,
+                        _ => return None,
+                    };
+                    return self.special_key(scan_code, params[1]);
+                }
+                b'P'..=b'S' => {
+                    return self.special_key(InputKey::SCAN_F1 + (ch - b'P') as u16, params[1]);
+                }
+                // Shift+Tab
+                b'Z' => {
+                    self.key_modifier = Modifier::LEFT_SHIFT;
+                    return NonZeroInputKey::new(InputKey::SCAN_TAB, 0);
+                }
+                0x40..=0x7e => return self.cursor_key(ch, params[1]),
+                _ => {}
+            }
+        }
+    }
+
+    fn cursor_key(&mut self, final_byte: u8, modifier_param: u16) -> Option<NonZeroInputKey> {
+        let scan_code = match final_byte {
+            b'A' => InputKey::SCAN_UP,
+            b'B' => InputKey::SCAN_DOWN,
+            b'C' => InputKey::SCAN_RIGHT,
+            b'D' => InputKey::SCAN_LEFT,
+            b'H' => InputKey::SCAN_HOME,
+            b'F' => InputKey::SCAN_END,
+            _ => return None,
+        };
+        self.special_key(scan_code, modifier_param)
+    }
+
+    /// Makes a key without a character from the unmodified scan code and the xterm modifier parameter
+    fn special_key(&mut self, scan_code: u16, modifier_param: u16) -> Option<NonZeroInputKey> {
+        // xterm encodes the modifiers as 1 + (shift | alt << 1 | ctrl << 2 | meta << 3)
+        let bits = modifier_param.saturating_sub(1);
+        let mut modifier = Modifier::empty();
+        modifier.set(Modifier::LEFT_SHIFT, (bits & 1) != 0);
+        modifier.set(Modifier::LEFT_ALT, (bits & 0x0A) != 0);
+        modifier.set(Modifier::LEFT_CTRL, (bits & 4) != 0);
+        self.key_modifier = modifier;
+
+        let variant = if modifier.has_ctrl() {
+            2
+        } else if modifier.has_alt() {
+            3
+        } else if modifier.has_shift() {
+            1
+        } else {
+            0
+        };
+        let scan_code = MODIFIED_SCAN_CODES
+            .iter()
+            .find(|v| v[0] == scan_code)
+            .map(|v| v[variant])
+            .unwrap_or(scan_code);
+        NonZeroInputKey::new(scan_code, 0)
+    }
+}
+
 impl SimpleTextInput for VT100<'_> {
     fn reset(&mut self) {
+        self.pending_byte = None;
+        self.pending_surrogate = None;
+        self.key_modifier = Modifier::empty();
         self.inner.0.reset();
     }
 
     fn read_key_stroke(&mut self) -> Option<NonZeroInputKey> {
-        match self.inner.0.read_byte() {
-            Some(ch) => NonZeroInputKey::new(0xffff, ch as u16),
-            None => None,
+        if let Some(low) = self.pending_surrogate.take() {
+            return NonZeroInputKey::new(InputKey::SCAN_CHAR, low);
         }
+        let ch = self
+            .pending_byte
+            .take()
+            .or_else(|| self.inner.0.read_byte())?;
+        self.key_modifier = Modifier::empty();
+        match ch {
+            0x1b => self.read_escape_sequence(),
+            b'\r' => NonZeroInputKey::new(InputKey::SCAN_ENTER, ch as u16),
+            b'\t' => NonZeroInputKey::new(InputKey::SCAN_TAB, ch as u16),
+            0x08 | 0x7f => NonZeroInputKey::new(InputKey::SCAN_BACKSPACE, 0x08),
+            0x80..=0xff => self.read_utf8(ch),
+            _ => NonZeroInputKey::new(InputKey::SCAN_CHAR, ch as u16),
+        }
+    }
+
+    #[inline]
+    fn modifiers(&mut self) -> Modifier {
+        self.key_modifier
     }
 }
@@ -8,7 +8,9 @@ extern crate alloc;
 
 pub mod arch;
 pub mod env;
+pub mod fs;
 pub mod io;
+pub mod loader;
 pub mod mem;
 pub mod platform;
 pub mod sync;
@@ -0,0 +1,368 @@
+//! Linux bzImage Loader
+//!
+//! Loads the x86 Linux kernel and enters its 32-bit entry point with `boot_params`,
+//! as described in `Documentation/arch/x86/boot.rst`.
+
+use super::*;
+use crate::arch::gdt::KERNEL_DSEL;
+use crate::io::graphics::PixelFormat;
+use crate::platform::{Platform, x86_pc::ibm_pc};
+use core::arch::asm;
+
+/// Setup header at the offset 0x1F1 of the image, and of `boot_params` as well
+#[derive(Debug, Clone, Copy)]
+pub struct SetupHeader {
+    pub setup_sects: u8,
+    pub version: u16,
+    pub loadflags: u8,
+    pub initrd_addr_max: u32,
+    pub kernel_alignment: u32,
+    pub relocatable_kernel: bool,
+    pub cmdline_size: u32,
+    pub pref_address: u64,
+    pub init_size: u32,
+}
+
+impl SetupHeader {
+    pub const OFFSET: usize = 0x1f1;
+
+    pub const BOOT_FLAG: u16 = 0xAA55;
+
+    /// `HdrS`
+    pub const MAGIC: u32 = 0x5372_6448;
+
+    /// The oldest protocol version supported, which has `pref_address` and `init_size`
+    pub const MIN_VERSION: u16 = 0x020a;
+
+    /// `loadflags`: the protected-mode code is loaded at 0x100000
+    pub const LOADED_HIGH: u8 = 0x01;
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        let bytes = bytes.get(..0x268).ok_or(LoadError::BadFormat)?;
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+
+        if u16_at(0x1fe) != Self::BOOT_FLAG || u32_at(0x202) != Self::MAGIC {
+            return Err(LoadError::BadFormat);
+        }
+        let header = Self {
+            setup_sects: match bytes[0x1f1] {
+                0 => 4,
+                v => v,
+            },
+            version: u16_at(0x206),
+            loadflags: bytes[0x211],
+            initrd_addr_max: u32_at(0x22c),
+            kernel_alignment: u32_at(0x230),
+            relocatable_kernel: bytes[0x234] != 0,
+            cmdline_size: u32_at(0x238),
+            pref_address: u64_at(0x258),
+            init_size: u32_at(0x260),
+        };
+        if header.version < Self::MIN_VERSION || (header.loadflags & Self::LOADED_HIGH) == 0 {
+            return Err(LoadError::NotSupported);
+        }
+        Ok(header)
+    }
+
+    /// Returns the offset of the protected-mode code in the image
+    #[inline]
+    pub const fn pm_offset(&self) -> usize {
+        (self.setup_sects as usize + 1) * 512
+    }
+}
+
+/// Linux bzImage to be loaded
+pub struct BzImage<'a> {
+    header: SetupHeader,
+    blob: &'a [u8],
+}
+
+impl<'a> BzImage<'a> {
+    /// Parses and validates the setup header of the image
+    pub fn parse(blob: &'a [u8]) -> Result<Self, LoadError> {
+        let header = SetupHeader::from_bytes(blob)?;
+        if blob.len() <= header.pm_offset()
+            || !header.kernel_alignment.is_power_of_two()
+            || header.pref_address > u32::MAX as u64
+        {
+            return Err(LoadError::InvalidData);
+        }
+        Ok(Self { header, blob })
+    }
+
+    #[inline]
+    pub const fn header(&self) -> &SetupHeader {
+        &self.header
+    }
+
+    /// Places the protected-mode kernel, the initial ramdisk and `boot_params` with the command line
+    pub fn load(&self, cmdline: &str, initrd: Option<&[u8]>) -> Result<BzImageBoot, LoadError> {
+        if !matches!(System::platform(), Platform::PcBios) {
+            return Err(LoadError::NotSupported);
+        }
+        if cmdline.len() > self.header.cmdline_size as usize {
+            return Err(LoadError::InvalidData);
+        }
+
+        let image = self.place_kernel()?;
+        let initrd = match initrd.map(|blob| self.place_initrd(blob)).transpose() {
+            Ok(initrd) => initrd,
+            Err(err) => {
+                image.free();
+                return Err(err);
+            }
+        };
+        match self.place_boot_params(&image, cmdline, initrd.as_ref()) {
+            Ok(boot_params) => Ok(BzImageBoot {
+                image,
+                boot_params,
+                initrd,
+            }),
+            Err(err) => {
+                image.free();
+                if let Some(initrd) = initrd {
+                    initrd.free();
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn place_kernel(&self) -> Result<LoadedImage, LoadError> {
+        let pm_code = &self.blob[self.header.pm_offset()..];
+        let size = pm_code.len().max(self.header.init_size as usize);
+        let align = (self.header.kernel_alignment as usize).max(MemoryManager::PAGE_SIZE as usize);
+        let pref_address = self.header.pref_address as usize;
+
+        let mut image = match LoadedImage::alloc(Some(pref_address), size, align) {
+            Ok(image) => image,
+            Err(_) if self.header.relocatable_kernel => LoadedImage::alloc(None, size, align)?,
+            Err(err) => return Err(err),
+        };
+        image.entry = image.base();
+        image.as_mut_slice()[..pm_code.len()].copy_from_slice(pm_code);
+        Ok(image)
+    }
+
+    fn place_initrd(&self, blob: &[u8]) -> Result<LoadedImage, LoadError> {
+        let limit = self.header.initrd_addr_max as u64 + 1;
+        let mut initrd =
+            LoadedImage::alloc_below(limit, blob.len(), MemoryManager::PAGE_SIZE as usize)?;
+        initrd.as_mut_slice()[..blob.len()].copy_from_slice(blob);
+        Ok(initrd)
+    }
+
+    fn place_boot_params(
+        &self,
+        image: &LoadedImage,
+        cmdline: &str,
+        initrd: Option<&LoadedImage>,
+    ) -> Result<LoadedImage, LoadError> {
+        // The command line follows the zero page
+        let mut boot_params = LoadedImage::alloc(
+            None,
+            BootParams::SIZE + cmdline.len() + 1,
+            MemoryManager::PAGE_SIZE as usize,
+        )?;
+        let p_cmdline = boot_params.base() + BootParams::SIZE;
+        let slice = boot_params.as_mut_slice();
+        slice[BootParams::SIZE..BootParams::SIZE + cmdline.len()]
+            .copy_from_slice(cmdline.as_bytes());
+
+        let mut params = BootParams(&mut slice[..BootParams::SIZE]);
+        let hdr_end = 0x202 + self.blob[0x201] as usize;
+        params.0[SetupHeader::OFFSET..hdr_end]
+            .copy_from_slice(&self.blob[SetupHeader::OFFSET..hdr_end]);
+
+        params.set_u8(BootParams::TYPE_OF_LOADER, BootParams::LOADER_UNDEFINED);
+        params.set_u32(BootParams::CODE32_START, image.base() as u32);
+        params.set_u32(BootParams::CMD_LINE_PTR, p_cmdline as u32);
+        if let Some(initrd) = initrd {
+            params.set_u32(BootParams::RAMDISK_IMAGE, initrd.base() as u32);
+            params.set_u32(BootParams::RAMDISK_SIZE, initrd.size() as u32);
+        }
+        if let Some(rsdp) = System::find_config_table_entry(&acpi::ACPI_20_TABLE_GUID)
+            .or_else(|| System::find_config_table_entry(&acpi::ACPI_10_TABLE_GUID))
+        {
+            params.set_u64(BootParams::ACPI_RSDP_ADDR, rsdp.address.get().as_u64());
+        }
+        params.set_screen_info();
+        params.set_e820_table();
+
+        Ok(boot_params)
+    }
+}
+
+/// Zero page passed to the 32-bit entry point
+struct BootParams<'a>(&'a mut [u8]);
+
+impl BootParams<'_> {
+    const SIZE: usize = 0x1000;
+
+    const ACPI_RSDP_ADDR: usize = 0x070;
+    const E820_ENTRIES: usize = 0x1e8;
+    const TYPE_OF_LOADER: usize = 0x210;
+    const CODE32_START: usize = 0x214;
+    const RAMDISK_IMAGE: usize = 0x218;
+    const RAMDISK_SIZE: usize = 0x21c;
+    const CMD_LINE_PTR: usize = 0x228;
+    const E820_TABLE: usize = 0x2d0;
+    const E820_MAX_ENTRIES: usize = 128;
+    const E820_ENTRY_SIZE: usize = 20;
+
+    const LOADER_UNDEFINED: u8 = 0xff;
+
+    // screen_info
+    const ORIG_VIDEO_MODE: usize = 0x06;
+    const ORIG_VIDEO_COLS: usize = 0x07;
+    const ORIG_VIDEO_LINES: usize = 0x0e;
+    const ORIG_VIDEO_IS_VGA: usize = 0x0f;
+    const ORIG_VIDEO_POINTS: usize = 0x10;
+    const LFB_WIDTH: usize = 0x12;
+    const LFB_HEIGHT: usize = 0x14;
+    const LFB_DEPTH: usize = 0x16;
+    const LFB_BASE: usize = 0x18;
+    const LFB_SIZE: usize = 0x1c;
+    const LFB_LINELENGTH: usize = 0x24;
+    const RED_SIZE: usize = 0x26;
+    const PAGES: usize = 0x32;
+
+    const VIDEO_TYPE_VGA: u8 = 0x01;
+    const VIDEO_TYPE_VLFB: u8 = 0x23;
+
+    #[inline]
+    fn set_u8(&mut self, offset: usize, value: u8) {
+        self.0[offset] = value;
+    }
+
+    #[inline]
+    fn set_u16(&mut self, offset: usize, value: u16) {
+        self.0[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[inline]
+    fn set_u32(&mut self, offset: usize, value: u32) {
+        self.0[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[inline]
+    fn set_u64(&mut self, offset: usize, value: u64) {
+        self.0[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Describes the framebuffer of the current VESA mode, or the VGA text mode
+    fn set_screen_info(&mut self) {
+        let Some(mode) = System::conctl().current_graphics_mode() else {
+            self.set_u8(Self::ORIG_VIDEO_MODE, 0x03);
+            self.set_u8(Self::ORIG_VIDEO_COLS, 80);
+            self.set_u8(Self::ORIG_VIDEO_LINES, 25);
+            self.set_u8(Self::ORIG_VIDEO_IS_VGA, Self::VIDEO_TYPE_VGA);
+            self.set_u16(Self::ORIG_VIDEO_POINTS, 16);
+            return;
+        };
+        // size and position of red, green, blue and reserved
+        let (depth, fields) = match mode.info.pixel_format {
+            PixelFormat::Indexed8 => (8, [0; 8]),
+            PixelFormat::BGRX8888 => (32, [8, 16, 8, 8, 8, 0, 8, 24]),
+            PixelFormat::RGBX8888 => (32, [8, 0, 8, 8, 8, 16, 8, 24]),
+        };
+        self.set_u8(Self::ORIG_VIDEO_IS_VGA, Self::VIDEO_TYPE_VLFB);
+        self.set_u16(Self::LFB_WIDTH, mode.info.width);
+        self.set_u16(Self::LFB_HEIGHT, mode.info.height);
+        self.set_u16(Self::LFB_DEPTH, depth);
+        self.set_u32(Self::LFB_BASE, mode.fb.as_u64() as u32);
+        // in 64KB units for VESA
+        self.set_u32(Self::LFB_SIZE, mode.fb_size.div_ceil(0x1_0000) as u32);
+        self.set_u16(Self::LFB_LINELENGTH, mode.info.bytes_per_scanline);
+        self.0[Self::RED_SIZE..Self::RED_SIZE + 8].copy_from_slice(&fields);
+        self.set_u16(Self::PAGES, 1);
+    }
+
+    /// Copies the memory map of INT 15h AX=E820h, or makes one from the memory manager if not available
+    fn set_e820_table(&mut self) {
+        let mut count = 0;
+        let mut push = |base: u64, size: u64, attr: u32| {
+            if count < Self::E820_MAX_ENTRIES {
+                let offset = Self::E820_TABLE + count * Self::E820_ENTRY_SIZE;
+                self.0[offset..offset + 8].copy_from_slice(&base.to_le_bytes());
+                self.0[offset + 8..offset + 16].copy_from_slice(&size.to_le_bytes());
+                self.0[offset + 16..offset + 20].copy_from_slice(&attr.to_le_bytes());
+                count += 1;
+            }
+        };
+
+        let smap = ibm_pc::smap();
+        if smap.is_empty() {
+            for entry in MemoryManager::memory_list() {
+                let attr = match entry.mem_type {
+                    MemoryType::Available | MemoryType::Used => 1,
+                    MemoryType::AcpiReclaim => 3,
+                    MemoryType::AcpiNvs => 4,
+                    _ => 2,
+                };
+                push(entry.base, entry.size, attr);
+            }
+        } else {
+            for entry in smap {
+                push(entry.base, entry.size, entry.attr);
+            }
+        }
+        self.set_u8(Self::E820_ENTRIES, count as u8);
+    }
+}
+
+/// Kernel ready to be started with `boot_params`
+pub struct BzImageBoot {
+    image: LoadedImage,
+    boot_params: LoadedImage,
+    initrd: Option<LoadedImage>,
+}
+
+impl BzImageBoot {
+    /// Returns the address of `boot_params` passed to the kernel
+    #[inline]
+    pub const fn boot_params(&self) -> usize {
+        self.boot_params.base()
+    }
+
+    /// Returns the initial ramdisk placed for the kernel
+    #[inline]
+    pub const fn initrd(&self) -> Option<&LoadedImage> {
+        self.initrd.as_ref()
+    }
+
+    /// Exits minios and enters the 32-bit entry point of the kernel
+    ///
+    /// The GDT of minios already has the flat `__BOOT_CS` (0x10) and `__BOOT_DS` (0x18) the protocol requires.
+    ///
+    /// # Safety
+    ///
+    /// The image must be a Linux kernel for x86.
+    pub unsafe fn start(self) -> ! {
+        unsafe {
+            System::exit_minios();
+
+            asm!(
+                "cli",
+                "mov ds, eax",
+                "mov es, eax",
+                "mov fs, eax",
+                "mov gs, eax",
+                "mov ss, eax",
+                "mov esi, edx",
+                "xor ebp, ebp",
+                "xor edi, edi",
+                "xor ebx, ebx",
+                "jmp ecx",
+                in("eax") KERNEL_DSEL.as_usize(),
+                in("ecx") self.image.entry(),
+                in("edx") self.boot_params.base(),
+                options(noreturn),
+            );
+        }
+    }
+}
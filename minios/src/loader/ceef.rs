@@ -0,0 +1,216 @@
+//! Compact & Efficient Executable Format
+//!
+//! Kernel images produced by `tools/elf2ceef`.
+//! Version 0 has sections copied to their addresses, version 1 has the whole image compressed with STK1.
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CeefVersion {
+    /// Version 0, segmented, not compressed
+    V0 = 0,
+    /// Version 1, not segmented, compressed
+    V1 = 1,
+}
+
+impl CeefVersion {
+    #[inline]
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::V0),
+            1 => Some(Self::V1),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CeefHeader {
+    pub magic: u16,
+    pub version: CeefVersion,
+    pub n_secs: u8,
+    pub entry: u32,
+    pub base: u32,
+    pub minalloc: u32,
+}
+
+impl CeefHeader {
+    pub const MAGIC: u16 = 0xCEEF;
+
+    pub const SIZE: usize = 16;
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        let bytes = bytes.get(..Self::SIZE).ok_or(LoadError::BadFormat)?;
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let magic = u16::from_le_bytes([bytes[0], bytes[1]]);
+        if magic != Self::MAGIC {
+            return Err(LoadError::BadFormat);
+        }
+        let version = CeefVersion::from_u8(bytes[2]).ok_or(LoadError::NotSupported)?;
+        Ok(Self {
+            magic,
+            version,
+            n_secs: bytes[3],
+            entry: u32_at(4),
+            base: u32_at(8),
+            minalloc: u32_at(12),
+        })
+    }
+
+    #[inline]
+    pub const fn n_secs(&self) -> usize {
+        self.n_secs as usize
+    }
+
+    #[inline]
+    pub const fn size_of_headers(&self) -> usize {
+        Self::SIZE + self.n_secs() * CeefSecHeader::SIZE
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CeefSecHeader {
+    pub attr: u8,
+    pub filesz: u32,
+    pub vaddr: u32,
+    pub memsz: u32,
+}
+
+impl CeefSecHeader {
+    pub const SIZE: usize = 16;
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        let bytes = bytes.get(..Self::SIZE).ok_or(LoadError::InvalidData)?;
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        Ok(Self {
+            attr: bytes[0],
+            filesz: u32_at(4),
+            vaddr: u32_at(8),
+            memsz: u32_at(12),
+        })
+    }
+
+    /// Returns the protection attributes of the section, the same as `p_flags` of ELF
+    #[inline]
+    pub const fn attr(&self) -> usize {
+        (self.attr >> 5) as usize
+    }
+
+    /// Returns the alignment of the section in log2
+    #[inline]
+    pub const fn align(&self) -> usize {
+        (self.attr & 31) as usize
+    }
+}
+
+/// CEEF image to be loaded
+pub struct CeefImage<'a> {
+    header: CeefHeader,
+    sections: Vec<CeefSecHeader>,
+    payload: &'a [u8],
+}
+
+impl<'a> CeefImage<'a> {
+    /// Parses and validates the headers of the image
+    pub fn parse(blob: &'a [u8]) -> Result<Self, LoadError> {
+        let header = CeefHeader::from_bytes(blob)?;
+        let payload = blob
+            .get(header.size_of_headers()..)
+            .ok_or(LoadError::InvalidData)?;
+        let sections = (0..header.n_secs())
+            .map(|index| {
+                CeefSecHeader::from_bytes(&blob[CeefHeader::SIZE + index * CeefSecHeader::SIZE..])
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let base = header.base as u64;
+        let end = base + header.minalloc as u64;
+        if header.minalloc == 0
+            || !(base..end).contains(&(header.entry as u64))
+            || end > usize::MAX as u64
+        {
+            return Err(LoadError::InvalidData);
+        }
+        let mut filesz = 0u64;
+        for section in &sections {
+            let vaddr = section.vaddr as u64;
+            if section.filesz > section.memsz || vaddr < base || vaddr + section.memsz as u64 > end
+            {
+                return Err(LoadError::InvalidData);
+            }
+            filesz += section.filesz as u64;
+        }
+        if filesz > payload.len() as u64 {
+            return Err(LoadError::InvalidData);
+        }
+
+        Ok(Self {
+            header,
+            sections,
+            payload,
+        })
+    }
+
+    #[inline]
+    pub const fn header(&self) -> &CeefHeader {
+        &self.header
+    }
+
+    #[inline]
+    pub fn sections(&self) -> &[CeefSecHeader] {
+        &self.sections
+    }
+
+    /// Places the image at its base address and returns it ready to be started
+    pub fn load(&self) -> Result<LoadedImage, LoadError> {
+        // The image has a fixed base address, so the alignment of the sections is not enforced
+        let align = MemoryManager::PAGE_SIZE as usize;
+        if self.header.base as usize & (align - 1) != 0 {
+            return Err(LoadError::InvalidData);
+        }
+        let mut image = LoadedImage::alloc(
+            Some(self.header.base as usize),
+            self.header.minalloc as usize,
+            align,
+        )?;
+        image.entry = self.header.entry as usize;
+
+        match self.place(&mut image) {
+            Ok(_) => Ok(image),
+            Err(err) => {
+                image.free();
+                Err(err)
+            }
+        }
+    }
+
+    fn place(&self, image: &mut LoadedImage) -> Result<(), LoadError> {
+        match self.header.version {
+            CeefVersion::V0 => {
+                let mut offset = 0;
+                for section in &self.sections {
+                    let filesz = section.filesz as usize;
+                    let memsz = section.memsz as usize;
+                    let src = &self.payload[offset..offset + filesz];
+                    let dest = image.slice_at(section.vaddr as usize, memsz)?;
+                    dest[..filesz].copy_from_slice(src);
+                    // BSS
+                    dest[filesz..].fill(0);
+                    offset += filesz;
+                }
+            }
+            CeefVersion::V1 => {
+                let (size, stream) = stk1::header(self.payload).ok_or(LoadError::InvalidData)?;
+                let base = image.base();
+                let dest = image.slice_at(base, size)?;
+                stk1::decode(stream, dest).ok_or(LoadError::InvalidData)?;
+                // BSS
+                let minalloc = self.header.minalloc as usize;
+                image.slice_at(base + size, minalloc - size)?.fill(0);
+            }
+        }
+        Ok(())
+    }
+}
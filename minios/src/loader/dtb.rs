@@ -0,0 +1,140 @@
+//! Device Tree Blob Rewriter
+//!
+//! The device tree parser is read-only, so the blob is rebuilt token by token with the changes applied.
+
+use crate::*;
+use fdt::{DeviceTree, Header, NodeName, Token};
+
+/// Size of the blob header of version 17
+const HEADER_SIZE: usize = 40;
+
+/// Builds a copy of the device tree with the properties of `/chosen` replaced
+///
+/// `/chosen` is added to the root node if it does not exist.
+pub fn patch_chosen(header: &Header, props: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut strings = Vec::new();
+    let mut dt_struct = Vec::new();
+    let mut depth = 0usize;
+    let mut in_chosen = false;
+    let mut has_chosen = false;
+    let mut is_patched = false;
+
+    for token in header.tokens() {
+        match token {
+            Token::BeginNode(name) => {
+                depth += 1;
+                if depth == 2 && name.0 == NodeName::CHOSEN.0 {
+                    in_chosen = true;
+                    has_chosen = true;
+                } else if in_chosen && depth == 3 && !is_patched {
+                    // Properties must precede the subnodes
+                    for (key, value) in props {
+                        push_prop(&mut dt_struct, &mut strings, key, value);
+                    }
+                    is_patched = true;
+                }
+                push_begin_node(&mut dt_struct, name.0);
+            }
+            Token::Prop(name, ptr, len) => {
+                if in_chosen && depth == 2 && props.iter().any(|(key, _)| *key == name.0) {
+                    continue;
+                }
+                let value = unsafe { core::slice::from_raw_parts(ptr as *const u8, len) };
+                push_prop(&mut dt_struct, &mut strings, name.0, value);
+            }
+            Token::EndNode => {
+                if in_chosen && depth == 2 {
+                    if !is_patched {
+                        for (key, value) in props {
+                            push_prop(&mut dt_struct, &mut strings, key, value);
+                        }
+                        is_patched = true;
+                    }
+                    in_chosen = false;
+                } else if depth == 1 && !has_chosen {
+                    push_begin_node(&mut dt_struct, NodeName::CHOSEN.0);
+                    for (key, value) in props {
+                        push_prop(&mut dt_struct, &mut strings, key, value);
+                    }
+                    push_u32(&mut dt_struct, DeviceTree::FDT_END_NODE);
+                }
+                push_u32(&mut dt_struct, DeviceTree::FDT_END_NODE);
+                depth = depth.saturating_sub(1);
+            }
+        }
+    }
+    push_u32(&mut dt_struct, DeviceTree::FDT_END);
+
+    let mut rsvmap = Vec::new();
+    for (base, size) in header.reserved_maps().chain([(0, 0)]) {
+        rsvmap.extend_from_slice(&base.to_be_bytes());
+        rsvmap.extend_from_slice(&size.to_be_bytes());
+    }
+
+    let off_mem_rsvmap = HEADER_SIZE;
+    let off_dt_struct = off_mem_rsvmap + rsvmap.len();
+    let off_dt_strings = off_dt_struct + dt_struct.len();
+    let total_size = off_dt_strings + strings.len();
+
+    let mut blob = Vec::with_capacity(total_size);
+    // Keeps `boot_cpuid_phys` of the original header
+    blob.extend_from_slice(unsafe {
+        core::slice::from_raw_parts(header.as_ptr() as *const u8, HEADER_SIZE)
+    });
+    for (offset, value) in [
+        (4, total_size as u32),
+        (8, off_dt_struct as u32),
+        (12, off_dt_strings as u32),
+        (16, off_mem_rsvmap as u32),
+        (20, Header::CURRENT_VERSION),
+        (24, Header::COMPATIBLE_VERSION),
+        (32, strings.len() as u32),
+        (36, dt_struct.len() as u32),
+    ] {
+        blob[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+    blob.extend_from_slice(&rsvmap);
+    blob.extend_from_slice(&dt_struct);
+    blob.extend_from_slice(&strings);
+    blob
+}
+
+#[inline]
+fn push_u32(dt_struct: &mut Vec<u8>, value: u32) {
+    dt_struct.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Appends the bytes padded with zeros to the 4 byte boundary
+fn push_padded(dt_struct: &mut Vec<u8>, bytes: &[u8]) {
+    dt_struct.extend_from_slice(bytes);
+    dt_struct.resize(dt_struct.len().next_multiple_of(4), 0);
+}
+
+fn push_begin_node(dt_struct: &mut Vec<u8>, name: &str) {
+    push_u32(dt_struct, DeviceTree::FDT_BEGIN_NODE);
+    dt_struct.extend_from_slice(name.as_bytes());
+    push_padded(dt_struct, &[0]);
+}
+
+fn push_prop(dt_struct: &mut Vec<u8>, strings: &mut Vec<u8>, name: &str, value: &[u8]) {
+    let name_offset = string_offset(strings, name);
+    push_u32(dt_struct, DeviceTree::FDT_PROP);
+    push_u32(dt_struct, value.len() as u32);
+    push_u32(dt_struct, name_offset);
+    push_padded(dt_struct, value);
+}
+
+/// Returns the offset of the name in the strings block, appending it if not found
+fn string_offset(strings: &mut Vec<u8>, name: &str) -> u32 {
+    let mut offset = 0;
+    for item in strings.split(|v| *v == 0) {
+        if item == name.as_bytes() && offset < strings.len() {
+            return offset as u32;
+        }
+        offset += item.len() + 1;
+    }
+    let offset = strings.len();
+    strings.extend_from_slice(name.as_bytes());
+    strings.push(0);
+    offset as u32
+}
@@ -0,0 +1,422 @@
+//! ELF Executable Loader
+//!
+//! Loads ELF32 and ELF64 executables for the running processor.
+//! Position independent images are relocated with the relative relocations only.
+
+use super::*;
+use ::elf::*;
+use core::mem::size_of;
+
+/// Program header common to ELF32 and ELF64
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    pub p_type: SegmentType,
+    pub p_flags: SegmentFlags,
+    pub p_offset: u64,
+    pub p_vaddr: u64,
+    pub p_paddr: u64,
+    pub p_filesz: u64,
+    pub p_memsz: u64,
+    pub p_align: u64,
+}
+
+impl From<elf32::ProgramHeader> for Segment {
+    #[inline]
+    fn from(value: elf32::ProgramHeader) -> Self {
+        Self {
+            p_type: value.p_type,
+            p_flags: value.p_flags,
+            p_offset: value.p_offset as u64,
+            p_vaddr: value.p_vaddr as u64,
+            p_paddr: value.p_paddr as u64,
+            p_filesz: value.p_filesz as u64,
+            p_memsz: value.p_memsz as u64,
+            p_align: value.p_align as u64,
+        }
+    }
+}
+
+impl From<elf64::ProgramHeader> for Segment {
+    #[inline]
+    fn from(value: elf64::ProgramHeader) -> Self {
+        Self {
+            p_type: value.p_type,
+            p_flags: value.p_flags,
+            p_offset: value.p_offset,
+            p_vaddr: value.p_vaddr,
+            p_paddr: value.p_paddr,
+            p_filesz: value.p_filesz,
+            p_memsz: value.p_memsz,
+            p_align: value.p_align,
+        }
+    }
+}
+
+/// ELF executable to be loaded
+pub struct ElfImage<'a> {
+    blob: &'a [u8],
+    is_64bit: bool,
+    elf_type: ElfType,
+    machine: Machine,
+    entry: u64,
+    segments: Vec<Segment>,
+}
+
+impl<'a> ElfImage<'a> {
+    /// Machine type of the running processor
+    #[cfg(target_arch = "x86")]
+    pub const NATIVE_MACHINE: Machine = EM_386;
+    #[cfg(target_arch = "x86_64")]
+    pub const NATIVE_MACHINE: Machine = EM_X86_64;
+    #[cfg(target_arch = "aarch64")]
+    pub const NATIVE_MACHINE: Machine = EM_AARCH64;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    pub const NATIVE_MACHINE: Machine = EM_RISCV;
+
+    /// Relocation type to add the load bias
+    #[cfg(target_arch = "x86")]
+    const R_RELATIVE: RelocationType = R_386_RELATIVE;
+    #[cfg(target_arch = "x86_64")]
+    const R_RELATIVE: RelocationType = R_X86_64_RELATIVE;
+    #[cfg(target_arch = "aarch64")]
+    const R_RELATIVE: RelocationType = R_AARCH64_RELATIVE;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    const R_RELATIVE: RelocationType = R_RISCV_RELATIVE;
+
+    /// Parses and validates the headers of the executable for the running processor
+    #[inline]
+    pub fn parse(blob: &'a [u8]) -> Result<Self, LoadError> {
+        Self::parse_for(blob, Self::NATIVE_MACHINE)
+    }
+
+    /// Parses and validates the headers of the executable for the machine
+    pub fn parse_for(blob: &'a [u8], machine: Machine) -> Result<Self, LoadError> {
+        let ident = blob.get(..EI_NIDENT).ok_or(LoadError::BadFormat)?;
+        if ident[..4] != ELFMAG {
+            return Err(LoadError::BadFormat);
+        }
+        if ident[EI_DATA] != ELFDATA2LSB || ident[EI_VERSION] != EV_CURRENT {
+            return Err(LoadError::NotSupported);
+        }
+
+        let (is_64bit, elf_type, e_machine, entry, phoff, phentsize, phnum) = match ident[EI_CLASS]
+        {
+            ELFCLASS32 => {
+                let header = read::<elf32::Header>(blob, 0)?;
+                (
+                    false,
+                    header.e_type,
+                    header.e_machine,
+                    header.e_entry as u64,
+                    header.e_phoff as u64,
+                    header.e_phentsize as usize,
+                    header.e_phnum as usize,
+                )
+            }
+            ELFCLASS64 => {
+                let header = read::<elf64::Header>(blob, 0)?;
+                (
+                    true,
+                    header.e_type,
+                    header.e_machine,
+                    header.e_entry,
+                    header.e_phoff,
+                    header.e_phentsize as usize,
+                    header.e_phnum as usize,
+                )
+            }
+            _ => return Err(LoadError::NotSupported),
+        };
+        if e_machine != machine || (elf_type != ET_EXEC && elf_type != ET_DYN) {
+            return Err(LoadError::NotSupported);
+        }
+
+        let min_phentsize = if is_64bit {
+            size_of::<elf64::ProgramHeader>()
+        } else {
+            size_of::<elf32::ProgramHeader>()
+        };
+        if phentsize < min_phentsize {
+            return Err(LoadError::InvalidData);
+        }
+        let mut segments = Vec::with_capacity(phnum);
+        for index in 0..phnum {
+            let offset = phoff
+                .checked_add((index * phentsize) as u64)
+                .ok_or(LoadError::InvalidData)?;
+            let offset = usize::try_from(offset).map_err(|_| LoadError::InvalidData)?;
+            let segment: Segment = if is_64bit {
+                read::<elf64::ProgramHeader>(blob, offset)?.into()
+            } else {
+                read::<elf32::ProgramHeader>(blob, offset)?.into()
+            };
+            if segment.p_type == PT_LOAD
+                && (segment.p_filesz > segment.p_memsz
+                    || segment
+                        .p_offset
+                        .checked_add(segment.p_filesz)
+                        .is_none_or(|v| v > blob.len() as u64)
+                    || segment.p_vaddr.checked_add(segment.p_memsz).is_none()
+                    || segment.p_paddr.checked_add(segment.p_memsz).is_none())
+            {
+                return Err(LoadError::InvalidData);
+            }
+            segments.push(segment);
+        }
+        if !segments.iter().any(|v| v.p_type == PT_LOAD) {
+            return Err(LoadError::InvalidData);
+        }
+
+        Ok(Self {
+            blob,
+            is_64bit,
+            elf_type,
+            machine: e_machine,
+            entry,
+            segments,
+        })
+    }
+
+    #[inline]
+    pub const fn is_64bit(&self) -> bool {
+        self.is_64bit
+    }
+
+    #[inline]
+    pub const fn elf_type(&self) -> ElfType {
+        self.elf_type
+    }
+
+    #[inline]
+    pub const fn machine(&self) -> Machine {
+        self.machine
+    }
+
+    /// Returns whether the image can be placed anywhere
+    #[inline]
+    pub fn is_position_independent(&self) -> bool {
+        self.elf_type == ET_DYN
+    }
+
+    #[inline]
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    #[inline]
+    fn load_segments(&self) -> impl Iterator<Item = &Segment> {
+        self.segments.iter().filter(|v| v.p_type == PT_LOAD)
+    }
+
+    /// Places the segments in memory and returns the image ready to be started
+    ///
+    /// Executables are placed at the physical addresses of their segments,
+    /// position independent images are placed anywhere and relocated.
+    pub fn load(&self) -> Result<LoadedImage, LoadError> {
+        let is_pie = self.is_position_independent();
+        let load_addr = |segment: &Segment| {
+            if is_pie {
+                segment.p_vaddr
+            } else {
+                segment.p_paddr
+            }
+        };
+        let page_mask = MemoryManager::PAGE_SIZE - 1;
+        let start = self.load_segments().map(load_addr).min().unwrap_or(0) & !page_mask;
+        let end = self
+            .load_segments()
+            .map(|v| load_addr(v) + v.p_memsz)
+            .max()
+            .unwrap_or(0);
+        // Executables are placed at fixed addresses, so only the page alignment matters
+        let align = if is_pie {
+            self.load_segments()
+                .map(|v| v.p_align)
+                .filter(|v| v.is_power_of_two())
+                .max()
+                .unwrap_or(1)
+                .max(MemoryManager::PAGE_SIZE)
+        } else {
+            MemoryManager::PAGE_SIZE
+        };
+        if end > usize::MAX as u64 || align > usize::MAX as u64 {
+            return Err(LoadError::InvalidData);
+        }
+
+        let mut image = LoadedImage::alloc(
+            (!is_pie).then_some(start as usize),
+            (end - start) as usize,
+            align as usize,
+        )?;
+        let bias = (image.base() as u64).wrapping_sub(start);
+
+        match self.place(&mut image, bias) {
+            Ok(entry) => {
+                image.entry = entry;
+                Ok(image)
+            }
+            Err(err) => {
+                image.free();
+                Err(err)
+            }
+        }
+    }
+
+    /// Copies the segments, applies the relocations and returns the entry point
+    fn place(&self, image: &mut LoadedImage, bias: u64) -> Result<usize, LoadError> {
+        let is_pie = self.is_position_independent();
+        for segment in self.load_segments() {
+            let addr = if is_pie {
+                segment.p_vaddr
+            } else {
+                segment.p_paddr
+            };
+            let dest =
+                image.slice_at(addr.wrapping_add(bias) as usize, segment.p_memsz as usize)?;
+            let filesz = segment.p_filesz as usize;
+            let offset = segment.p_offset as usize;
+            dest[..filesz].copy_from_slice(&self.blob[offset..offset + filesz]);
+            // BSS
+            dest[filesz..].fill(0);
+        }
+
+        if is_pie {
+            self.relocate(image, bias)?;
+            return Ok(self.entry.wrapping_add(bias) as usize);
+        }
+
+        // The entry point is a virtual address, which may differ from the physical address
+        self.load_segments()
+            .find(|v| (v.p_vaddr..v.p_vaddr + v.p_memsz).contains(&self.entry))
+            .map(|v| (self.entry - v.p_vaddr + v.p_paddr) as usize)
+            .ok_or(LoadError::InvalidData)
+    }
+
+    /// Applies the relative relocations listed in the dynamic section
+    fn relocate(&self, image: &mut LoadedImage, bias: u64) -> Result<(), LoadError> {
+        let Some(dynamic) = self.segments.iter().find(|v| v.p_type == PT_DYNAMIC) else {
+            return Ok(());
+        };
+
+        let mut rel = None;
+        let mut relsz = 0;
+        let mut rela = None;
+        let mut relasz = 0;
+        let dyn_size = if self.is_64bit {
+            size_of::<elf64::Dyn>()
+        } else {
+            size_of::<elf32::Dyn>()
+        };
+        for index in 0..(dynamic.p_filesz as usize / dyn_size) {
+            let offset = dynamic.p_offset as usize + index * dyn_size;
+            let (tag, value) = if self.is_64bit {
+                let entry = read::<elf64::Dyn>(self.blob, offset)?;
+                (entry.tag(), entry.d_val)
+            } else {
+                let entry = read::<elf32::Dyn>(self.blob, offset)?;
+                (entry.tag(), entry.d_val as u64)
+            };
+            match tag {
+                DT_NULL => break,
+                DT_REL => rel = Some(value),
+                DT_RELSZ => relsz = value,
+                DT_RELA => rela = Some(value),
+                DT_RELASZ => relasz = value,
+                // The PLT and packed relative relocations are not supported
+                DT_JMPREL | DT_RELR => return Err(LoadError::NotSupported),
+                _ => {}
+            }
+        }
+
+        if let Some(rel) = rel {
+            let table = image
+                .slice_at(rel.wrapping_add(bias) as usize, relsz as usize)?
+                .to_vec();
+            if self.is_64bit {
+                for entry in table.chunks_exact(size_of::<elf64::Rel>()) {
+                    let entry = read::<elf64::Rel>(entry, 0)?;
+                    self.apply(image, bias, entry.r_type(), entry.r_offset, None)?;
+                }
+            } else {
+                for entry in table.chunks_exact(size_of::<elf32::Rel>()) {
+                    let entry = read::<elf32::Rel>(entry, 0)?;
+                    self.apply(image, bias, entry.r_type(), entry.r_offset as u64, None)?;
+                }
+            }
+        }
+        if let Some(rela) = rela {
+            let table = image
+                .slice_at(rela.wrapping_add(bias) as usize, relasz as usize)?
+                .to_vec();
+            if self.is_64bit {
+                for entry in table.chunks_exact(size_of::<elf64::Rela>()) {
+                    let entry = read::<elf64::Rela>(entry, 0)?;
+                    self.apply(
+                        image,
+                        bias,
+                        entry.r_type(),
+                        entry.r_offset,
+                        Some(entry.r_addend),
+                    )?;
+                }
+            } else {
+                for entry in table.chunks_exact(size_of::<elf32::Rela>()) {
+                    let entry = read::<elf32::Rela>(entry, 0)?;
+                    self.apply(
+                        image,
+                        bias,
+                        entry.r_type(),
+                        entry.r_offset as u64,
+                        Some(entry.r_addend as i64),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a relocation, the addend is taken from the target if `addend` is `None`
+    fn apply(
+        &self,
+        image: &mut LoadedImage,
+        bias: u64,
+        r_type: RelocationType,
+        r_offset: u64,
+        addend: Option<i64>,
+    ) -> Result<(), LoadError> {
+        if r_type.0 == 0 {
+            return Ok(());
+        }
+        if r_type != Self::R_RELATIVE {
+            return Err(LoadError::NotSupported);
+        }
+        let addr = r_offset.wrapping_add(bias) as usize;
+        if self.is_64bit {
+            let target = image.slice_at(addr, 8)?;
+            let addend = match addend {
+                Some(v) => v as u64,
+                None => u64::from_le_bytes(target.try_into().unwrap()),
+            };
+            target.copy_from_slice(&bias.wrapping_add(addend).to_le_bytes());
+        } else {
+            let target = image.slice_at(addr, 4)?;
+            let addend = match addend {
+                Some(v) => v as u32,
+                None => u32::from_le_bytes(target.try_into().unwrap()),
+            };
+            target.copy_from_slice(&(bias as u32).wrapping_add(addend).to_le_bytes());
+        }
+        Ok(())
+    }
+}
+
+/// Reads a structure of `lib/elf` from the blob
+#[inline]
+fn read<T: Copy>(blob: &[u8], offset: usize) -> Result<T, LoadError> {
+    let bytes = offset
+        .checked_add(size_of::<T>())
+        .and_then(|end| blob.get(offset..end))
+        .ok_or(LoadError::InvalidData)?;
+    // The structures consist of integers only and any bit pattern is valid
+    Ok(unsafe { (bytes.as_ptr() as *const T).read_unaligned() })
+}
@@ -0,0 +1,300 @@
+//! Linux Image Loader
+//!
+//! Loads the flat `Image` of arm64 and RISC-V Linux kernels and starts them with the device tree,
+//! as described in `Documentation/arch/arm64/booting.rst` and `Documentation/arch/riscv/boot-image-header.rst`.
+
+use super::*;
+use core::arch::asm;
+
+/// Header at the beginning of the `Image`
+#[derive(Debug, Clone, Copy)]
+pub struct LinuxImageHeader {
+    /// Offset of the image from the aligned base address
+    pub text_offset: u64,
+    /// Effective size of the image including BSS
+    pub image_size: u64,
+    pub flags: u64,
+}
+
+impl LinuxImageHeader {
+    pub const SIZE: usize = 64;
+
+    /// `ARM\x64`
+    #[cfg(target_arch = "aarch64")]
+    pub const MAGIC: u32 = 0x644d_5241;
+    /// `RSC\x05`
+    #[cfg(target_arch = "riscv64")]
+    pub const MAGIC: u32 = 0x0543_5352;
+    /// `RISCV\0\0\0`, deprecated since version 0.2 of the header
+    #[cfg(target_arch = "riscv64")]
+    pub const MAGIC_DEPRECATED: u64 = 0x0000_0056_4353_4952;
+
+    /// Alignment of the base address, 2MB for both arm64 and RV64
+    pub const BASE_ALIGN: usize = 0x20_0000;
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        let bytes = bytes.get(..Self::SIZE).ok_or(LoadError::BadFormat)?;
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+
+        #[cfg(target_arch = "aarch64")]
+        let is_valid = u32_at(56) == Self::MAGIC;
+        #[cfg(target_arch = "riscv64")]
+        let is_valid = u32_at(56) == Self::MAGIC || u64_at(48) == Self::MAGIC_DEPRECATED;
+        if !is_valid {
+            return Err(LoadError::BadFormat);
+        }
+
+        let header = Self {
+            text_offset: u64_at(8),
+            image_size: u64_at(16),
+            flags: u64_at(24),
+        };
+        // Kernels older than the header with `image_size` are not supported
+        if header.image_size == 0 {
+            return Err(LoadError::NotSupported);
+        }
+        Ok(header)
+    }
+
+    /// Returns whether the kernel is big endian (arm64 only)
+    #[inline]
+    pub const fn is_big_endian(&self) -> bool {
+        cfg!(target_arch = "aarch64") && (self.flags & 1) != 0
+    }
+}
+
+/// Linux kernel image to be loaded
+pub struct LinuxImage<'a> {
+    header: LinuxImageHeader,
+    blob: &'a [u8],
+}
+
+impl<'a> LinuxImage<'a> {
+    /// Parses and validates the header of the image
+    pub fn parse(blob: &'a [u8]) -> Result<Self, LoadError> {
+        let header = LinuxImageHeader::from_bytes(blob)?;
+        if header.is_big_endian() {
+            return Err(LoadError::NotSupported);
+        }
+        if header.text_offset > usize::MAX as u64
+            || header.image_size > usize::MAX as u64
+            || (header.image_size as usize) < blob.len()
+        {
+            return Err(LoadError::InvalidData);
+        }
+        Ok(Self { header, blob })
+    }
+
+    #[inline]
+    pub const fn header(&self) -> &LinuxImageHeader {
+        &self.header
+    }
+
+    /// Places the image at `text_offset` from an aligned base address
+    pub fn load(&self) -> Result<LoadedImage, LoadError> {
+        let text_offset = self.header.text_offset as usize;
+        let size = text_offset
+            .checked_add(self.header.image_size as usize)
+            .ok_or(LoadError::InvalidData)?;
+        let mut image = LoadedImage::alloc(None, size, LinuxImageHeader::BASE_ALIGN)?;
+        let entry = image.base() + text_offset;
+        image.entry = entry;
+        // The rest of the image is BSS, which is zero-filled by the allocation
+        image
+            .slice_at(entry, self.blob.len())?
+            .copy_from_slice(self.blob);
+        Ok(image)
+    }
+}
+
+/// Kernel ready to be started with the device tree
+pub struct LinuxBoot {
+    image: LoadedImage,
+    dtb: LoadedImage,
+    initrd: Option<LoadedImage>,
+}
+
+impl LinuxBoot {
+    /// Prepares the device tree with the command line and the initial ramdisk for the image
+    ///
+    /// The memory of the image is released if the preparation fails.
+    pub fn new(
+        image: LoadedImage,
+        cmdline: &str,
+        initrd: Option<&[u8]>,
+    ) -> Result<Self, LoadError> {
+        let initrd = match initrd {
+            Some(blob) => match Self::place_initrd(blob) {
+                Ok(initrd) => Some(initrd),
+                Err(err) => {
+                    image.free();
+                    return Err(err);
+                }
+            },
+            None => None,
+        };
+        match Self::place_dtb(cmdline, initrd.as_ref()) {
+            Ok(dtb) => Ok(Self { image, dtb, initrd }),
+            Err(err) => {
+                image.free();
+                if let Some(initrd) = initrd {
+                    initrd.free();
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns the address of the device tree passed to the kernel
+    #[inline]
+    pub const fn dtb(&self) -> usize {
+        self.dtb.base()
+    }
+
+    /// Returns the initial ramdisk placed for the kernel
+    #[inline]
+    pub const fn initrd(&self) -> Option<&LoadedImage> {
+        self.initrd.as_ref()
+    }
+
+    fn place_initrd(blob: &[u8]) -> Result<LoadedImage, LoadError> {
+        let mut initrd = LoadedImage::alloc(None, blob.len(), MemoryManager::PAGE_SIZE as usize)?;
+        initrd.as_mut_slice()[..blob.len()].copy_from_slice(blob);
+        Ok(initrd)
+    }
+
+    fn place_dtb(cmdline: &str, initrd: Option<&LoadedImage>) -> Result<LoadedImage, LoadError> {
+        let dt = System::device_tree().ok_or(LoadError::NotSupported)?;
+
+        let mut bootargs = Vec::with_capacity(cmdline.len() + 1);
+        bootargs.extend_from_slice(cmdline.as_bytes());
+        bootargs.push(0);
+        let initrd_start;
+        let initrd_end;
+        let mut props: Vec<(&str, &[u8])> = Vec::new();
+        // The arguments given by the firmware are kept if there is no command line
+        if !cmdline.is_empty() {
+            props.push(("bootargs", &bootargs));
+        }
+        if let Some(initrd) = initrd {
+            initrd_start = (initrd.base() as u64).to_be_bytes();
+            initrd_end = ((initrd.base() + initrd.size()) as u64).to_be_bytes();
+            props.push(("linux,initrd-start", &initrd_start));
+            props.push(("linux,initrd-end", &initrd_end));
+        }
+
+        let blob = dtb::patch_chosen(dt.header(), &props);
+        let mut dtb = LoadedImage::alloc(None, blob.len(), MemoryManager::PAGE_SIZE as usize)?;
+        dtb.as_mut_slice()[..blob.len()].copy_from_slice(&blob);
+        Ok(dtb)
+    }
+
+    /// Exits minios and transfers control to the kernel in the state the boot protocol requires
+    ///
+    /// # Safety
+    ///
+    /// The image must be a Linux kernel for the running processor.
+    pub unsafe fn start(self) -> ! {
+        let entry = self.image.entry();
+        let dtb = self.dtb.base();
+        unsafe {
+            #[cfg(target_arch = "riscv64")]
+            let hart_id = crate::platform::Platform::boot_hart_id();
+
+            // The devices are written back before the interrupts are disabled
+            System::exit_minios();
+            Hal::cpu().disable_interrupt();
+
+            #[cfg(target_arch = "aarch64")]
+            {
+                clean_dcache(self.image.base(), self.image.size());
+                clean_dcache(self.dtb.base(), self.dtb.size());
+                if let Some(initrd) = &self.initrd {
+                    clean_dcache(initrd.base(), initrd.size());
+                }
+                enter_kernel(entry, dtb)
+            }
+            #[cfg(target_arch = "riscv64")]
+            {
+                enter_kernel(entry, hart_id, dtb)
+            }
+        }
+    }
+}
+
+/// Cleans the data cache of the range to the point of coherency
+#[cfg(target_arch = "aarch64")]
+unsafe fn clean_dcache(base: usize, size: usize) {
+    unsafe {
+        let ctr: usize;
+        asm!("mrs {0}, ctr_el0", out(reg) ctr, options(nomem, nostack));
+        let line_size = 4 << ((ctr >> 16) & 15);
+        let mut p = base & !(line_size - 1);
+        while p < base + size {
+            asm!("dc cvac, {0}", in(reg) p, options(nostack));
+            p += line_size;
+        }
+        asm!("dsb sy", options(nostack));
+    }
+}
+
+/// Turns off the MMU and the data cache of the current exception level and jumps to the kernel
+///
+/// `x0` is the address of the device tree and `x1` to `x3` are zero.
+#[cfg(target_arch = "aarch64")]
+unsafe fn enter_kernel(entry: usize, dtb: usize) -> ! {
+    unsafe {
+        asm!(
+            "msr daifset, #0xf",
+            "mrs x9, CurrentEL",
+            "cmp x9, #8",
+            "b.eq 2f",
+            "mrs x9, sctlr_el1",
+            "bic x9, x9, #1",
+            "bic x9, x9, #4",
+            "msr sctlr_el1, x9",
+            "b 3f",
+            "2:",
+            "mrs x9, sctlr_el2",
+            "bic x9, x9, #1",
+            "bic x9, x9, #4",
+            "msr sctlr_el2, x9",
+            "3:",
+            "isb",
+            "ic iallu",
+            "dsb sy",
+            "isb",
+            "mov x1, xzr",
+            "mov x2, xzr",
+            "mov x3, xzr",
+            "br x16",
+            in("x0") dtb,
+            in("x16") entry,
+            options(noreturn),
+        );
+    }
+}
+
+/// Turns off the address translation and jumps to the kernel
+///
+/// `a0` is the hart ID and `a1` is the address of the device tree.
+#[cfg(target_arch = "riscv64")]
+unsafe fn enter_kernel(entry: usize, hart_id: usize, dtb: usize) -> ! {
+    unsafe {
+        asm!(
+            "csrw sie, zero",
+            "csrci sstatus, 0x02",
+            "csrw satp, zero",
+            "sfence.vma",
+            "fence.i",
+            "jr t0",
+            in("a0") hart_id,
+            in("a1") dtb,
+            in("t0") entry,
+            options(noreturn),
+        );
+    }
+}
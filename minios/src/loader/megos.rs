@@ -0,0 +1,211 @@
+//! MEG-OS Boot Protocol
+//!
+//! Hands off the loaded kernel with a [`BootInfo`] describing the environment minios leaves behind.
+
+use super::*;
+use crate::io::graphics::PixelFormat;
+use crate::mem::{AddressConstraint, MemoryMapEntry};
+use crate::platform::Platform;
+use bootprot::*;
+use core::mem::{align_of, size_of};
+use core::ptr::NonNull;
+
+/// Kernel ready to be started with the boot information
+pub struct MegOsBoot {
+    image: LoadedImage,
+    info: NonNull<BootInfo>,
+}
+
+impl MegOsBoot {
+    /// Extra descriptors for the entries split by the allocation of the boot information itself
+    const MMAP_SLACK: usize = 4;
+
+    /// Builds the boot information for the image
+    ///
+    /// The image is released if the boot information cannot be built.
+    pub fn new(image: LoadedImage, cmdline: &str) -> Result<Self, LoadError> {
+        match Self::build(&image, cmdline) {
+            Ok(info) => Ok(Self { image, info }),
+            Err(err) => {
+                image.free();
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns the boot information passed to the kernel
+    #[inline]
+    pub fn boot_info(&self) -> &BootInfo {
+        unsafe { self.info.as_ref() }
+    }
+
+    /// Exits minios and transfers control to the kernel with the boot information
+    ///
+    /// # Safety
+    ///
+    /// The image must be a MEG-OS kernel for the running processor.
+    pub unsafe fn start(self) -> ! {
+        unsafe {
+            System::exit_minios();
+            self.image.start(self.info.as_ptr() as usize)
+        }
+    }
+
+    fn build(image: &LoadedImage, cmdline: &str) -> Result<NonNull<BootInfo>, LoadError> {
+        // BootInfo, the memory map and the command line are placed in one block below 4GB
+        let mmap_capacity = MemoryManager::memory_list().count() + Self::MMAP_SLACK;
+        let mmap_offset =
+            size_of::<BootInfo>().next_multiple_of(align_of::<BootMemoryMapDescriptor>());
+        let cmdline_offset = mmap_offset + mmap_capacity * size_of::<BootMemoryMapDescriptor>();
+        let size = cmdline_offset + cmdline.len() + 1;
+        let layout = Layout::from_size_align(size, MemoryManager::PAGE_SIZE as usize)
+            .map_err(|_| LoadError::InvalidData)?;
+        let base = MemoryManager::zalloc_constrained(
+            layout,
+            AddressConstraint::below_4g(),
+            MemoryType::Used,
+            None,
+        )?;
+
+        let (initrd_base, initrd_size) = match System::initrd() {
+            Some(initrd) => {
+                let blob = initrd.as_bytes();
+                (blob.as_ptr() as usize, blob.len())
+            }
+            None => (0, 0),
+        };
+        if initrd_base + initrd_size > u32::MAX as usize {
+            unsafe {
+                let _ = MemoryManager::zfree(base, layout);
+            }
+            return Err(LoadError::NotSupported);
+        }
+
+        unsafe {
+            // The allocation above may have split some entries, so the map is taken after it
+            let mmap = core::slice::from_raw_parts_mut(
+                base.add(mmap_offset) as *mut BootMemoryMapDescriptor,
+                mmap_capacity,
+            );
+            let mut mmap_len = 0;
+            let mut real_bitmap = [0u32; 8];
+            for entry in MemoryManager::memory_list() {
+                let Some(desc) = Self::convert_mmap_entry(&entry) else {
+                    continue;
+                };
+                if desc.mem_type == BootMemoryType::Available {
+                    let start = desc.base / MemoryManager::PAGE_SIZE;
+                    let end = start + desc.page_count as u64;
+                    for page in start..end.min(256) {
+                        real_bitmap[page as usize / 32] |= 1 << (page % 32);
+                    }
+                }
+                let Some(slot) = mmap.get_mut(mmap_len) else {
+                    break;
+                };
+                *slot = desc;
+                mmap_len += 1;
+            }
+
+            let p_cmdline = base.add(cmdline_offset);
+            core::slice::from_raw_parts_mut(p_cmdline, cmdline.len())
+                .copy_from_slice(cmdline.as_bytes());
+
+            let info = base as *mut BootInfo;
+            info.write(BootInfo {
+                platform: Self::platform_type(),
+                color_mode: ColorMode::Unspecified,
+                screen_width: 0,
+                screen_height: 0,
+                vram_stride: 0,
+                vram_base: 0,
+                master_page_table: 0,
+                acpi_rsdptr: Self::config_table_address(&[
+                    acpi::ACPI_20_TABLE_GUID,
+                    acpi::ACPI_10_TABLE_GUID,
+                ]),
+                dtb: Self::config_table_address(&[fdt::DTB_TABLE_GUID]),
+                smbios: Self::config_table_address(&[smbios::SMBIOS_GUID, smbios::SMBIOS3_GUID]),
+                kernel_base: image.base() as u64,
+                total_memory_size: MemoryManager::total_memory_size() as u64,
+                cmdline: p_cmdline as usize as u64,
+                initrd_base: initrd_base as u32,
+                initrd_size: initrd_size as u32,
+                mmap_base: mmap.as_ptr() as usize as u32,
+                mmap_len: mmap_len as u32,
+                real_bitmap,
+                flags: BootFlags::empty(),
+            });
+
+            let info = &mut *info;
+            if let Some(mode) = System::conctl().current_graphics_mode()
+                && let Some(stride) = mode.info.pixels_per_scanline()
+            {
+                info.color_mode = match mode.info.pixel_format {
+                    PixelFormat::Indexed8 => ColorMode::Indexed8,
+                    PixelFormat::BGRX8888 => ColorMode::Argb32,
+                    PixelFormat::RGBX8888 => ColorMode::Abgr32,
+                };
+                info.screen_width = mode.info.width;
+                info.screen_height = mode.info.height;
+                info.vram_stride = stride;
+                info.vram_base = mode.fb.as_u64();
+            }
+
+            Ok(NonNull::new_unchecked(info))
+        }
+    }
+
+    fn platform_type() -> PlatformType {
+        match System::platform() {
+            Platform::Nec98 => PlatformType::Nec98,
+            Platform::PcBios => PlatformType::PcCompatible,
+            Platform::FmTowns => PlatformType::FmTowns,
+            Platform::Uefi => PlatformType::UefiNative,
+            _ => PlatformType::Unspecified,
+        }
+    }
+
+    /// Returns the address of the first configuration table found in the order of `guids`
+    fn config_table_address(guids: &[guid::Guid]) -> u64 {
+        guids
+            .iter()
+            .find_map(System::find_config_table_entry)
+            .map(|entry| entry.address.get().as_u64())
+            .unwrap_or(0)
+    }
+
+    /// Converts the memory map entry to the page granularity descriptor
+    ///
+    /// The available memory is rounded inward and the others are rounded outward,
+    /// so that the kernel never uses memory that is not free.
+    fn convert_mmap_entry(entry: &MemoryMapEntry) -> Option<BootMemoryMapDescriptor> {
+        let mem_type = match entry.mem_type {
+            MemoryType::Available => BootMemoryType::Available,
+            MemoryType::Used => BootMemoryType::OsLoaderData,
+            MemoryType::Reserved => BootMemoryType::Reserved,
+            MemoryType::AcpiReclaim => BootMemoryType::AcpiReclaim,
+            MemoryType::AcpiNvs => BootMemoryType::AcpiNonVolatile,
+            MemoryType::DeviceTree => BootMemoryType::FirmwareData,
+            MemoryType::OtherFw => BootMemoryType::FirmwareData,
+        };
+        let range = entry.range();
+        let (start, end) = if mem_type == BootMemoryType::Available {
+            (
+                range.start.next_multiple_of(MemoryManager::PAGE_SIZE),
+                range.end & MemoryManager::PAGE_MASK,
+            )
+        } else {
+            (
+                range.start & MemoryManager::PAGE_MASK,
+                range.end.next_multiple_of(MemoryManager::PAGE_SIZE),
+            )
+        };
+        let page_count = end.checked_sub(start)? / MemoryManager::PAGE_SIZE;
+        (page_count > 0).then(|| BootMemoryMapDescriptor {
+            base: start,
+            page_count: page_count.min(u32::MAX as u64) as u32,
+            mem_type,
+        })
+    }
+}
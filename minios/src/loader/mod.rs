@@ -0,0 +1,144 @@
+//! Executable Image Loaders
+
+#[cfg(all(target_arch = "x86", feature = "pc"))]
+pub mod bzimage;
+pub mod ceef;
+pub mod elf;
+#[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+pub mod linux;
+pub mod megos;
+
+#[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+mod dtb;
+mod stk1;
+
+use crate::mem::{MemoryError, MemoryManager, MemoryType};
+use crate::*;
+use core::alloc::Layout;
+use core::mem::transmute;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    /// The image is not in the expected format
+    BadFormat,
+    /// The image is for another architecture or uses features not supported
+    NotSupported,
+    /// The image is truncated or inconsistent
+    InvalidData,
+    /// The memory at the address required by the image is not available
+    OutOfMemory,
+}
+
+impl From<MemoryError> for LoadError {
+    #[inline]
+    fn from(value: MemoryError) -> Self {
+        match value {
+            MemoryError::InvalidParameter => Self::InvalidData,
+            MemoryError::OutOfMemory => Self::OutOfMemory,
+        }
+    }
+}
+
+/// Image placed in memory, ready to be started
+#[derive(Debug)]
+pub struct LoadedImage {
+    base: usize,
+    size: usize,
+    align: usize,
+    entry: usize,
+}
+
+impl LoadedImage {
+    /// Allocates the memory for the image at `base`, or anywhere if `base` is `None`
+    fn alloc(base: Option<usize>, size: usize, align: usize) -> Result<Self, LoadError> {
+        let layout =
+            Layout::from_size_align(size.max(1), align).map_err(|_| LoadError::InvalidData)?;
+        let desired_addr = match base {
+            Some(base) => {
+                Some(NonNullPhysicalAddress::from_usize(base).ok_or(LoadError::NotSupported)?)
+            }
+            None => None,
+        };
+        let ptr = MemoryManager::zalloc(layout, desired_addr, MemoryType::Used, None)?;
+        Ok(Self {
+            base: ptr as usize,
+            size: layout.size(),
+            align: layout.align(),
+            entry: 0,
+        })
+    }
+
+    /// Allocates the memory for the image as high as possible below `limit`
+    #[cfg(all(target_arch = "x86", feature = "pc"))]
+    fn alloc_below(limit: u64, size: usize, align: usize) -> Result<Self, LoadError> {
+        use crate::mem::{AddressConstraint, MemoryAllocationStrategy};
+
+        let layout =
+            Layout::from_size_align(size.max(1), align).map_err(|_| LoadError::InvalidData)?;
+        let ptr = MemoryManager::zalloc_constrained(
+            layout,
+            AddressConstraint::new(0..limit),
+            MemoryType::Used,
+            Some(MemoryAllocationStrategy::LastFit),
+        )?;
+        Ok(Self {
+            base: ptr as usize,
+            size: layout.size(),
+            align: layout.align(),
+            entry: 0,
+        })
+    }
+
+    /// Returns the address the image is placed at
+    #[inline]
+    pub const fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Returns the size of the memory occupied by the image
+    #[inline]
+    pub const fn size(&self) -> usize {
+        self.size
+    }
+
+    #[inline]
+    pub const fn entry(&self) -> usize {
+        self.entry
+    }
+
+    /// Returns the memory of the image
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.base as *mut u8, self.size) }
+    }
+
+    /// Returns the part of the image memory at the address, if it is in the image
+    fn slice_at(&mut self, addr: usize, len: usize) -> Result<&mut [u8], LoadError> {
+        let offset = addr.checked_sub(self.base).ok_or(LoadError::InvalidData)?;
+        self.as_mut_slice()
+            .get_mut(offset..offset.checked_add(len).ok_or(LoadError::InvalidData)?)
+            .ok_or(LoadError::InvalidData)
+    }
+
+    /// Releases the memory of the image
+    pub fn free(self) {
+        unsafe {
+            let _ = MemoryManager::zfree(
+                self.base as *mut u8,
+                Layout::from_size_align_unchecked(self.size, self.align),
+            );
+        }
+    }
+
+    /// Transfers control to the entry point, passing `arg` as the only argument of the C calling convention
+    ///
+    /// # Safety
+    ///
+    /// The image must be a valid program for the running processor and the environment it expects must be set up.
+    pub unsafe fn start(self, arg: usize) -> ! {
+        unsafe {
+            let entry: extern "C" fn(usize) -> ! = transmute(self.entry);
+            entry(arg)
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! STK1 Decoder
+//!
+//! Decodes the LZ77 stream produced by `Stk1::encode` of the `compress` crate, the same as the SSBL does.
+
+/// Reader of the compressed stream
+struct Reader<'a> {
+    src: &'a [u8],
+    index: usize,
+}
+
+impl Reader<'_> {
+    #[inline]
+    fn next(&mut self) -> Option<u8> {
+        let result = self.src.get(self.index).copied();
+        self.index += 1;
+        result
+    }
+
+    /// Reads a variable length number continuing from `value`,
+    /// 7 bits per byte from the upper bits, terminated by a byte with the lowest bit set
+    fn s7s_from(&mut self, mut value: usize) -> Option<usize> {
+        loop {
+            let byte = self.next()?;
+            value = value.checked_mul(0x80)? | (byte >> 1) as usize;
+            if byte & 1 != 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    #[inline]
+    fn s7s(&mut self) -> Option<usize> {
+        self.s7s_from(0)
+    }
+}
+
+/// Returns the decoded size and the compressed stream following the size header
+pub fn header(src: &[u8]) -> Option<(usize, &[u8])> {
+    let mut reader = Reader { src, index: 0 };
+    let size = reader.s7s()?;
+    let _compressed_size = reader.s7s()?;
+    Some((size, &src[reader.index..]))
+}
+
+/// Decodes the stream into `dest`, whose length must be the decoded size
+pub fn decode(src: &[u8], dest: &mut [u8]) -> Option<()> {
+    let mut reader = Reader { src, index: 0 };
+    let end = dest.len();
+    let mut index = 0;
+    while index < end {
+        let byte = reader.next()?;
+        let n_literals = match byte & 0x0f {
+            0 => reader.s7s()?,
+            v => v as usize,
+        };
+        let n_copies = match byte >> 4 {
+            0 => reader.s7s()?,
+            v => v as usize,
+        };
+
+        let literals = src.get(reader.index..reader.index.checked_add(n_literals)?)?;
+        dest.get_mut(index..index.checked_add(n_literals)?)?
+            .copy_from_slice(literals);
+        reader.index += n_literals;
+        index += n_literals;
+
+        for _ in 0..n_copies {
+            if index >= end {
+                return Some(());
+            }
+            let byte = reader.next()?;
+            let distance = match byte & 0x01 {
+                0 => reader.s7s_from(((byte & 0x0f) >> 1) as usize)?,
+                _ => ((byte & 0x0f) >> 1) as usize,
+            };
+            let len = match byte >> 4 {
+                0 => reader.s7s()?,
+                v => v as usize,
+            }
+            .checked_add(1)?;
+
+            // The source may overlap the destination
+            let from = index.checked_sub(distance.checked_add(1)?)?;
+            if index.checked_add(len)? > end {
+                return None;
+            }
+            for i in 0..len {
+                dest[index + i] = dest[from + i];
+            }
+            index += len;
+        }
+    }
+    Some(())
+}
@@ -1,9 +1,9 @@
 //! Global Allocator
 
-use super::{MemoryManager, MemoryType};
+use super::heap::Heap;
 use core::{
     alloc::{GlobalAlloc, Layout},
-    ptr::null_mut,
+    ptr::{NonNull, null_mut},
 };
 
 #[global_allocator]
@@ -19,12 +19,25 @@ impl CustomAlloc {
 
 unsafe impl GlobalAlloc for CustomAlloc {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        MemoryManager::zalloc(layout, None, MemoryType::Used, None).unwrap_or(null_mut())
+        Heap::alloc(layout)
+            .map(|v| v.as_ptr())
+            .unwrap_or(null_mut())
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        unsafe {
-            MemoryManager::zfree(ptr, layout).unwrap();
+        if let Some(ptr) = NonNull::new(ptr) {
+            unsafe {
+                Heap::dealloc(ptr, layout);
+            }
         }
     }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let Some(ptr) = NonNull::new(ptr) else {
+            return null_mut();
+        };
+        unsafe { Heap::realloc(ptr, layout, new_size) }
+            .map(|v| v.as_ptr())
+            .unwrap_or(null_mut())
+    }
 }
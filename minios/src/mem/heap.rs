@@ -0,0 +1,182 @@
+//! Small Object Heap
+
+use super::{MemoryManager, MemoryType};
+use crate::sync::spin::SpinMutex;
+use core::{alloc::Layout, ptr::NonNull};
+
+static HEAP: SpinMutex<Heap> = SpinMutex::new(Heap::new());
+
+/// Size-class slab allocator layered on top of the [`MemoryManager`]
+///
+/// Requests up to [`Heap::MAX_SLAB_SIZE`] bytes are carved out of pages shared by
+/// blocks of the same size class. Larger requests go directly to the page allocator.
+pub struct Heap {
+    free_lists: [Option<NonNull<FreeBlock>>; Heap::NUMBER_OF_CLASSES],
+    slab_pages: usize,
+    slab_bytes_in_use: usize,
+    large_bytes_in_use: usize,
+}
+
+unsafe impl Send for Heap {}
+
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+}
+
+impl Heap {
+    /// Minimum block size, must be large enough to hold a [`FreeBlock`]
+    pub const MIN_SLAB_SIZE: usize = 16;
+
+    /// Maximum block size served by the slabs
+    pub const MAX_SLAB_SIZE: usize = 2048;
+
+    const MIN_SLAB_SHIFT: usize = Self::MIN_SLAB_SIZE.trailing_zeros() as usize;
+
+    const NUMBER_OF_CLASSES: usize =
+        (Self::MAX_SLAB_SIZE.trailing_zeros() - Self::MIN_SLAB_SIZE.trailing_zeros()) as usize + 1;
+
+    const SLAB_PAGE_SIZE: usize = MemoryManager::PAGE_SIZE as usize;
+
+    const fn new() -> Self {
+        Self {
+            free_lists: [None; Self::NUMBER_OF_CLASSES],
+            slab_pages: 0,
+            slab_bytes_in_use: 0,
+            large_bytes_in_use: 0,
+        }
+    }
+
+    /// Returns the size class index for the layout, or `None` for large objects
+    #[inline]
+    fn size_class(layout: Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align()).max(Self::MIN_SLAB_SIZE);
+        if size > Self::MAX_SLAB_SIZE {
+            return None;
+        }
+        let shift = size.next_power_of_two().trailing_zeros() as usize;
+        Some(shift - Self::MIN_SLAB_SHIFT)
+    }
+
+    #[inline]
+    const fn class_size(class: usize) -> usize {
+        Self::MIN_SLAB_SIZE << class
+    }
+
+    pub fn alloc(layout: Layout) -> Option<NonNull<u8>> {
+        match Self::size_class(layout) {
+            Some(class) => loop {
+                if let Some(block) = HEAP.lock().alloc_slab(class) {
+                    return Some(block);
+                }
+                // The page allocator is called without the lock, as it may be re-entered
+                let page = Self::alloc_slab_page()?;
+                HEAP.lock().add_slab_page(page, class);
+            },
+            None => {
+                let size = Self::large_size(layout);
+                let ptr = MemoryManager::zalloc(layout, None, MemoryType::Used, None).ok()?;
+                HEAP.lock().large_bytes_in_use += size;
+                NonNull::new(ptr)
+            }
+        }
+    }
+
+    pub unsafe fn dealloc(ptr: NonNull<u8>, layout: Layout) {
+        match Self::size_class(layout) {
+            Some(class) => unsafe {
+                HEAP.lock().dealloc_slab(ptr, class);
+            },
+            None => unsafe {
+                MemoryManager::zfree(ptr.as_ptr(), layout).unwrap();
+                HEAP.lock().large_bytes_in_use -= Self::large_size(layout);
+            },
+        }
+    }
+
+    pub unsafe fn realloc(
+        ptr: NonNull<u8>,
+        layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        let new_layout = Layout::from_size_align(new_size, layout.align()).ok()?;
+        match (Self::size_class(layout), Self::size_class(new_layout)) {
+            (None, None) => unsafe {
+                let new_ptr = MemoryManager::zrealloc(ptr.as_ptr(), layout, new_size).ok()?;
+                let mut heap = HEAP.lock();
+                heap.large_bytes_in_use -= Self::large_size(layout);
+                heap.large_bytes_in_use += Self::large_size(new_layout);
+                NonNull::new(new_ptr)
+            },
+            (Some(old_class), Some(new_class)) if old_class == new_class => Some(ptr),
+            _ => unsafe {
+                let new_ptr = Self::alloc(new_layout)?;
+                new_ptr.copy_from_nonoverlapping(ptr, layout.size().min(new_size));
+                Self::dealloc(ptr, layout);
+                Some(new_ptr)
+            },
+        }
+    }
+
+    /// Returns the number of bytes occupied by live allocations
+    pub fn used_size() -> usize {
+        let heap = HEAP.lock();
+        heap.slab_bytes_in_use + heap.large_bytes_in_use
+    }
+
+    /// Returns the number of pages owned by the slabs
+    pub fn slab_pages() -> usize {
+        HEAP.lock().slab_pages
+    }
+
+    #[inline]
+    fn large_size(layout: Layout) -> usize {
+        (layout.size() + MemoryManager::PAGE_SIZE_M1 as usize) & MemoryManager::PAGE_MASK as usize
+    }
+
+    fn alloc_slab(&mut self, class: usize) -> Option<NonNull<u8>> {
+        let block = self.free_lists[class]?;
+        unsafe {
+            self.free_lists[class] = block.as_ref().next;
+        }
+        self.slab_bytes_in_use += Self::class_size(class);
+        Some(block.cast())
+    }
+
+    unsafe fn dealloc_slab(&mut self, ptr: NonNull<u8>, class: usize) {
+        let block = ptr.cast::<FreeBlock>();
+        unsafe {
+            block.write(FreeBlock {
+                next: self.free_lists[class],
+            });
+        }
+        self.free_lists[class] = Some(block);
+        self.slab_bytes_in_use -= Self::class_size(class);
+    }
+
+    /// Takes a new page for the slabs from the page allocator
+    fn alloc_slab_page() -> Option<NonNull<u8>> {
+        let page = MemoryManager::zalloc(
+            Layout::from_size_align(Self::SLAB_PAGE_SIZE, Self::SLAB_PAGE_SIZE).ok()?,
+            None,
+            MemoryType::Used,
+            None,
+        )
+        .ok()?;
+        NonNull::new(page)
+    }
+
+    /// Splits the page into free blocks of the size class
+    fn add_slab_page(&mut self, page: NonNull<u8>, class: usize) {
+        let block_size = Self::class_size(class);
+        let mut head = self.free_lists[class];
+        for offset in (0..Self::SLAB_PAGE_SIZE).step_by(block_size).rev() {
+            unsafe {
+                let block = page.add(offset).cast::<FreeBlock>();
+                block.write(FreeBlock { next: head });
+                head = Some(block);
+            }
+        }
+        self.free_lists[class] = head;
+        self.slab_pages += 1;
+    }
+}
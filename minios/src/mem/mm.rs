@@ -2,29 +2,28 @@
 //!
 //! TODO: Will rewrite the whole thing later
 
-use super::{MemoryAllocationStrategy, MemoryMapEntry, MemoryType};
+use super::{AddressConstraint, MemoryAllocationStrategy, MemoryMapEntry, MemoryType};
 use crate::*;
 use core::{
     alloc::Layout,
     cell::UnsafeCell,
     cmp,
-    ops::{Deref, DerefMut, Range},
+    ops::{Index, IndexMut, Range},
     ptr::{NonNull, null_mut},
 };
-#[allow(unused_imports)]
 use minilib::fixedvec::FixedVec;
 
 #[cfg(target_arch = "x86")]
 use crate::arch::lomem::LoMemoryManager;
 
 #[cfg(feature = "device_tree")]
-use fdt::DeviceTree;
+use fdt::{DeviceTree, PropName};
 
 static mut MM: UnsafeCell<MemoryManager> = UnsafeCell::new(MemoryManager::new());
 
 pub struct MemoryManager {
     conventional: MemMapTable,
-    himem: Vec<MemoryMapEntry>,
+    himem: FixedVec<MemoryMapEntry, { MemoryManager::MAX_HIMEM_ENTRIES }>,
     total_memory_size: usize,
     total_extended_memory_size: usize,
     allocation_strategy: MemoryAllocationStrategy,
@@ -37,10 +36,16 @@ impl MemoryManager {
 
     pub const PAGE_MASK: u64 = !(Self::PAGE_SIZE - 1);
 
+    /// Start of the high memory that is not managed by the conventional memory map
+    pub const HIMEM_BASE: u64 = 0x1_0000_0000;
+
+    /// Capacity of the high memory table, which never grows so that the allocator is not re-entered
+    const MAX_HIMEM_ENTRIES: usize = 64;
+
     const fn new() -> Self {
         Self {
             conventional: MemMapTable::new(),
-            himem: Vec::new(),
+            himem: FixedVec::new(),
             total_memory_size: 0,
             total_extended_memory_size: 0,
             allocation_strategy: MemoryAllocationStrategy::FirstFit,
@@ -69,13 +74,12 @@ impl MemoryManager {
             + page_size_m1)
             & page_mask;
         let first_page_size = Self::PAGE_SIZE as usize;
-        let first_page_len = first_page_size / core::mem::size_of::<ConventionalMemoryMapEntry>();
         let end = end - first_page_size;
         unsafe {
             let shared = Self::shared_mut();
             shared
                 .conventional
-                .init(end as *mut ConventionalMemoryMapEntry, first_page_len);
+                .init(end as *mut ConventionalMemoryMapEntry);
             shared
                 .conventional
                 .push(ConventionalMemoryMapEntry::new(
@@ -96,16 +100,17 @@ impl MemoryManager {
     }
 
     #[cfg(feature = "device_tree")]
-    #[inline]
     pub unsafe fn init_dt(dt: &DeviceTree) {
+        let page_size = Self::PAGE_SIZE as usize;
         let page_size_m1 = Self::PAGE_SIZE_M1 as usize;
         let page_mask = Self::PAGE_MASK as usize;
 
         let info = System::boot_info();
+        let start = (info.start_conventional_memory as usize + page_size_m1) & page_mask;
 
         if true {
             println!("Early Memory Map from DeviceTree:");
-            for item in dt.memory_map().unwrap() {
+            for item in dt.memory_map().into_iter().flatten() {
                 println!(
                     "DT MEMMAP: {:08x}-{:08x} {}KB",
                     item.0,
@@ -127,46 +132,30 @@ impl MemoryManager {
                 dt.range().0 as usize + dt.range().1 - 1,
                 (dt.range().1 + 1023) >> 10,
             );
-
-            println!(
-                "Conventional Memory: {:08x}-{:08x} {}KB",
-                info.start_conventional_memory as usize,
-                info.start_conventional_memory as usize + info.conventional_memory_size as usize
-                    - 1,
-                (info.conventional_memory_size + 1023) >> 10,
-            );
         }
 
-        let start = (info.start_conventional_memory as usize + page_size_m1) & page_mask;
-        let end = (info.start_conventional_memory as usize
-            + info.conventional_memory_size as usize
-            + page_size_m1)
-            & page_mask;
-        let first_page_size = Self::PAGE_SIZE as usize;
-        let first_page_len = first_page_size / core::mem::size_of::<ConventionalMemoryMapEntry>();
-        let end = end - first_page_size;
         unsafe {
             let shared = Self::shared_mut();
+
+            // The first page of the table is placed right after the image
             shared
                 .conventional
-                .init(end as *mut ConventionalMemoryMapEntry, first_page_len);
-            shared
-                .conventional
-                .push(ConventionalMemoryMapEntry::new(
-                    start,
-                    end - start,
-                    MemoryType::Available,
-                ))
-                .unwrap();
-            shared
-                .conventional
-                .push(ConventionalMemoryMapEntry::new(
-                    end,
-                    first_page_size,
-                    MemoryType::Used,
-                ))
+                .init(start as *mut ConventionalMemoryMapEntry);
+
+            // Everything from the beginning of the RAM bank up to the table is
+            // occupied by the firmware, the image and its stack
+            let image_base = dt
+                .memory_map()
+                .into_iter()
+                .flatten()
+                .find(|(base, size)| (*base..base + size).contains(&(start as u64)))
+                .map(|v| v.0)
+                .unwrap_or(start as u64);
+            Self::register_memmap(image_base..(start + page_size) as u64, MemoryType::Used)
                 .unwrap();
 
+            // The regions in use are registered before the free memory, so that the pages
+            // taken for the table never overlap them.
             let range = dt.range();
             Self::register_memmap(
                 range.0 as u64..range.0 as u64 + range.1 as u64,
@@ -181,9 +170,93 @@ impl MemoryManager {
                 )
                 .unwrap();
             }
+
+            Self::register_reserved_memory_dt(dt, false);
+
+            if let Some((base, size)) = dt.root().chosen().and_then(|v| v.initrd()) {
+                let _ = Self::register_memmap(base..base + size, MemoryType::Reserved);
+            }
+
+            for (base, size) in dt.memory_map().into_iter().flatten() {
+                let _ = Self::register_memmap(base..base + size, MemoryType::Available);
+            }
+
+            // Dynamic regions are allocated after all static regions are known
+            Self::register_reserved_memory_dt(dt, true);
+        }
+    }
+
+    /// Registers the children of `/reserved-memory`
+    ///
+    /// Regions marked `reusable` may be reclaimed by the OS, so they are registered as
+    /// [`MemoryType::OtherFw`]. Other regions, including `no-map`, are never handed out.
+    #[cfg(feature = "device_tree")]
+    unsafe fn register_reserved_memory_dt(dt: &DeviceTree, dynamic: bool) {
+        let Some(reserved_memory) = dt.root().reserved_memory() else {
+            return;
+        };
+        let address_cells = reserved_memory
+            .address_cells()
+            .unwrap_or(dt.root().address_cells());
+        let size_cells = reserved_memory
+            .size_cells()
+            .unwrap_or(dt.root().size_cells());
+
+        for child in reserved_memory.children() {
+            if !child.status_is_ok() {
+                continue;
+            }
+            let mem_type = if child.has_prop(PropName::REUSABLE) {
+                MemoryType::OtherFw
+            } else {
+                MemoryType::Reserved
+            };
+
+            if let Some(reg) = child.reg() {
+                if !dynamic {
+                    for (base, size) in reg {
+                        let _ = unsafe { Self::register_memmap(base..base + size, mem_type) };
+                    }
+                }
+            } else if let Some(size) = child.get_prop_u64(PropName::SIZE) {
+                if !dynamic {
+                    continue;
+                }
+                let align = child
+                    .get_prop_u64(PropName::ALIGNMENT)
+                    .unwrap_or(Self::PAGE_SIZE);
+                let Ok(layout) = Layout::from_size_align(size as usize, align as usize) else {
+                    continue;
+                };
+
+                // The region is simply left out if it cannot be allocated
+                if let Some(alloc_ranges) = child.get_prop(PropName::ALLOC_RANGES) {
+                    for (base, size) in alloc_ranges.address_and_size(address_cells, size_cells) {
+                        let result = Self::zalloc_constrained(
+                            layout,
+                            AddressConstraint::new(base..base + size),
+                            mem_type,
+                            Some(MemoryAllocationStrategy::LastFit),
+                        );
+                        if result.is_ok() {
+                            break;
+                        }
+                    }
+                } else {
+                    let _ = Self::zalloc_constrained(
+                        layout,
+                        AddressConstraint::any(),
+                        mem_type,
+                        Some(MemoryAllocationStrategy::LastFit),
+                    );
+                }
+            }
         }
     }
 
+    /// Registers the range in the memory map.
+    ///
+    /// Only the gaps and the free areas within the range are changed, other entries that overlap are kept as they are.
     pub unsafe fn register_memmap(
         range: Range<u64>,
         mem_type: MemoryType,
@@ -195,21 +268,9 @@ impl MemoryManager {
         }
         unsafe {
             let shared = Self::shared_mut();
-            if range.start >= 0x1_0000_0000 {
-                // libpoe itself uses less than 4GB of memory
-                let mut index = None;
-                let new_item = MemoryMapEntry::new(start, end - start, mem_type);
-                for (i, item) in shared.himem.iter().enumerate() {
-                    if item.base > new_item.base {
-                        index = Some(i);
-                        break;
-                    }
-                }
-                if let Some(index) = index {
-                    shared.himem.insert(index, new_item);
-                } else {
-                    shared.himem.push(new_item);
-                }
+            if start >= Self::HIMEM_BASE {
+                // High memory is only used by the constrained allocations
+                shared.register_high(start..end, mem_type)?;
 
                 let mut acc = 0;
                 for item in shared.himem.iter() {
@@ -219,32 +280,12 @@ impl MemoryManager {
                     }
                 }
                 shared.total_extended_memory_size = ((acc + 0xfffff) >> 20) as usize;
-            } else if range.end > 0x1_0000_0000 {
-                return Err(MemoryError::InvalidParameter);
+            } else if end > Self::HIMEM_BASE {
+                // Split the range at the 4GB boundary
+                Self::register_memmap(start..Self::HIMEM_BASE, mem_type)?;
+                return Self::register_memmap(Self::HIMEM_BASE..end, mem_type);
             } else {
-                let new_item = ConventionalMemoryMapEntry::new(
-                    start as usize,
-                    (end - start) as usize,
-                    mem_type,
-                );
-                let mut index = None;
-                for (i, item) in shared.conventional.iter().enumerate() {
-                    if item.base() > new_item.base() {
-                        index = Some(i);
-                        break;
-                    }
-                }
-                if let Some(index) = index {
-                    shared
-                        .conventional
-                        .insert(index, new_item)
-                        .map_err(|_| MemoryError::OutOfMemory)?;
-                } else {
-                    shared
-                        .conventional
-                        .push(new_item)
-                        .map_err(|_| MemoryError::OutOfMemory)?;
-                }
+                shared.register_conventional(start as usize..end as usize, mem_type)?;
             }
 
             let mut acc = 0;
@@ -262,6 +303,98 @@ impl MemoryManager {
         Ok(())
     }
 
+    fn register_conventional(
+        &mut self,
+        range: Range<usize>,
+        mem_type: MemoryType,
+    ) -> Result<(), MemoryError> {
+        let mut cursor = range.start;
+        while cursor < range.end {
+            self.reserve_table()?;
+            let index = self
+                .conventional
+                .iter()
+                .position(|v| v.range().end > cursor);
+            match index {
+                Some(index) if self.conventional[index].base() <= cursor => {
+                    // overlaps with the existing entry
+                    let item = &self.conventional[index];
+                    let next = item.range().end.min(range.end);
+                    if item.mem_type() == MemoryType::Available && mem_type != MemoryType::Available
+                    {
+                        self.carve(index, cursor..next, mem_type)?;
+                    }
+                    cursor = next;
+                }
+                Some(index) => {
+                    // fills the gap before the entry
+                    let next = self.conventional[index].base().min(range.end);
+                    self.conventional
+                        .insert(
+                            index,
+                            ConventionalMemoryMapEntry::new(cursor, next - cursor, mem_type),
+                        )
+                        .map_err(|_| MemoryError::OutOfMemory)?;
+                    self.coalesce(index);
+                    cursor = next;
+                }
+                None => {
+                    self.conventional
+                        .push(ConventionalMemoryMapEntry::new(
+                            cursor,
+                            range.end - cursor,
+                            mem_type,
+                        ))
+                        .map_err(|_| MemoryError::OutOfMemory)?;
+                    self.coalesce(self.conventional.len() - 1);
+                    cursor = range.end;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Same as [`MemoryManager::register_conventional`], but for the high memory
+    fn register_high(
+        &mut self,
+        range: Range<u64>,
+        mem_type: MemoryType,
+    ) -> Result<(), MemoryError> {
+        let mut cursor = range.start;
+        while cursor < range.end {
+            self.check_himem_capacity()?;
+            let index = self.himem.iter().position(|v| v.range().end > cursor);
+            match index {
+                Some(index) if self.himem[index].base <= cursor => {
+                    // overlaps with the existing entry
+                    let item = &self.himem[index];
+                    let next = item.range().end.min(range.end);
+                    if item.mem_type == MemoryType::Available && mem_type != MemoryType::Available {
+                        self.carve_high(index, cursor..next, mem_type)?;
+                    }
+                    cursor = next;
+                }
+                Some(index) => {
+                    // fills the gap before the entry
+                    let next = self.himem[index].base.min(range.end);
+                    self.himem
+                        .insert(index, MemoryMapEntry::new(cursor, next - cursor, mem_type))
+                        .map_err(|_| MemoryError::OutOfMemory)?;
+                    self.coalesce_high(index);
+                    cursor = next;
+                }
+                None => {
+                    self.himem
+                        .push(MemoryMapEntry::new(cursor, range.end - cursor, mem_type))
+                        .map_err(|_| MemoryError::OutOfMemory)?;
+                    self.coalesce_high(self.himem.len() - 1);
+                    cursor = range.end;
+                }
+            }
+        }
+        Ok(())
+    }
+
     #[cfg(target_arch = "x86")]
     #[inline]
     pub fn memory_list<'a>() -> impl Iterator<Item = MemoryMapEntry> + 'a {
@@ -359,6 +492,7 @@ impl MemoryManager {
                 shared._zalloc(
                     layout,
                     desired_addr,
+                    0..usize::MAX,
                     mem_type,
                     strategy.unwrap_or(shared.allocation_strategy),
                 )
@@ -366,6 +500,60 @@ impl MemoryManager {
         }
     }
 
+    /// Allocates memory within the address constraint.
+    ///
+    /// High memory above 4GB is preferred if the constraint allows it, so that
+    /// large buffers do not consume the conventional memory.
+    #[must_use]
+    pub fn zalloc_constrained(
+        layout: Layout,
+        constraint: AddressConstraint,
+        mem_type: MemoryType,
+        strategy: Option<MemoryAllocationStrategy>,
+    ) -> Result<*mut u8, MemoryError> {
+        if mem_type == MemoryType::Available
+            || layout.size() > i32::MAX as usize
+            || layout.align() > i32::MAX as usize
+        {
+            return Err(MemoryError::InvalidParameter);
+        }
+        // Only the memory that can be addressed by a pointer can be allocated
+        let constraint = constraint.intersection(AddressConstraint::new(0..usize::MAX as u64));
+        if constraint.is_empty() {
+            return Err(MemoryError::InvalidParameter);
+        }
+
+        let shared = unsafe { Self::shared_mut() };
+        let strategy = strategy.unwrap_or(shared.allocation_strategy);
+        unsafe {
+            without_interrupts!({
+                let mut result = Err(MemoryError::OutOfMemory);
+                if constraint.end() > Self::HIMEM_BASE {
+                    result = shared._zalloc_high(
+                        layout,
+                        constraint.intersection(AddressConstraint::above_4g()),
+                        mem_type,
+                        strategy,
+                    );
+                }
+                if result.is_err() && constraint.start() < Self::HIMEM_BASE {
+                    let limit = constraint.intersection(AddressConstraint::below_4g());
+                    result = shared._zalloc(
+                        layout,
+                        None,
+                        limit.start() as usize..limit.end() as usize,
+                        mem_type,
+                        strategy,
+                    );
+                }
+                result
+            })
+        }
+    }
+
+    /// Frees the memory allocated by [`MemoryManager::zalloc`].
+    ///
+    /// The range may also be a part of an allocated block, in which case only that part is freed.
     pub unsafe fn zfree(ptr: *mut u8, layout: Layout) -> Result<(), MemoryFreeError> {
         if ptr == null_mut() {
             // do nothing
@@ -376,11 +564,83 @@ impl MemoryManager {
         unsafe { without_interrupts!(shared._zfree(ptr, layout)) }
     }
 
+    /// Changes the size of the allocated block without moving it.
+    ///
+    /// Only the blocks in the conventional memory can be resized in place.
+    pub unsafe fn zrealloc_in_place(
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> Result<(), MemoryError> {
+        if ptr == null_mut()
+            || ptr as u64 >= Self::HIMEM_BASE
+            || new_size == 0
+            || new_size > i32::MAX as usize
+        {
+            return Err(MemoryError::InvalidParameter);
+        }
+
+        let shared = unsafe { Self::shared_mut() };
+        unsafe { without_interrupts!(shared._realloc_in_place(ptr, layout, new_size)) }
+    }
+
+    /// Changes the size of the allocated block, moving it if it cannot be resized in place.
+    ///
+    /// The blocks in the high memory are always moved.
+    pub unsafe fn zrealloc(
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> Result<*mut u8, MemoryError> {
+        unsafe {
+            let is_high = ptr as u64 >= Self::HIMEM_BASE;
+            if !is_high {
+                match Self::zrealloc_in_place(ptr, layout, new_size) {
+                    Ok(_) => return Ok(ptr),
+                    Err(MemoryError::InvalidParameter) => {
+                        return Err(MemoryError::InvalidParameter);
+                    }
+                    Err(MemoryError::OutOfMemory) => {}
+                }
+            } else if new_size == 0 || new_size > i32::MAX as usize {
+                return Err(MemoryError::InvalidParameter);
+            }
+
+            let shared = Self::shared();
+            let mem_type = if is_high {
+                shared
+                    .himem
+                    .iter()
+                    .find(|v| v.range().contains(&(ptr as u64)))
+                    .map(|v| v.mem_type)
+            } else {
+                shared
+                    .conventional
+                    .iter()
+                    .find(|v| v.range().contains(&(ptr as usize)))
+                    .map(|v| v.mem_type())
+            }
+            .filter(|v| *v != MemoryType::Available)
+            .ok_or(MemoryError::InvalidParameter)?;
+            let new_layout = Layout::from_size_align(new_size, layout.align())
+                .map_err(|_| MemoryError::InvalidParameter)?;
+            let new_ptr = if is_high {
+                Self::zalloc_constrained(new_layout, AddressConstraint::any(), mem_type, None)?
+            } else {
+                Self::zalloc(new_layout, None, mem_type, None)?
+            };
+            new_ptr.copy_from_nonoverlapping(ptr, layout.size().min(new_size));
+            Self::zfree(ptr, layout).map_err(|_| MemoryError::InvalidParameter)?;
+            Ok(new_ptr)
+        }
+    }
+
     #[inline]
     unsafe fn _zalloc(
         &mut self,
         layout: Layout,
         desired_addr: Option<NonNullPhysicalAddress>,
+        limit: Range<usize>,
         mem_type: MemoryType,
         strategy: MemoryAllocationStrategy,
     ) -> Result<*mut u8, MemoryError> {
@@ -389,120 +649,447 @@ impl MemoryManager {
         let align_m1 = align - 1;
         let align_mask = !align_m1;
 
+        self.reserve_table()?;
+
         let mut found = None;
         if let Some(desired_addr) = desired_addr {
             let desired_addr = desired_addr.get().as_usize();
-            for (index, item) in self.conventional.iter_mut().enumerate() {
-                match item.mem_type() {
-                    MemoryType::Available => {
-                        if item.range().contains(&desired_addr) {
-                            if item.range().end >= desired_addr + size {
-                                found = Some((index, item, desired_addr, size));
-                                break;
-                            } else {
-                                return Err(MemoryError::OutOfMemory);
-                            }
-                        }
+            if desired_addr & Self::PAGE_SIZE_M1 as usize != 0 {
+                return Err(MemoryError::InvalidParameter);
+            }
+            for (index, item) in self.conventional.iter().enumerate() {
+                if item.range().contains(&desired_addr) {
+                    if item.mem_type() == MemoryType::Available
+                        && item.range().end - desired_addr >= size
+                    {
+                        found = Some((index, desired_addr));
+                        break;
+                    } else {
+                        return Err(MemoryError::OutOfMemory);
                     }
-                    _ => {}
                 }
             }
         } else {
             match strategy {
-                MemoryAllocationStrategy::FirstFit | MemoryAllocationStrategy::BestFit => {
-                    for (index, item) in self.conventional.iter_mut().enumerate() {
-                        match item.mem_type() {
-                            MemoryType::Available => {
-                                let start = (item.base() + align_m1) & align_mask;
-                                let end = item.range().end;
-                                let end_fixed = start + size;
-                                if end >= end_fixed {
-                                    if strategy == MemoryAllocationStrategy::BestFit {
-                                        if found.as_ref().map(|v| v.1.size()).unwrap_or(0)
-                                            < item.size()
-                                        {
-                                            found = Some((index, item, start, size));
-                                        }
-                                    } else {
-                                        found = Some((index, item, start, size));
-                                    }
+                MemoryAllocationStrategy::FirstFit => {
+                    for (index, item) in self.conventional.iter().enumerate() {
+                        if let Some(start) = item.fit(size, align_mask, &limit) {
+                            found = Some((index, start));
+                            break;
+                        }
+                    }
+                }
+                MemoryAllocationStrategy::BestFit => {
+                    let mut best_size = usize::MAX;
+                    for (index, item) in self.conventional.iter().enumerate() {
+                        if let Some(start) = item.fit(size, align_mask, &limit) {
+                            if item.size() < best_size {
+                                best_size = item.size();
+                                found = Some((index, start));
+                                if best_size == size {
                                     break;
                                 }
                             }
-                            _ => {}
                         }
                     }
                 }
                 MemoryAllocationStrategy::LastFit => {
-                    for (index, item) in self.conventional.iter_mut().enumerate().rev() {
-                        match item.mem_type() {
-                            MemoryType::Available => {
-                                let end = item.range().end;
-                                let start = (end - size) & align_mask;
-                                if start >= item.base() {
-                                    found = Some((index, item, start, size));
-                                    break;
-                                }
-                            }
-                            _ => {}
+                    for (index, item) in self.conventional.iter().enumerate().rev() {
+                        if let Some(start) = item.fit_last(size, align_mask, &limit) {
+                            found = Some((index, start));
+                            break;
                         }
                     }
                 }
             }
         }
-        let Some(found) = found else {
+        let Some((index, start)) = found else {
             return Err(MemoryError::OutOfMemory);
         };
 
-        let mut target_index = found.0;
-        let target = found.1;
-        let new_item = ConventionalMemoryMapEntry::new(found.2, found.3, mem_type);
+        self.carve(index, start..start + size, mem_type)?;
 
-        let extra_item = (new_item.range().end < target.range().end).then(|| {
-            ConventionalMemoryMapEntry::new(
-                new_item.range().end,
-                target.range().end - new_item.range().end,
-                MemoryType::Available,
-            )
-        });
+        let p = start as *mut u8;
+        unsafe {
+            p.write_bytes(0, size);
+        }
+        Ok(p)
+    }
 
-        if new_item.base() > target.base() {
-            target.set_size(new_item.base() - target.base());
-            target_index += 1;
+    #[inline]
+    unsafe fn _zfree(&mut self, ptr: *mut u8, layout: Layout) -> Result<(), MemoryFreeError> {
+        let size =
+            (layout.size() as usize + Self::PAGE_SIZE_M1 as usize) & Self::PAGE_MASK as usize;
+        let start = ptr as usize;
+        if start & Self::PAGE_SIZE_M1 as usize != 0 {
+            return Err(MemoryFreeError::InvalidPointer);
+        }
+        if size == 0 {
+            return Err(MemoryFreeError::InvalidParameter);
+        }
+        if start as u64 >= Self::HIMEM_BASE {
+            return self._zfree_high(start as u64, size as u64);
+        }
+        let end = start + size;
+
+        // A partial free may split an entry into three, so make room first.
+        // Failure here only matters if a split is actually needed.
+        let _ = self.reserve_table();
+
+        let Some(index) = self.find_entry(start) else {
+            return Err(MemoryFreeError::InvalidPointer);
+        };
+        let item = &self.conventional[index];
+        match item.mem_type() {
+            MemoryType::Used => {}
+            MemoryType::Available => return Err(MemoryFreeError::DoubleFree),
+            // Reserved by the firmware, not allocated by minios
+            _ => return Err(MemoryFreeError::InvalidPointer),
+        }
+        if item.range().end < end {
+            return Err(MemoryFreeError::InvalidParameter);
+        }
+
+        let index = self
+            .carve(index, start..end, MemoryType::Available)
+            .map_err(|_| MemoryFreeError::InvalidParameter)?;
+        self.coalesce(index);
+
+        Ok(())
+    }
+
+    unsafe fn _realloc_in_place(
+        &mut self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> Result<(), MemoryError> {
+        let old_size = (layout.size() + Self::PAGE_SIZE_M1 as usize) & Self::PAGE_MASK as usize;
+        let new_size = (new_size + Self::PAGE_SIZE_M1 as usize) & Self::PAGE_MASK as usize;
+        let start = ptr as usize;
+        let old_end = start + old_size;
+        let new_end = start + new_size;
+
+        self.reserve_table()?;
+
+        let Some(index) = self.find_entry(start) else {
+            return Err(MemoryError::InvalidParameter);
+        };
+        let item = self.conventional[index].clone();
+        if item.mem_type() == MemoryType::Available || item.range().end < old_end {
+            return Err(MemoryError::InvalidParameter);
+        }
+
+        if new_size < old_size {
+            let index = self.carve(index, new_end..old_end, MemoryType::Available)?;
+            self.coalesce(index);
+        } else if new_size > old_size {
+            if item.range().end != old_end {
+                return Err(MemoryError::OutOfMemory);
+            }
+            match self.conventional.get(index + 1) {
+                Some(next)
+                    if next.mem_type() == MemoryType::Available
+                        && next.base() == old_end
+                        && next.range().end >= new_end => {}
+                _ => return Err(MemoryError::OutOfMemory),
+            }
+            self.carve(index + 1, old_end..new_end, item.mem_type())?;
+            self.conventional[index].set_size(new_size + (start - item.base()));
+            self.conventional.remove(index + 1);
+            unsafe {
+                (old_end as *mut u8).write_bytes(0, new_end - old_end);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the index of the entry containing the address
+    #[inline]
+    fn find_entry(&self, addr: usize) -> Option<usize> {
+        self.conventional
+            .iter()
+            .position(|v| v.range().contains(&addr))
+    }
+
+    /// Changes the type of the range inside the entry, splitting the entry as needed.
+    ///
+    /// Returns the index of the entry that now covers the range.
+    fn carve(
+        &mut self,
+        index: usize,
+        range: Range<usize>,
+        mem_type: MemoryType,
+    ) -> Result<usize, MemoryError> {
+        let target = self.conventional[index].clone();
+        let target_end = target.range().end;
+        let new_item = ConventionalMemoryMapEntry::new(range.start, range.len(), mem_type);
+
+        let mut index = index;
+        if range.start > target.base() {
+            self.conventional[index].set_size(range.start - target.base());
+            index += 1;
             self.conventional
-                .insert(target_index, new_item.clone())
-                .unwrap();
+                .insert(index, new_item)
+                .map_err(|_| MemoryError::OutOfMemory)?;
         } else {
-            *target = new_item.clone();
+            self.conventional[index] = new_item;
+        }
+        if range.end < target_end {
+            self.conventional
+                .insert(
+                    index + 1,
+                    ConventionalMemoryMapEntry::new(
+                        range.end,
+                        target_end - range.end,
+                        target.mem_type(),
+                    ),
+                )
+                .map_err(|_| MemoryError::OutOfMemory)?;
         }
-        if let Some(extra_item) = extra_item {
-            target_index += 1;
-            self.conventional.insert(target_index, extra_item).unwrap();
+        Ok(index)
+    }
+
+    /// Merges the free entry with its free neighbours
+    fn coalesce(&mut self, index: usize) {
+        let mut index = index;
+        if self.conventional[index].mem_type() != MemoryType::Available {
+            return;
         }
+        if index > 0 {
+            let prev = &self.conventional[index - 1];
+            if prev.mem_type() == MemoryType::Available
+                && prev.range().end == self.conventional[index].base()
+            {
+                let size = prev.size() + self.conventional[index].size();
+                self.conventional[index - 1].set_size(size);
+                self.conventional.remove(index);
+                index -= 1;
+            }
+        }
+        if let Some(next) = self.conventional.get(index + 1) {
+            if next.mem_type() == MemoryType::Available
+                && next.base() == self.conventional[index].range().end
+            {
+                let size = self.conventional[index].size() + next.size();
+                self.conventional[index].set_size(size);
+                self.conventional.remove(index + 1);
+            }
+        }
+    }
 
-        let p = new_item.base() as *mut u8;
+    unsafe fn _zalloc_high(
+        &mut self,
+        layout: Layout,
+        constraint: AddressConstraint,
+        mem_type: MemoryType,
+        strategy: MemoryAllocationStrategy,
+    ) -> Result<*mut u8, MemoryError> {
+        let size = (layout.size() as u64 + Self::PAGE_SIZE_M1) & Self::PAGE_MASK;
+        let align_mask = !((layout.align() as u64).max(Self::PAGE_SIZE) - 1);
+        let limit = constraint.range();
+
+        // Make sure that the entry can be split before touching the map
+        self.check_himem_capacity()?;
+
+        let found = match strategy {
+            MemoryAllocationStrategy::FirstFit => {
+                self.himem.iter().enumerate().find_map(|(index, item)| {
+                    item.fit(size, align_mask, &limit)
+                        .map(|start| (index, start))
+                })
+            }
+            MemoryAllocationStrategy::BestFit => self
+                .himem
+                .iter()
+                .enumerate()
+                .filter_map(|(index, item)| {
+                    item.fit(size, align_mask, &limit)
+                        .map(|start| (index, start, item.size))
+                })
+                .min_by_key(|v| v.2)
+                .map(|v| (v.0, v.1)),
+            MemoryAllocationStrategy::LastFit => {
+                self.himem
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .find_map(|(index, item)| {
+                        item.fit_last(size, align_mask, &limit)
+                            .map(|start| (index, start))
+                    })
+            }
+        };
+        let Some((index, start)) = found else {
+            return Err(MemoryError::OutOfMemory);
+        };
+
+        self.carve_high(index, start..start + size, mem_type)?;
+
+        let p = start as usize as *mut u8;
         unsafe {
-            p.write_bytes(0, new_item.size() as usize);
+            p.write_bytes(0, size as usize);
         }
         Ok(p)
     }
 
-    #[inline]
-    unsafe fn _zfree(&mut self, ptr: *mut u8, layout: Layout) -> Result<(), MemoryFreeError> {
-        let size =
-            (layout.size() as usize + Self::PAGE_SIZE_M1 as usize) & Self::PAGE_MASK as usize;
-        let temp = ConventionalMemoryMapEntry::new(ptr as usize, size, MemoryType::Used);
-        for item in self.conventional.iter_mut() {
-            if item.range() == temp.range() {
-                if item.mem_type() == MemoryType::Available {
-                    return Err(MemoryFreeError::DoubleFree);
-                } else {
-                    item.set_mem_type(MemoryType::Available);
-                    return Ok(());
-                }
+    unsafe fn _zfree_high(&mut self, start: u64, size: u64) -> Result<(), MemoryFreeError> {
+        let end = start + size;
+        self.check_himem_capacity()
+            .map_err(|_| MemoryFreeError::InvalidParameter)?;
+
+        let Some(index) = self.himem.iter().position(|v| v.range().contains(&start)) else {
+            return Err(MemoryFreeError::InvalidPointer);
+        };
+        let item = &self.himem[index];
+        match item.mem_type {
+            MemoryType::Used => {}
+            MemoryType::Available => return Err(MemoryFreeError::DoubleFree),
+            // Reserved by the firmware, not allocated by minios
+            _ => return Err(MemoryFreeError::InvalidPointer),
+        }
+        if item.range().end < end {
+            return Err(MemoryFreeError::InvalidParameter);
+        }
+
+        let index = self
+            .carve_high(index, start..end, MemoryType::Available)
+            .map_err(|_| MemoryFreeError::InvalidParameter)?;
+        self.coalesce_high(index);
+
+        Ok(())
+    }
+
+    /// Same as [`MemoryManager::carve`], but for the high memory
+    fn carve_high(
+        &mut self,
+        index: usize,
+        range: Range<u64>,
+        mem_type: MemoryType,
+    ) -> Result<usize, MemoryError> {
+        let target = self.himem[index].clone();
+        let target_end = target.range().end;
+        let new_item = MemoryMapEntry::new(range.start, range.end - range.start, mem_type);
+
+        let mut index = index;
+        if range.start > target.base {
+            self.himem[index].size = range.start - target.base;
+            index += 1;
+            self.himem
+                .insert(index, new_item)
+                .map_err(|_| MemoryError::OutOfMemory)?;
+        } else {
+            self.himem[index] = new_item;
+        }
+        if range.end < target_end {
+            self.himem
+                .insert(
+                    index + 1,
+                    MemoryMapEntry::new(range.end, target_end - range.end, target.mem_type),
+                )
+                .map_err(|_| MemoryError::OutOfMemory)?;
+        }
+        Ok(index)
+    }
+
+    /// Same as [`MemoryManager::coalesce`], but for the high memory
+    fn coalesce_high(&mut self, index: usize) {
+        let mut index = index;
+        if self.himem[index].mem_type != MemoryType::Available {
+            return;
+        }
+        if index > 0 {
+            let prev = &self.himem[index - 1];
+            if prev.mem_type == MemoryType::Available && prev.range().end == self.himem[index].base
+            {
+                self.himem[index - 1].size += self.himem[index].size;
+                self.himem.remove(index);
+                index -= 1;
+            }
+        }
+        if let Some(next) = self.himem.get(index + 1) {
+            if next.mem_type == MemoryType::Available && next.base == self.himem[index].range().end
+            {
+                let size = next.size;
+                self.himem[index].size += size;
+                self.himem.remove(index + 1);
             }
         }
-        Err(MemoryFreeError::InvalidPointer)
+    }
+
+    /// Makes sure that the table has room for a few more entries, chaining another page if needed
+    fn reserve_table(&mut self) -> Result<(), MemoryError> {
+        if self.conventional.spare_capacity() >= MemMapTable::MIN_SPARE_ENTRIES {
+            return Ok(());
+        }
+        let page_size = Self::PAGE_SIZE as usize;
+        let Some(index) = self
+            .conventional
+            .iter()
+            .rposition(|v| v.mem_type() == MemoryType::Available && v.size() >= page_size)
+        else {
+            return self.check_table_capacity();
+        };
+        let page = self.conventional[index].range().end - page_size;
+
+        let result = unsafe {
+            self.conventional
+                .add_page(page as *mut ConventionalMemoryMapEntry)
+        };
+        if result.is_err() {
+            return self.check_table_capacity();
+        }
+        self.carve(index, page..page + page_size, MemoryType::Used)
+            .map(|_| ())
+    }
+
+    /// Makes sure that an entry of the high memory table can be split into three
+    #[inline]
+    fn check_himem_capacity(&self) -> Result<(), MemoryError> {
+        if self.himem.capacity() - self.himem.len() >= 2 {
+            Ok(())
+        } else {
+            Err(MemoryError::OutOfMemory)
+        }
+    }
+
+    #[inline]
+    fn check_table_capacity(&self) -> Result<(), MemoryError> {
+        if self.conventional.spare_capacity() > 0 {
+            Ok(())
+        } else {
+            Err(MemoryError::OutOfMemory)
+        }
+    }
+}
+
+impl MemoryMapEntry {
+    /// Returns the lowest aligned start address if the free entry can hold the size within the limit
+    #[inline]
+    fn fit(&self, size: u64, align_mask: u64, limit: &Range<u64>) -> Option<u64> {
+        if self.mem_type != MemoryType::Available {
+            return None;
+        }
+        let base = self.base.max(limit.start);
+        let end = self.range().end.min(limit.end);
+        let start = base.checked_add(!align_mask)? & align_mask;
+        (end >= start && end - start >= size).then(|| start)
+    }
+
+    /// Returns the highest aligned start address if the free entry can hold the size within the limit
+    #[inline]
+    fn fit_last(&self, size: u64, align_mask: u64, limit: &Range<u64>) -> Option<u64> {
+        if self.mem_type != MemoryType::Available {
+            return None;
+        }
+        let base = self.base.max(limit.start);
+        let end = self.range().end.min(limit.end);
+        if end < base || end - base < size {
+            return None;
+        }
+        let start = (end - size) & align_mask;
+        (start >= base).then(|| start)
     }
 }
 
@@ -564,6 +1151,7 @@ impl ConventionalMemoryMapEntry {
             3 => MemoryType::AcpiReclaim,
             4 => MemoryType::AcpiNvs,
             5 => MemoryType::DeviceTree,
+            6 => MemoryType::OtherFw,
             _ => unreachable!(),
         }
     }
@@ -575,6 +1163,33 @@ impl ConventionalMemoryMapEntry {
             end: self.base + self.size(),
         }
     }
+
+    /// Returns the lowest aligned start address if the free entry can hold the size within the limit
+    #[inline]
+    fn fit(&self, size: usize, align_mask: usize, limit: &Range<usize>) -> Option<usize> {
+        if self.mem_type() != MemoryType::Available {
+            return None;
+        }
+        let base = self.base.max(limit.start);
+        let end = self.range().end.min(limit.end);
+        let start = base.checked_add(!align_mask)? & align_mask;
+        (end >= start && end - start >= size).then(|| start)
+    }
+
+    /// Returns the highest aligned start address if the free entry can hold the size within the limit
+    #[inline]
+    fn fit_last(&self, size: usize, align_mask: usize, limit: &Range<usize>) -> Option<usize> {
+        if self.mem_type() != MemoryType::Available {
+            return None;
+        }
+        let base = self.base.max(limit.start);
+        let end = self.range().end.min(limit.end);
+        if end < base || end - base < size {
+            return None;
+        }
+        let start = (end - size) & align_mask;
+        (start >= base).then(|| start)
+    }
 }
 
 impl PartialOrd for ConventionalMemoryMapEntry {
@@ -605,32 +1220,46 @@ impl core::fmt::Debug for ConventionalMemoryMapEntry {
     }
 }
 
+/// Conventional memory map table
+///
+/// The table starts with a single page and chains additional pages when it becomes full.
 pub struct MemMapTable {
-    ptr: NonNull<ConventionalMemoryMapEntry>,
+    pages: FixedVec<NonNull<ConventionalMemoryMapEntry>, { MemMapTable::MAX_PAGES }>,
     len: usize,
-    cap: usize,
 }
 
 impl MemMapTable {
     const SIZE_OF_ELEMENT: usize = core::mem::size_of::<ConventionalMemoryMapEntry>();
 
+    const ENTRIES_PER_PAGE: usize = MemoryManager::PAGE_SIZE as usize / Self::SIZE_OF_ELEMENT;
+
+    const MAX_PAGES: usize = 16;
+
+    /// Number of free entries to keep for splitting entries
+    const MIN_SPARE_ENTRIES: usize = 4;
+
     #[inline]
     pub const fn new() -> Self {
         Self {
-            ptr: NonNull::dangling(),
+            pages: FixedVec::new(),
             len: 0,
-            cap: 0,
         }
     }
 
     #[inline]
-    pub unsafe fn init(
-        &mut self,
-        first_page: *mut ConventionalMemoryMapEntry,
-        first_page_size: usize,
-    ) {
-        self.ptr = unsafe { NonNull::new_unchecked(first_page) };
-        self.cap = first_page_size / Self::SIZE_OF_ELEMENT;
+    pub unsafe fn init(&mut self, first_page: *mut ConventionalMemoryMapEntry) {
+        self.pages.clear();
+        self.len = 0;
+        unsafe {
+            self.add_page(first_page).unwrap();
+        }
+    }
+
+    /// Adds a page to the end of the chain
+    #[inline]
+    pub unsafe fn add_page(&mut self, page: *mut ConventionalMemoryMapEntry) -> Result<(), ()> {
+        let page = NonNull::new(page).ok_or(())?;
+        self.pages.push(page).map_err(|_| ())
     }
 
     #[inline]
@@ -639,24 +1268,51 @@ impl MemMapTable {
     }
 
     #[inline]
-    pub fn as_slice(&self) -> &[ConventionalMemoryMapEntry] {
-        unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    pub fn capacity(&self) -> usize {
+        self.pages.len() * Self::ENTRIES_PER_PAGE
+    }
+
+    #[inline]
+    pub fn spare_capacity(&self) -> usize {
+        self.capacity() - self.len
+    }
+
+    #[inline]
+    fn entry_ptr(&self, index: usize) -> *mut ConventionalMemoryMapEntry {
+        unsafe {
+            self.pages[index / Self::ENTRIES_PER_PAGE]
+                .as_ptr()
+                .add(index % Self::ENTRIES_PER_PAGE)
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&ConventionalMemoryMapEntry> {
+        (index < self.len).then(|| unsafe { &*self.entry_ptr(index) })
     }
 
     #[inline]
-    pub fn as_mut_slice(&mut self) -> &mut [ConventionalMemoryMapEntry] {
-        unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut ConventionalMemoryMapEntry> {
+        (index < self.len).then(|| unsafe { &mut *self.entry_ptr(index) })
+    }
+
+    #[inline]
+    pub fn iter<'a>(
+        &'a self,
+    ) -> impl DoubleEndedIterator<Item = &'a ConventionalMemoryMapEntry> + ExactSizeIterator + 'a
+    {
+        (0..self.len).map(move |index| unsafe { &*self.entry_ptr(index) })
     }
 
     pub fn push(
         &mut self,
         value: ConventionalMemoryMapEntry,
     ) -> Result<(), ConventionalMemoryMapEntry> {
-        if self.len >= self.cap {
+        if self.len >= self.capacity() {
             return Err(value);
         }
         unsafe {
-            core::ptr::write(self.ptr.as_ptr().add(self.len), value);
+            self.entry_ptr(self.len).write(value);
         }
         self.len += 1;
         Ok(())
@@ -667,34 +1323,44 @@ impl MemMapTable {
         index: usize,
         value: ConventionalMemoryMapEntry,
     ) -> Result<(), ConventionalMemoryMapEntry> {
-        if self.len >= self.cap || index >= self.len {
+        if self.len >= self.capacity() || index > self.len {
             return Err(value);
         }
         unsafe {
-            core::ptr::copy(
-                self.ptr.as_ptr().add(index),
-                self.ptr.as_ptr().add(index + 1),
-                self.len - index,
-            );
-            core::ptr::write(self.ptr.as_ptr().add(index), value);
+            for i in (index..self.len).rev() {
+                self.entry_ptr(i + 1).write(self.entry_ptr(i).read());
+            }
+            self.entry_ptr(index).write(value);
         }
         self.len += 1;
         Ok(())
     }
+
+    pub fn remove(&mut self, index: usize) -> ConventionalMemoryMapEntry {
+        assert!(index < self.len);
+        unsafe {
+            let value = self.entry_ptr(index).read();
+            for i in index + 1..self.len {
+                self.entry_ptr(i - 1).write(self.entry_ptr(i).read());
+            }
+            self.len -= 1;
+            value
+        }
+    }
 }
 
-impl Deref for MemMapTable {
-    type Target = [ConventionalMemoryMapEntry];
+impl Index<usize> for MemMapTable {
+    type Output = ConventionalMemoryMapEntry;
 
     #[inline]
-    fn deref(&self) -> &[ConventionalMemoryMapEntry] {
-        self.as_slice()
+    fn index(&self, index: usize) -> &ConventionalMemoryMapEntry {
+        self.get(index).unwrap()
     }
 }
 
-impl DerefMut for MemMapTable {
+impl IndexMut<usize> for MemMapTable {
     #[inline]
-    fn deref_mut(&mut self) -> &mut [ConventionalMemoryMapEntry] {
-        self.as_mut_slice()
+    fn index_mut(&mut self, index: usize) -> &mut ConventionalMemoryMapEntry {
+        self.get_mut(index).unwrap()
     }
 }
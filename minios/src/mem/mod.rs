@@ -1,4 +1,5 @@
 pub mod global_alloc;
+pub mod heap;
 pub mod mmio;
 
 mod mm;
@@ -25,6 +26,84 @@ pub enum MemoryAllocationStrategy {
     LastFit,
 }
 
+/// Physical address range that an allocation must fit in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressConstraint {
+    start: u64,
+    end: u64,
+}
+
+impl AddressConstraint {
+    const _4G: u64 = 0x1_0000_0000;
+
+    #[inline]
+    pub const fn new(range: Range<u64>) -> Self {
+        Self {
+            start: range.start,
+            end: range.end,
+        }
+    }
+
+    /// No constraint
+    #[inline]
+    pub const fn any() -> Self {
+        Self::new(0..u64::MAX)
+    }
+
+    /// Below 16MB, for ISA DMA
+    #[inline]
+    pub const fn below_16m() -> Self {
+        Self::new(0..0x100_0000)
+    }
+
+    /// Below 4GB, for 32-bit devices
+    #[inline]
+    pub const fn below_4g() -> Self {
+        Self::new(0..Self::_4G)
+    }
+
+    /// Above 4GB, high memory only
+    #[inline]
+    pub const fn above_4g() -> Self {
+        Self::new(Self::_4G..u64::MAX)
+    }
+
+    #[inline]
+    pub const fn start(&self) -> u64 {
+        self.start
+    }
+
+    #[inline]
+    pub const fn end(&self) -> u64 {
+        self.end
+    }
+
+    #[inline]
+    pub const fn range(&self) -> Range<u64> {
+        self.start..self.end
+    }
+
+    #[inline]
+    pub fn intersection(&self, other: Self) -> Self {
+        Self {
+            start: self.start.max(other.start),
+            end: self.end.min(other.end),
+        }
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+}
+
+impl Default for AddressConstraint {
+    #[inline]
+    fn default() -> Self {
+        Self::any()
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, PartialEq, Eq)]
 pub struct MemoryMapEntry {
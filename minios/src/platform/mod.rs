@@ -15,7 +15,7 @@ pub mod rv_sbi;
 #[cfg(feature = "sbi")]
 pub use rv_sbi as current;
 
-use core::fmt;
+use core::{fmt, time::Duration};
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
@@ -61,4 +61,10 @@ pub trait PlatformTrait {
     unsafe fn exit();
 
     fn reset_system() -> !;
+
+    /// Returns the time elapsed since the timer was started
+    fn monotonic() -> Duration;
+
+    /// Waits for the next timer event or interrupt, but no later than the deadline
+    fn idle(deadline: Duration);
 }
@@ -7,6 +7,7 @@ use core::{
     cell::UnsafeCell,
     mem::MaybeUninit,
     sync::atomic::{Ordering, compiler_fence},
+    time::Duration,
 };
 
 pub mod fb;
@@ -66,7 +67,6 @@ impl PlatformTrait for Platform {
             let _end = PhysicalAddress::new(_end);
             boot_info.start_conventional_memory =
                 _end.rounding_up(MemoryManager::PAGE_SIZE).as_repr() as u32;
-            boot_info.conventional_memory_size = 0x40_0000;
 
             {
                 let currentel: usize;
@@ -102,6 +102,16 @@ impl PlatformTrait for Platform {
     fn reset_system() -> ! {
         todo!()
     }
+
+    #[inline]
+    fn monotonic() -> Duration {
+        timer::monotonic()
+    }
+
+    #[inline]
+    fn idle(_deadline: Duration) {
+        Hal::cpu().no_op();
+    }
 }
 
 // #[inline]
@@ -1,4 +1,5 @@
 use crate::mem::mmio::Mmio32;
+use core::{arch::asm, time::Duration};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
@@ -19,10 +20,10 @@ unsafe impl Mmio32 for SystemTimer {
     }
 }
 
-#[allow(dead_code)]
 impl SystemTimer {
+    /// Returns the free running 1MHz counter
     #[inline]
-    fn get() -> u64 {
+    pub fn get() -> u64 {
         unsafe {
             loop {
                 let hi = Self::Hi.read();
@@ -35,3 +36,37 @@ impl SystemTimer {
         }
     }
 }
+
+/// ARM Generic Timer
+pub struct GenericTimer;
+
+impl GenericTimer {
+    #[inline]
+    pub fn frequency() -> u64 {
+        let result: u64;
+        unsafe {
+            asm!("mrs {}, cntfrq_el0", out(reg) result);
+        }
+        result
+    }
+
+    #[inline]
+    pub fn ticks() -> u64 {
+        let result: u64;
+        unsafe {
+            asm!("isb", "mrs {}, cntpct_el0", out(reg) result);
+        }
+        result
+    }
+}
+
+/// Returns the time elapsed since the timer was started
+pub fn monotonic() -> Duration {
+    let freq = GenericTimer::frequency();
+    if freq != 0 {
+        let ticks = GenericTimer::ticks();
+        Duration::new(ticks / freq, ((ticks % freq) * 1_000_000_000 / freq) as u32)
+    } else {
+        Duration::from_micros(SystemTimer::get())
+    }
+}
@@ -5,17 +5,34 @@ use crate::{
     arch::{cpu, csr::CSR},
     *,
 };
-use core::{arch::naked_asm, ffi::c_void};
+use core::{
+    arch::naked_asm,
+    ffi::c_void,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
 mod sbi_console;
+pub mod timer;
 
 unsafe extern "C" {
     unsafe static _end: c_void;
 }
 
+static BOOT_HART_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl Platform {
+    /// Returns the ID of the hart that minios was started on
+    #[inline]
+    pub fn boot_hart_id() -> usize {
+        BOOT_HART_ID.load(Ordering::Relaxed)
+    }
+}
+
 impl PlatformTrait for Platform {
     unsafe fn init_dt_early(dt: &fdt::DeviceTree, arg: usize) {
         let hart_id = arg;
+        BOOT_HART_ID.store(hart_id, Ordering::Relaxed);
         unsafe {
             sbi_console::SbiConsole::init();
             System::set_stdin(sbi_console::SbiConsole::shared());
@@ -41,13 +58,14 @@ impl PlatformTrait for Platform {
             let end = PhysicalAddress::new(&_end as *const _ as PhysicalAddressRepr);
             boot_info.start_conventional_memory =
                 end.rounding_up(mem::MemoryManager::PAGE_SIZE).as_repr() as u32;
-            boot_info.conventional_memory_size = 0x40_0000;
 
             println!("Model: {}", dt.root().model());
             for item in dt.root().compatible().unwrap() {
                 println!("compatible: {}", item);
             }
 
+            timer::Timer::init_dt(dt);
+
             CSR::STVEC.write(_arch_stvec as *const () as usize);
             CSR::SIE.set(1 << 5);
             sbi::legacy::set_timer(1);
@@ -69,6 +87,19 @@ impl PlatformTrait for Platform {
     fn reset_system() -> ! {
         sbi::legacy::shutdown();
     }
+
+    #[inline]
+    fn monotonic() -> Duration {
+        timer::Timer::monotonic()
+    }
+
+    fn idle(deadline: Duration) {
+        // Interrupts are not delivered, but a pending STIP still wakes up the WFI
+        sbi::legacy::set_timer(timer::Timer::duration_to_ticks(deadline));
+        Hal::cpu().wait_for_interrupt();
+        // Keep the timer pending so that other WFI users are not blocked
+        sbi::legacy::set_timer(1);
+    }
 }
 
 #[unsafe(naked)]
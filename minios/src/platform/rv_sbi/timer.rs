@@ -0,0 +1,53 @@
+//! Timer based on the `time` CSR
+
+use crate::arch::csr::CSR;
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+static TIMEBASE_FREQUENCY: AtomicU64 = AtomicU64::new(Timer::DEFAULT_FREQUENCY);
+
+pub struct Timer;
+
+impl Timer {
+    /// Used when the Device Tree does not provide `timebase-frequency`
+    pub const DEFAULT_FREQUENCY: u64 = 10_000_000;
+
+    pub(super) fn init_dt(dt: &fdt::DeviceTree) {
+        if let Some(freq) = dt
+            .root()
+            .cpus()
+            .and_then(|cpus| cpus.get_prop_u32(fdt::PropName::TIMEBASE_FREQUENCY))
+            .filter(|&v| v != 0)
+        {
+            TIMEBASE_FREQUENCY.store(freq as u64, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    pub fn frequency() -> u64 {
+        TIMEBASE_FREQUENCY.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn ticks() -> u64 {
+        CSR::rdtime() as u64
+    }
+
+    #[inline]
+    pub fn monotonic() -> Duration {
+        let freq = Self::frequency();
+        let ticks = Self::ticks();
+        Duration::new(ticks / freq, ((ticks % freq) * 1_000_000_000 / freq) as u32)
+    }
+
+    /// Converts the duration to the timer ticks, rounding up
+    pub fn duration_to_ticks(duration: Duration) -> u64 {
+        let freq = Self::frequency();
+        duration
+            .as_secs()
+            .saturating_mul(freq)
+            .saturating_add((duration.subsec_nanos() as u64 * freq).div_ceil(1_000_000_000))
+    }
+}
@@ -0,0 +1,95 @@
+//! Common part of the Disk Bios Drivers
+//!
+//! The disk BIOS can only transfer to the low memory,
+//! so the data is copied through a bounce buffer of a page.
+
+use crate::arch::lomem::{LoMemoryManager, ManagedLowMemory};
+use crate::*;
+
+/// Block device through the disk BIOS
+///
+/// Drivers implement the addressing and the error mapping in [`BiosDisk::transfer`].
+pub(super) trait BiosDisk {
+    fn media(&self) -> &MediaInfo;
+
+    /// Transfers blocks between the disk and the buffer in low memory
+    fn transfer(
+        &mut self,
+        is_write: bool,
+        block: u64,
+        count: usize,
+        buffer: &ManagedLowMemory,
+    ) -> Result<(), BlockIoError>;
+
+    /// Returns the number of blocks to transfer at once from the block
+    fn max_blocks_at(&self, block: u64) -> usize {
+        let _ = block;
+        LoMemoryManager::PAGE_SIZE / self.media().block_size as usize
+    }
+
+    /// Returns the number of blocks if the range is on the media
+    fn check_range(&self, block: LBA, len: usize) -> Result<usize, BlockIoError> {
+        let media = self.media();
+        let block_size = media.block_size as usize;
+        if !len.is_multiple_of(block_size) {
+            return Err(BlockIoError::InvalidParameter);
+        }
+        let count = len / block_size;
+        match block.0.checked_add(count as u64) {
+            Some(end) if end <= media.block_count.0 => Ok(count),
+            _ => Err(BlockIoError::InvalidParameter),
+        }
+    }
+
+    fn read_blocks(&mut self, block: LBA, buf: &mut [u8]) -> Result<(), BlockIoError> {
+        let count = self.check_range(block, buf.len())?;
+        let block_size = self.media().block_size as usize;
+        let buffer = LoMemoryManager::alloc_page();
+        let mut index = 0;
+        while index < count {
+            let lba = block.0 + index as u64;
+            let n_blocks = self.max_blocks_at(lba).min(count - index);
+            self.transfer(false, lba, n_blocks, &buffer)?;
+            let len = n_blocks * block_size;
+            buf[index * block_size..][..len].copy_from_slice(&buffer.as_slice()[..len]);
+            index += n_blocks;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, block: LBA, buf: &[u8]) -> Result<(), BlockIoError> {
+        if self.media().is_read_only() {
+            return Err(BlockIoError::WriteProtected);
+        }
+        let count = self.check_range(block, buf.len())?;
+        let block_size = self.media().block_size as usize;
+        let buffer = LoMemoryManager::alloc_page();
+        let mut index = 0;
+        while index < count {
+            let lba = block.0 + index as u64;
+            let n_blocks = self.max_blocks_at(lba).min(count - index);
+            let len = n_blocks * block_size;
+            buffer.as_slice()[..len].copy_from_slice(&buf[index * block_size..][..len]);
+            self.transfer(true, lba, n_blocks, &buffer)?;
+            index += n_blocks;
+        }
+        Ok(())
+    }
+}
+
+/// Media information of the drive number of the disk BIOS
+#[inline]
+pub(super) const fn make_media_info(
+    drive: u8,
+    flags: u32,
+    block_size: u32,
+    block_count: u64,
+) -> MediaInfo {
+    MediaInfo {
+        media_id: MediaId(drive as u32),
+        flags,
+        block_size,
+        io_align: 0,
+        block_count: LBA(block_count),
+    }
+}
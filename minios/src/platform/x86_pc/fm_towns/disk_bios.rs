@@ -0,0 +1,308 @@
+//! Disk Bios Driver
+
+use super::bios;
+use crate::arch::lomem::{LoMemoryManager, ManagedLowMemory};
+use crate::arch::vm86::{VM86, X86StackContext};
+use crate::io::media::{cache::CachedBlockDevice, partition::SharedBlockDevice};
+use crate::platform::x86_pc::bios_disk::{BiosDisk, make_media_info};
+use crate::sync::spin::SpinMutex;
+use crate::*;
+use alloc::format;
+use x86::gpr::Flags;
+
+pub(super) struct DiskBios {
+    //
+}
+
+impl DiskBios {
+    /// Registers the boot drive, as the disk BIOS has no equipment list to enumerate
+    #[inline(never)]
+    pub unsafe fn init() {
+        unsafe {
+            let boot_drive = System::boot_info().bios_boot_drive;
+            let device = boot_drive.0;
+            let unit = device & 0x0f;
+            let (name, device) = match device & 0xf0 {
+                Int93Device::DEVICE_FDD => (format!("fd{}", unit), Int93Device::new_floppy(device)),
+                Int93Device::DEVICE_HDD => (format!("hd{}", unit), Int93Device::new_hdd(device)),
+                Int93Device::DEVICE_CDROM => {
+                    (format!("cd{}", unit), Int93Device::new_cdrom(device))
+                }
+                _ => return,
+            };
+            let Some(device) = device else {
+                return;
+            };
+            let device = CachedBlockDevice::new(Box::new(device));
+            let device: SharedBlockDevice = Arc::new(SpinMutex::new(Box::new(device)));
+            System::register_block_device(&name, device, None, true);
+        }
+    }
+}
+
+/// How the device is addressed by the disk BIOS
+#[derive(Debug, Clone, Copy)]
+enum Addressing {
+    /// Floppy disks are addressed by cylinder, head and sector
+    Chs { heads: u32, sectors_per_track: u32 },
+    /// Hard disks and CD-ROMs are addressed by the sector number
+    Lba,
+}
+
+/// Block device through INT 93h
+struct Int93Device {
+    device: u8,
+    media_info: MediaInfo,
+    addressing: Addressing,
+}
+
+impl Int93Device {
+    const DEVICE_FDD: u8 = 0x20;
+    const DEVICE_HDD: u8 = 0xb0;
+    const DEVICE_CDROM: u8 = 0xc0;
+
+    const CMD_RECALIBRATE: u8 = 0x03;
+    const CMD_READ: u8 = 0x05;
+    const CMD_WRITE: u8 = 0x06;
+
+    /// 2HD 1232KB, the native format of FM TOWNS
+    const DEFAULT_FLOPPY_GEOMETRY: (u32, u32, u32, u32) = (1024, 77, 2, 8);
+    const HDD_BLOCK_SIZE: u32 = 512;
+    const CDROM_BLOCK_SIZE: u32 = 2048;
+    /// Sector numbers are passed in 24 bits
+    const MAX_LBA: u64 = 0x00ff_ffff;
+
+    const MAX_RETRY: usize = 3;
+
+    fn new_floppy(device: u8) -> Option<Self> {
+        let (block_size, cylinders, heads, sectors_per_track) = Self::DEFAULT_FLOPPY_GEOMETRY;
+        let mut this = Self {
+            device,
+            media_info: make_media_info(
+                device,
+                0,
+                block_size,
+                (cylinders * heads * sectors_per_track) as u64,
+            ),
+            addressing: Addressing::Chs {
+                heads,
+                sectors_per_track,
+            },
+        };
+
+        // The BIOS reads a sector of the size the media is formatted with
+        let buffer = LoMemoryManager::alloc_page();
+        this.transfer(false, 0, 1, &buffer).ok()?;
+        let boot_sector = buffer.as_slice();
+        let u16_at =
+            |offset: usize| u16::from_le_bytes([boot_sector[offset], boot_sector[offset + 1]]);
+        let bytes_per_sector = u16_at(0x0b) as u32;
+        let total_sectors = u16_at(0x13) as u32;
+        let sectors_per_track = u16_at(0x18) as u32;
+        let heads = u16_at(0x1a) as u32;
+        if bytes_per_sector.is_power_of_two()
+            && (128..=1024).contains(&bytes_per_sector)
+            && total_sectors > 0
+            && (1..=2).contains(&heads)
+            && (1..=36).contains(&sectors_per_track)
+        {
+            this.media_info.block_size = bytes_per_sector;
+            this.media_info.block_count = LBA(total_sectors as u64);
+            this.addressing = Addressing::Chs {
+                heads,
+                sectors_per_track,
+            };
+        }
+
+        Some(this)
+    }
+
+    fn new_hdd(device: u8) -> Option<Self> {
+        let mut this = Self {
+            device,
+            // The BIOS does not report the capacity, only the addressable range is known
+            media_info: make_media_info(
+                device,
+                MediaInfo::FLAG_UNKNOWN_CAPACITY,
+                Self::HDD_BLOCK_SIZE,
+                Self::MAX_LBA + 1,
+            ),
+            addressing: Addressing::Lba,
+        };
+        this.reset().ok()?;
+        Some(this)
+    }
+
+    fn new_cdrom(device: u8) -> Option<Self> {
+        let mut this = Self {
+            device,
+            media_info: make_media_info(
+                device,
+                MediaInfo::FLAG_READ_ONLY | MediaInfo::FLAG_UNKNOWN_CAPACITY,
+                Self::CDROM_BLOCK_SIZE,
+                Self::MAX_LBA + 1,
+            ),
+            addressing: Addressing::Lba,
+        };
+
+        // Takes the capacity from the ISO 9660 primary volume descriptor
+        let buffer = LoMemoryManager::alloc_page();
+        this.transfer(false, 16, 1, &buffer).ok()?;
+        let pvd = buffer.as_slice();
+        if pvd[0] == 0x01 && &pvd[1..6] == b"CD001" {
+            let volume_space_size = u32::from_le_bytes(pvd[80..84].try_into().unwrap());
+            if volume_space_size > 16 {
+                this.media_info.block_count = LBA(volume_space_size as u64);
+                this.media_info.flags &= !MediaInfo::FLAG_UNKNOWN_CAPACITY;
+            }
+        }
+
+        Some(this)
+    }
+}
+
+impl BiosDisk for Int93Device {
+    #[inline]
+    fn media(&self) -> &MediaInfo {
+        &self.media_info
+    }
+
+    fn max_blocks_at(&self, block: u64) -> usize {
+        let max_blocks = LoMemoryManager::PAGE_SIZE / self.media_info.block_size as usize;
+        match self.addressing {
+            Addressing::Chs {
+                sectors_per_track, ..
+            } => {
+                // Transfers do not cross the track
+                let remain = sectors_per_track as u64 - block % sectors_per_track as u64;
+                max_blocks.min(remain as usize)
+            }
+            Addressing::Lba => max_blocks,
+        }
+    }
+
+    fn transfer(
+        &mut self,
+        is_write: bool,
+        block: u64,
+        count: usize,
+        buffer: &ManagedLowMemory,
+    ) -> Result<(), BlockIoError> {
+        let command = if is_write {
+            Self::CMD_WRITE
+        } else {
+            Self::CMD_READ
+        };
+        let (ecx, edx) = match self.addressing {
+            Addressing::Chs {
+                heads,
+                sectors_per_track,
+            } => {
+                let sector = (block % sectors_per_track as u64) as u32;
+                let track = block / sectors_per_track as u64;
+                let head = (track % heads as u64) as u32;
+                let cylinder = track / heads as u64;
+                if cylinder > 0xff {
+                    return Err(BlockIoError::InvalidParameter);
+                }
+                // Sector numbers of floppy disks start from 1
+                (cylinder as u32, (head << 8) | (sector + 1))
+            }
+            Addressing::Lba => {
+                if block > Self::MAX_LBA {
+                    return Err(BlockIoError::InvalidParameter);
+                }
+                ((block >> 16) as u32, (block & 0xffff) as u32)
+            }
+        };
+
+        let mut regs = X86StackContext::default();
+        let mut result = Ok(());
+        for _ in 0..Self::MAX_RETRY {
+            regs.eax.set_d(((command as u32) << 8) | self.device as u32);
+            regs.ebx.set_d(count as u32);
+            regs.ecx.set_d(ecx);
+            regs.edx.set_d(edx);
+            regs.edi.set_d(0);
+            unsafe {
+                regs.set_vmds(buffer.sel());
+                VM86::call_bios(bios::INT93, &mut regs);
+            }
+            if !regs.eflags.contains(Flags::CF) && regs.eax.h() == 0 {
+                return Ok(());
+            }
+            let error = Int93Error::new(regs.eax.h(), regs.ecx.w());
+            result = Err(error.into());
+            match error {
+                Int93Error::NotReady | Int93Error::WriteProtected | Int93Error::ParameterError => {
+                    break;
+                }
+                _ => {
+                    let _ = self.reset();
+                }
+            }
+        }
+        result
+    }
+}
+
+impl BlockDevice for Int93Device {
+    fn reset(&mut self) -> Result<(), BlockIoError> {
+        let mut regs = X86StackContext::default();
+        regs.eax
+            .set_d(((Self::CMD_RECALIBRATE as u32) << 8) | self.device as u32);
+        unsafe {
+            VM86::call_bios(bios::INT93, &mut regs);
+        }
+        if regs.eflags.contains(Flags::CF) || regs.eax.h() != 0 {
+            Err(Int93Error::new(regs.eax.h(), regs.ecx.w()).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read(&mut self, block: LBA, buf: &mut [u8]) -> Result<(), BlockIoError> {
+        self.read_blocks(block, buf)
+    }
+
+    fn write(&mut self, block: LBA, buf: &[u8]) -> Result<(), BlockIoError> {
+        self.write_blocks(block, buf)
+    }
+
+    #[inline]
+    fn media_info(&self) -> MediaInfo {
+        self.media_info
+    }
+}
+
+/// Errors reported by the disk BIOS in AH, with the details in CX for hardware errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Int93Error {
+    ParameterError,
+    NotReady,
+    WriteProtected,
+    HardwareError,
+}
+
+impl Int93Error {
+    #[inline]
+    pub fn new(ah: u8, cx: u16) -> Self {
+        match ah {
+            0x02 => Self::ParameterError,
+            0x80 if cx & 0x0001 != 0 => Self::NotReady,
+            0x80 if cx & 0x0002 != 0 => Self::WriteProtected,
+            _ => Self::HardwareError,
+        }
+    }
+}
+
+impl From<Int93Error> for BlockIoError {
+    fn from(value: Int93Error) -> Self {
+        match value {
+            Int93Error::ParameterError => BlockIoError::InvalidParameter,
+            Int93Error::NotReady => BlockIoError::NoMedia,
+            Int93Error::WriteProtected => BlockIoError::WriteProtected,
+            Int93Error::HardwareError => BlockIoError::DeviceError,
+        }
+    }
+}
@@ -115,6 +115,10 @@ impl SimpleTextInput for FmtKbd {
     fn read_key_stroke(&mut self) -> Option<NonZeroInputKey> {
         self.last_key_data.take()
     }
+
+    fn modifiers(&mut self) -> Modifier {
+        self.key_modifier
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
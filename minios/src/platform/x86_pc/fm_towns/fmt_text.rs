@@ -5,11 +5,14 @@ use crate::{
     arch::cpu::Cpu,
     io::{
         graphics::color::IndexedColor,
-        tty::{SimpleTextOutput, SimpleTextOutputMode},
+        tty::{
+            SimpleTextOutput, SimpleTextOutputMode,
+            ansi::{AnsiScreen, AnsiState},
+        },
     },
     platform::x86_pc::fm_towns::crtc::Crtc,
 };
-use core::{cell::UnsafeCell, mem::transmute};
+use core::{cell::UnsafeCell, mem::transmute, ops::Range};
 use x86::isolated_io::*;
 
 const TVRAM_OFFSET_MASK: usize = 0x0003_ffff / 4;
@@ -66,6 +69,7 @@ pub struct FmtText {
     bg_color_u32: u32,
     tvram_offset: usize,
     tvram_crtc_fa1: u16,
+    ansi: AnsiState,
 }
 
 static mut FMT_TEXT: UnsafeCell<FmtText> = UnsafeCell::new(FmtText {
@@ -81,6 +85,7 @@ static mut FMT_TEXT: UnsafeCell<FmtText> = UnsafeCell::new(FmtText {
     bg_color_u32: 0,
     tvram_offset: 0,
     tvram_crtc_fa1: 0,
+    ansi: AnsiState::new(),
 });
 
 impl FmtText {
@@ -157,34 +162,20 @@ impl FmtText {
         row as usize * 512 / 4 * 16 as usize + col as usize
     }
 
-    fn adjust_coords(&mut self, col: u8, row: u8, wrap_around: bool) -> Option<(u8, u8)> {
-        if row < self.mode.rows && (!wrap_around || col < self.mode.columns) {
-            return None;
-        }
-
-        let mut col = col;
-        let mut row = row;
-
-        if wrap_around {
-            while col >= self.mode.columns {
-                col -= self.mode.columns;
-                row += 1;
-            }
-        }
-
-        while row >= self.mode.rows {
-            unsafe {
-                let vram = self.get_vram().add(self.tvram_offset & TVRAM_OFFSET_MASK);
-                Cpu::rep_stosd(vram, self.bg_color_u32, 512 * 16 / 4);
-                self.tvram_offset = (self.tvram_offset + 512 * 16 / 4) & TVRAM_OFFSET_MASK;
-
-                self.tvram_crtc_fa1 = self.tvram_crtc_fa1 + 1024 * 16 / 8;
-                self.set_hw_scroll(self.tvram_crtc_fa1);
-            }
-            row -= 1;
+    /// Returns the pointer to the first scanline of the row
+    #[inline]
+    fn row_ptr(&self, row: u8) -> *mut u32 {
+        unsafe {
+            self.get_vram()
+                .add((self.pos(0, row) + self.tvram_offset) & TVRAM_OFFSET_MASK)
         }
+    }
 
-        Some((col, row))
+    /// Scrolls the whole screen up by a row using the hardware scroll
+    fn hw_scroll_up(&mut self) {
+        self.tvram_offset = (self.tvram_offset + 512 * 16 / 4) & TVRAM_OFFSET_MASK;
+        self.tvram_crtc_fa1 = self.tvram_crtc_fa1.wrapping_add(1024 * 16 / 8);
+        self.set_hw_scroll(self.tvram_crtc_fa1);
     }
 
     fn octuple(value: u8) -> u32 {
@@ -194,32 +185,40 @@ impl FmtText {
 }
 
 impl core::fmt::Write for FmtText {
+    #[inline]
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        let old_cursor_visible = self.enable_cursor(false);
+        AnsiState::write_str(self, s);
+        Ok(())
+    }
+}
+
+impl AnsiScreen for FmtText {
+    #[inline]
+    fn ansi_state(&mut self) -> &mut AnsiState {
+        &mut self.ansi
+    }
+
+    #[inline]
+    fn mode_mut(&mut self) -> &mut SimpleTextOutputMode {
+        &mut self.mode
+    }
 
+    fn put_str(&mut self, s: &str) {
         let mut col = self.mode.cursor_column;
         let mut row = self.mode.cursor_row;
-        let mut pos = self.pos(col, row);
 
         for ch in s.chars() {
             match ch {
                 '\n' => {
                     col = 0;
-                    row += 1;
-                    if let Some((new_col, new_row)) = self.adjust_coords(col, row, false) {
-                        col = new_col;
-                        row = new_row;
-                    }
-                    pos = self.pos(col, row);
+                    row = self.line_feed(row);
                 }
                 '\r' => {
                     col = 0;
-                    pos = self.pos(col, row);
                 }
                 '\x08' => {
                     if col > 0 {
                         col -= 1;
-                        pos -= 1;
                     }
                 }
                 _ => {
@@ -229,16 +228,13 @@ impl core::fmt::Write for FmtText {
                         b'?' as u32
                     };
 
-                    if let Some((new_col, new_row)) = self.adjust_coords(col, row, true) {
-                        col = new_col;
-                        row = new_row;
-                        pos = self.pos(col, row);
+                    if col >= self.mode.columns {
+                        col = 0;
+                        row = self.line_feed(row);
                     }
 
                     unsafe {
-                        let mut vram = self
-                            .get_vram()
-                            .add((pos + self.tvram_offset) & TVRAM_OFFSET_MASK);
+                        let mut vram = self.row_ptr(row).add(col as usize);
 
                         if true {
                             let font_data = self.get_base_font16().add(ch as usize * 16);
@@ -268,27 +264,70 @@ impl core::fmt::Write for FmtText {
                     }
 
                     col += 1;
-                    pos += 1;
                 }
             }
         }
 
-        if let Some((new_col, new_row)) = self.adjust_coords(col, row, false) {
-            col = new_col;
-            row = new_row;
-        }
         self.mode.cursor_column = col;
         self.mode.cursor_row = row;
-        if old_cursor_visible {
-            self.enable_cursor(old_cursor_visible);
+    }
+
+    fn erase(&mut self, row: u8, cols: Range<u8>) {
+        let cols = cols.start..cols.end.min(self.mode.columns);
+        if row >= self.mode.rows || cols.is_empty() {
+            return;
+        }
+        unsafe {
+            let mut vram = self.row_ptr(row).add(cols.start as usize);
+            for _ in 0..16 {
+                Cpu::rep_stosd(vram, self.bg_color_u32, cols.len());
+                vram = vram.add(512 / 4);
+            }
         }
+    }
 
-        Ok(())
+    fn scroll_region_up(&mut self, region: Range<u8>, lines: u8) {
+        let lines = lines.min(region.len() as u8);
+        if lines == 0 || region.end > self.mode.rows {
+            return;
+        }
+        if region.start == 0 && region.end == self.mode.rows {
+            for _ in 0..lines {
+                self.hw_scroll_up();
+            }
+        } else {
+            for row in region.start..region.end - lines {
+                unsafe {
+                    self.row_ptr(row + lines)
+                        .copy_to_nonoverlapping(self.row_ptr(row), 512 * 16 / 4);
+                }
+            }
+        }
+        for row in region.end - lines..region.end {
+            self.erase(row, 0..self.mode.columns);
+        }
+    }
+
+    fn scroll_region_down(&mut self, region: Range<u8>, lines: u8) {
+        let lines = lines.min(region.len() as u8);
+        if lines == 0 || region.end > self.mode.rows {
+            return;
+        }
+        for row in (region.start + lines..region.end).rev() {
+            unsafe {
+                self.row_ptr(row - lines)
+                    .copy_to_nonoverlapping(self.row_ptr(row), 512 * 16 / 4);
+            }
+        }
+        for row in region.start..region.start + lines {
+            self.erase(row, 0..self.mode.columns);
+        }
     }
 }
 
 impl SimpleTextOutput for FmtText {
     fn reset(&mut self) {
+        self.ansi.reset();
         self.set_attribute(0);
         self.mode.set_cursor_visible(true);
         self.clear_screen();
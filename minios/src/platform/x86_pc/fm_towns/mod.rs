@@ -6,10 +6,18 @@
 //!
 
 mod crtc;
+mod disk_bios;
 mod fmt_kbd;
 mod fmt_svga;
 mod fmt_text;
 
+mod bios {
+    use x86::prot::InterruptVector;
+
+    /// Disk BIOS Services
+    pub const INT93: InterruptVector = InterruptVector(0x93);
+}
+
 use crate::mem::{MemoryManager, MemoryType};
 use crate::platform::x86_pc::pic::Irq;
 use crate::*;
@@ -50,6 +58,8 @@ pub(super) unsafe fn init(_info: &SsblInfo) {
         // Irq(11).register(irq11).unwrap();
 
         fmt_svga::FmtSvga::init();
+
+        disk_bios::DiskBios::init();
     }
 }
 
@@ -6,10 +6,13 @@ use crate::{
         cpu::Cpu,
         vm86::{VM86, X86StackContext},
     },
-    io::tty::{SimpleTextOutput, SimpleTextOutputMode},
+    io::tty::{
+        SimpleTextOutput, SimpleTextOutputMode,
+        ansi::{AnsiScreen, AnsiState},
+    },
     platform::x86_pc::ibm_pc::bios::INT10,
 };
-use core::cell::UnsafeCell;
+use core::{cell::UnsafeCell, ops::Range};
 use x86::isolated_io::*;
 
 pub struct CgaText {
@@ -17,6 +20,7 @@ pub struct CgaText {
     max_scan_line: u8,
     attr_mask: u8,
     is_vga: bool,
+    ansi: AnsiState,
 }
 
 static mut CGA_TEXT: UnsafeCell<CgaText> = UnsafeCell::new(CgaText {
@@ -31,6 +35,7 @@ static mut CGA_TEXT: UnsafeCell<CgaText> = UnsafeCell::new(CgaText {
     max_scan_line: 0,
     attr_mask: 0x7f,
     is_vga: false,
+    ansi: AnsiState::new(),
 });
 
 impl CgaText {
@@ -102,102 +107,119 @@ impl CgaText {
         row as usize * self.mode.columns as usize + col as usize
     }
 
-    fn adjust_coords(&self, col: u8, row: u8, wrap_around: bool) -> Option<(u8, u8)> {
-        if row < self.mode.rows && (!wrap_around || col < self.mode.columns) {
-            return None;
-        }
-
-        let mut col = col;
-        let mut row = row;
-
-        if wrap_around {
-            while col >= self.mode.columns {
-                col -= self.mode.columns;
-                row += 1;
-            }
-        }
-
-        while row >= self.mode.rows {
-            unsafe {
-                let dst = self.get_vram() as *mut u32;
-                let (dst, _) = Cpu::rep_movsd(
-                    dst,
-                    dst.byte_offset(2 * self.mode.columns as isize),
-                    (self.mode.columns as usize * (self.mode.rows as usize - 1)) / 2,
-                );
-                Cpu::rep_stosd(
-                    dst,
-                    (0x20 | (self.mode.attribute as u32) << 8) * 0x10001,
-                    self.mode.columns as usize / 2,
-                );
-            }
-            row -= 1;
-        }
-
-        Some((col, row))
+    #[inline]
+    const fn blank(&self) -> u16 {
+        0x20 | (self.mode.attribute as u16) << 8
     }
 }
 
 impl core::fmt::Write for CgaText {
+    #[inline]
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        AnsiState::write_str(self, s);
+        Ok(())
+    }
+}
+
+impl AnsiScreen for CgaText {
+    #[inline]
+    fn ansi_state(&mut self) -> &mut AnsiState {
+        &mut self.ansi
+    }
+
+    #[inline]
+    fn mode_mut(&mut self) -> &mut SimpleTextOutputMode {
+        &mut self.mode
+    }
+
+    fn put_str(&mut self, s: &str) {
         let mut col = self.mode.cursor_column;
         let mut row = self.mode.cursor_row;
-        let mut pos = self.pos(col, row);
 
         for ch in s.chars() {
             match ch {
                 '\n' => {
                     col = 0;
-                    row += 1;
-                    if let Some((new_col, new_row)) = self.adjust_coords(col, row, false) {
-                        col = new_col;
-                        row = new_row;
-                    }
-                    pos = self.pos(col, row);
+                    row = self.line_feed(row);
                 }
                 '\r' => {
                     col = 0;
-                    pos = self.pos(col, row);
                 }
                 '\x08' => {
                     if col > 0 {
                         col -= 1;
-                        pos -= 1;
                     }
                 }
                 _ => {
                     let ch = if ch >= ' ' && ch < '\x7F' { ch } else { '?' };
 
-                    if let Some((new_col, new_row)) = self.adjust_coords(col, row, true) {
-                        col = new_col;
-                        row = new_row;
-                        pos = self.pos(col, row);
+                    if col >= self.mode.columns {
+                        col = 0;
+                        row = self.line_feed(row);
                     }
 
                     unsafe {
-                        let offset = pos as isize * 2;
+                        let offset = self.pos(col, row) as isize * 2;
                         let vram = self.get_vram().offset(offset);
                         vram.write_volatile(ch as u8);
                         vram.offset(1).write_volatile(self.mode.attribute);
                     }
 
                     col += 1;
-                    pos += 1;
                 }
             }
         }
 
-        if let Some((new_col, new_row)) = self.adjust_coords(col, row, false) {
-            col = new_col;
-            row = new_row;
-        }
         self.mode.cursor_column = col;
         self.mode.cursor_row = row;
-        if self.mode.is_cursor_visible() {
-            self.set_hw_cursor_position(col, row);
+    }
+
+    fn erase(&mut self, row: u8, cols: Range<u8>) {
+        let cols = cols.start..cols.end.min(self.mode.columns);
+        if row >= self.mode.rows {
+            return;
         }
+        let blank = self.blank();
+        unsafe {
+            let vram = self.get_vram() as *mut u16;
+            for col in cols {
+                vram.add(self.pos(col, row)).write_volatile(blank);
+            }
+        }
+    }
 
-        Ok(())
+    fn scroll_region_up(&mut self, region: Range<u8>, lines: u8) {
+        let lines = lines.min(region.len() as u8);
+        if lines == 0 || region.end > self.mode.rows {
+            return;
+        }
+        unsafe {
+            let vram = self.get_vram() as *mut u16;
+            vram.add(self.pos(0, region.start + lines)).copy_to(
+                vram.add(self.pos(0, region.start)),
+                self.pos(0, region.end - lines - region.start),
+            );
+        }
+        for row in region.end - lines..region.end {
+            self.erase(row, 0..self.mode.columns);
+        }
+    }
+
+    fn scroll_region_down(&mut self, region: Range<u8>, lines: u8) {
+        let lines = lines.min(region.len() as u8);
+        if lines == 0 || region.end > self.mode.rows {
+            return;
+        }
+        unsafe {
+            let vram = self.get_vram() as *mut u16;
+            vram.add(self.pos(0, region.start)).copy_to(
+                vram.add(self.pos(0, region.start + lines)),
+                self.pos(0, region.end - lines - region.start),
+            );
+        }
+        for row in region.start..region.start + lines {
+            self.erase(row, 0..self.mode.columns);
+        }
     }
 }
 
@@ -209,6 +231,7 @@ impl SimpleTextOutput for CgaText {
                 AttributeController::Mode.write(0x00);
             }
         }
+        self.ansi.reset();
         self.set_attribute(0);
         self.mode.set_cursor_visible(true);
         self.clear_screen();
@@ -1,7 +1,11 @@
 //! Disk Bios Driver
 
 use super::{bios, *};
-use arch::vm86::{VM86, X86StackContext};
+use crate::arch::lomem::ManagedLowMemory;
+use crate::io::media::{cache::CachedBlockDevice, partition::SharedBlockDevice};
+use crate::platform::x86_pc::bios_disk::{BiosDisk, make_media_info};
+use crate::sync::spin::SpinMutex;
+use alloc::format;
 use x86::{gpr::Flags, prot::Selector};
 
 pub(super) struct DiskBios {
@@ -11,105 +15,297 @@ pub(super) struct DiskBios {
 impl DiskBios {
     #[inline(never)]
     pub unsafe fn init() {
-        // let info = Environment::boot_info();
-        // unsafe {
-        //     let mut regs = X86StackContext::default();
-
-        //     println!("boot drive: {:02x}", info.bios_boot_drive.0);
-        //     print_disk_type(info.bios_boot_drive.0, &mut regs);
-
-        //     for i in 0..2 {
-        //         print_disk_type(i, &mut regs);
-        //     }
-
-        //     let p = 0x475 as *const u8;
-        //     let n_hdds = p.read_volatile();
-        //     println!("number of hard drive: {:02x}", n_hdds);
-        //     if n_hdds > 0 {
-        //         for i in 0..n_hdds {
-        //             print_disk_type(0x80 + i, &mut regs);
-        //         }
-        //     } else {
-        //         print_disk_type(0x80, &mut regs);
-        //     }
-        //     println!("");
-        // }
-    }
-}
+        unsafe {
+            let boot_drive = System::boot_info().bios_boot_drive;
 
-#[allow(dead_code)]
-fn print_disk_type(drive: u8, regs: &mut X86StackContext) {
-    let drive_type: u8;
+            // Equipment list in the BIOS data area
+            let equipment = (0x410 as *const u16).read_volatile();
+            let n_floppies = if equipment & 0x0001 != 0 {
+                ((equipment >> 6) & 0x03) as u8 + 1
+            } else {
+                0
+            };
+            let n_hdds = (0x475 as *const u8).read_volatile().min(0x20);
 
-    regs.eax.set_d(0x15ff);
-    regs.ecx.set_d(0xffff);
-    regs.edx.set_d(drive as u32);
-    unsafe {
-        VM86::call_bios(bios::INT13, regs);
-    }
-    if regs.eflags.contains(Flags::CF) {
-        println!("drive {:02x}: error {:02x}", drive, regs.eax.h());
-        return;
-    } else {
-        drive_type = regs.eax.h();
+            let mut is_boot_drive_found = false;
+            let drives = (0..n_floppies).chain(0x80..0x80 + n_hdds);
+            for drive in drives {
+                let drive = BiosDriveSpec(drive);
+                is_boot_drive_found |= drive == boot_drive;
+                Self::register(drive, drive == boot_drive);
+            }
+            // Boot from CD-ROM or other drives not in the list
+            if !is_boot_drive_found {
+                Self::register(boot_drive, true);
+            }
+        }
     }
 
-    regs.eax.set_d(0x0800);
-    regs.edx.set_d(drive as u32);
-    unsafe { regs.set_vmes(Selector::NULL) };
-    regs.edi.set_d(0);
-    unsafe {
-        VM86::call_bios(bios::INT13, regs);
-    }
-    if regs.eflags.contains(Flags::CF) {
-        println!("drive {:02x}: {:02x}", drive, drive_type);
-        return;
+    unsafe fn register(drive: BiosDriveSpec, is_boot_device: bool) {
+        let Some(device) = Int13Device::new(drive) else {
+            return;
+        };
+        let name = match drive.0 {
+            0x00..=0x7f => format!("fd{}", drive.0),
+            0x80..=0xdf => format!("hd{}", drive.0 - 0x80),
+            _ => format!("cd{}", drive.0 - 0xe0),
+        };
+        let device = CachedBlockDevice::new(Box::new(device));
+        let device: SharedBlockDevice = Arc::new(SpinMutex::new(Box::new(device)));
+        unsafe {
+            System::register_block_device(&name, device, None, is_boot_device);
+        }
     }
-
-    let chs = [regs.ecx.b(), regs.ecx.h(), regs.edx.h()];
-
-    println!(
-        "drive {:02x}: {:02x} {:02x} {:02x?} {:02x}",
-        drive,
-        drive_type,
-        regs.eax.h(),
-        chs,
-        regs.edx.b(),
-    );
 }
 
-#[allow(dead_code)]
+/// Block device through INT 13h
 struct Int13Device {
     drive_spec: BiosDriveSpec,
     media_info: MediaInfo,
+    /// Uses the INT 13h extensions (AH=42h/43h) instead of CHS
+    use_lba: bool,
+    heads: u32,
+    sectors_per_track: u32,
 }
 
 impl Int13Device {
-    // pub fn new() -> Self {
-    //     Self {}
-    // }
+    const DEFAULT_BLOCK_SIZE: u32 = 512;
+
+    /// Maximum number of sectors transferred at once by the INT 13h extensions
+    const MAX_LBA_TRANSFER: usize = 127;
+
+    const MAX_RETRY: usize = 3;
+
+    fn new(drive_spec: BiosDriveSpec) -> Option<Self> {
+        let drive = drive_spec.0;
+        let mut regs = X86StackContext::default();
+
+        // Check the INT 13h extensions for the fixed disk access
+        let mut use_lba = false;
+        if drive >= 0x80 {
+            regs.eax.set_d(0x4100);
+            regs.ebx.set_d(0x55aa);
+            regs.edx.set_d(drive as u32);
+            unsafe {
+                VM86::call_bios(bios::INT13, &mut regs);
+            }
+            use_lba = !regs.eflags.contains(Flags::CF)
+                && regs.ebx.w() == 0xaa55
+                && regs.ecx.w() & 0x0001 != 0;
+        }
+
+        if use_lba {
+            let buffer = LoMemoryManager::alloc_page();
+            let params = buffer.as_slice();
+            params[..0x1a].fill(0);
+            params[0..2].copy_from_slice(&0x1au16.to_le_bytes());
+            regs.eax.set_d(0x4800);
+            regs.edx.set_d(drive as u32);
+            regs.esi.set_d(0);
+            unsafe {
+                regs.set_vmds(buffer.sel());
+                VM86::call_bios(bios::INT13, &mut regs);
+            }
+            if !regs.eflags.contains(Flags::CF) {
+                let u32_at = |offset: usize| {
+                    u32::from_le_bytes(params[offset..offset + 4].try_into().unwrap())
+                };
+                let total_sectors = u64::from_le_bytes(params[0x10..0x18].try_into().unwrap());
+                let block_size = match u16::from_le_bytes([params[0x18], params[0x19]]) {
+                    0 => Self::DEFAULT_BLOCK_SIZE,
+                    v => v as u32,
+                };
+                if total_sectors > 0 && block_size as usize <= LoMemoryManager::PAGE_SIZE {
+                    return Some(Self {
+                        drive_spec,
+                        media_info: make_media_info(drive, 0, block_size, total_sectors),
+                        use_lba,
+                        heads: u32_at(0x08),
+                        sectors_per_track: u32_at(0x0c),
+                    });
+                }
+            }
+        }
+
+        // Falls back to CHS
+        regs.eax.set_d(0x0800);
+        regs.edx.set_d(drive as u32);
+        regs.edi.set_d(0);
+        unsafe {
+            regs.set_vmes(Selector::NULL);
+            VM86::call_bios(bios::INT13, &mut regs);
+        }
+        if regs.eflags.contains(Flags::CF) {
+            return None;
+        }
+        let sectors_per_track = (regs.ecx.b() & 0x3f) as u32;
+        let cylinders = (regs.ecx.h() as u32 | ((regs.ecx.b() as u32 & 0xc0) << 2)) + 1;
+        let heads = regs.edx.h() as u32 + 1;
+        if sectors_per_track == 0 {
+            return None;
+        }
+        let mut device = Self {
+            drive_spec,
+            media_info: make_media_info(
+                drive,
+                0,
+                Self::DEFAULT_BLOCK_SIZE,
+                cylinders as u64 * heads as u64 * sectors_per_track as u64,
+            ),
+            use_lba: false,
+            heads,
+            sectors_per_track,
+        };
+        // AH=08h reports the geometry of the drive, not of the inserted media
+        if drive < 0x80 {
+            device.detect_media_geometry();
+        }
+        Some(device)
+    }
+
+    /// Updates the geometry from the BPB of the boot sector if the media is formatted
+    fn detect_media_geometry(&mut self) {
+        let buffer = LoMemoryManager::alloc_page();
+        // The first sector can be read with any geometry
+        if self.transfer(false, 0, 1, &buffer).is_err() {
+            return;
+        }
+        let bpb = buffer.as_slice();
+        let u16_at = |offset: usize| u16::from_le_bytes([bpb[offset], bpb[offset + 1]]);
+        let bytes_per_sector = u16_at(0x0b) as u32;
+        let sectors_per_track = u16_at(0x18) as u32;
+        let heads = u16_at(0x1a) as u32;
+        let total_sectors = match u16_at(0x13) {
+            0 => u32::from_le_bytes(bpb[0x20..0x24].try_into().unwrap()),
+            v => v as u32,
+        };
+        if bytes_per_sector != Self::DEFAULT_BLOCK_SIZE
+            || !(1..=63).contains(&sectors_per_track)
+            || !(1..=255).contains(&heads)
+            || total_sectors == 0
+        {
+            return;
+        }
+        self.sectors_per_track = sectors_per_track;
+        self.heads = heads;
+        self.media_info.block_count = LBA(total_sectors as u64);
+    }
+}
+
+impl BiosDisk for Int13Device {
+    #[inline]
+    fn media(&self) -> &MediaInfo {
+        &self.media_info
+    }
+
+    fn max_blocks_at(&self, block: u64) -> usize {
+        let block_size = self.media_info.block_size as usize;
+        let max_blocks = LoMemoryManager::PAGE_SIZE / block_size;
+        if self.use_lba {
+            max_blocks.min(Self::MAX_LBA_TRANSFER)
+        } else {
+            // Some BIOSes cannot transfer across tracks
+            let remain = self.sectors_per_track as u64 - block % self.sectors_per_track as u64;
+            max_blocks.min(remain as usize)
+        }
+    }
+
+    fn transfer(
+        &mut self,
+        is_write: bool,
+        block: u64,
+        count: usize,
+        buffer: &ManagedLowMemory,
+    ) -> Result<(), BlockIoError> {
+        let drive = self.drive_spec.0 as u32;
+        // Disk address packet of the INT 13h extensions
+        let dap = self.use_lba.then(LoMemoryManager::alloc_page);
+        let mut regs = X86StackContext::default();
+        let mut result = Ok(());
+        for _ in 0..Self::MAX_RETRY {
+            if let Some(dap) = &dap {
+                let packet = dap.as_slice();
+                packet[0] = 0x10;
+                packet[1] = 0;
+                packet[2..4].copy_from_slice(&(count as u16).to_le_bytes());
+                packet[4..6].copy_from_slice(&0u16.to_le_bytes());
+                packet[6..8].copy_from_slice(&buffer.sel().0.to_le_bytes());
+                packet[8..16].copy_from_slice(&block.to_le_bytes());
+                regs.eax.set_d(if is_write { 0x4300 } else { 0x4200 });
+                regs.edx.set_d(drive);
+                regs.esi.set_d(0);
+                unsafe {
+                    regs.set_vmds(dap.sel());
+                }
+            } else {
+                let sector = (block % self.sectors_per_track as u64) as u32 + 1;
+                let track = block / self.sectors_per_track as u64;
+                let head = (track % self.heads as u64) as u32;
+                let cylinder = (track / self.heads as u64) as u32;
+                if cylinder > 0x3ff {
+                    return Err(BlockIoError::InvalidParameter);
+                }
+                regs.eax
+                    .set_d(if is_write { 0x0300 } else { 0x0200 } | count as u32);
+                regs.ecx
+                    .set_d(((cylinder & 0xff) << 8) | ((cylinder & 0x300) >> 2) | sector);
+                regs.edx.set_d((head << 8) | drive);
+                regs.ebx.set_d(0);
+                unsafe {
+                    regs.set_vmes(buffer.sel());
+                }
+            }
+            unsafe {
+                VM86::call_bios(bios::INT13, &mut regs);
+            }
+            if !regs.eflags.contains(Flags::CF) {
+                return Ok(());
+            }
+            let error = Int13ErrorCode::from_u8(regs.eax.h());
+            result = Err(error.into());
+            match error {
+                Int13ErrorCode::InvalidParameter
+                | Int13ErrorCode::WriteProtected
+                | Int13ErrorCode::DiskChanged
+                | Int13ErrorCode::TimedOut => break,
+                _ => {
+                    let _ = self.reset();
+                }
+            }
+        }
+        result
+    }
 }
 
-#[allow(dead_code)]
 impl BlockDevice for Int13Device {
     fn reset(&mut self) -> Result<(), BlockIoError> {
-        unimplemented!()
+        let mut regs = X86StackContext::default();
+        regs.eax.set_d(0x0000);
+        regs.edx.set_d(self.drive_spec.0 as u32);
+        unsafe {
+            VM86::call_bios(bios::INT13, &mut regs);
+        }
+        if regs.eflags.contains(Flags::CF) {
+            Err(Int13ErrorCode::from_u8(regs.eax.h()).into())
+        } else {
+            Ok(())
+        }
     }
 
-    fn read(&mut self, _block: LBA, _buf: &mut [u8]) -> Result<(), BlockIoError> {
-        unimplemented!()
+    fn read(&mut self, block: LBA, buf: &mut [u8]) -> Result<(), BlockIoError> {
+        self.read_blocks(block, buf)
     }
 
-    fn write(&mut self, _block: LBA, _buf: &[u8]) -> Result<(), BlockIoError> {
-        unimplemented!()
+    fn write(&mut self, block: LBA, buf: &[u8]) -> Result<(), BlockIoError> {
+        self.write_blocks(block, buf)
     }
 
+    #[inline]
     fn media_info(&self) -> MediaInfo {
-        unimplemented!()
+        self.media_info
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Int13ErrorCode {
     Success = 0,
@@ -130,7 +326,6 @@ enum Int13ErrorCode {
     UnknownError,
 }
 
-#[allow(dead_code)]
 impl Int13ErrorCode {
     #[inline]
     pub fn from_u8(code: u8) -> Self {
@@ -154,3 +349,17 @@ impl Int13ErrorCode {
         }
     }
 }
+
+impl From<Int13ErrorCode> for BlockIoError {
+    fn from(value: Int13ErrorCode) -> Self {
+        match value {
+            Int13ErrorCode::InvalidParameter => BlockIoError::InvalidParameter,
+            Int13ErrorCode::WriteProtected => BlockIoError::WriteProtected,
+            Int13ErrorCode::DiskChanged => BlockIoError::MediaChanged,
+            Int13ErrorCode::InvalidMedia
+            | Int13ErrorCode::TimedOut
+            | Int13ErrorCode::DriveNotReady => BlockIoError::NoMedia,
+            _ => BlockIoError::DeviceError,
+        }
+    }
+}
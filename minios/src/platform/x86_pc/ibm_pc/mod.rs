@@ -31,6 +31,7 @@ use crate::platform::x86_pc::pic::Irq;
 use crate::*;
 use acpi::{ACPI_10_TABLE_GUID, ACPI_20_TABLE_GUID, RsdPtr, RsdPtrV1};
 use core::{ffi::c_void, iter::Iterator, ops::Range};
+use libhid::Modifier;
 use smbios::{SMBIOS_GUID, SmBios};
 use x86::gpr::Eflags;
 
@@ -155,6 +156,7 @@ pub(super) unsafe fn init(_info: &SsblInfo) {
             smap_supported = true;
 
             let entry = &*(buf.as_slice().as_ptr() as *const SmapEntry);
+            (&mut *(&raw mut SMAP)).push(*entry);
             let range = entry.range();
             if let Some(mem_type) = entry.mem_type() {
                 if range.start < 0x10_0000 && range.end <= 0x10_0000 {
@@ -197,11 +199,22 @@ pub(super) unsafe fn exit() {
     // TODO:
 }
 
+/// Memory map reported by INT 15h AX=E820h
+static mut SMAP: Vec<SmapEntry> = Vec::new();
+
+/// Returns the memory map as reported by INT 15h AX=E820h, empty if it is not supported
+#[inline]
+pub fn smap<'a>() -> &'a [SmapEntry] {
+    unsafe { &*(&raw const SMAP) }
+}
+
+/// Address range descriptor of INT 15h AX=E820h, the same layout as `boot_e820_entry` of Linux
 #[repr(C, packed)]
-struct SmapEntry {
-    base: u64,
-    size: u64,
-    attr: u32,
+#[derive(Clone, Copy)]
+pub struct SmapEntry {
+    pub base: u64,
+    pub size: u64,
+    pub attr: u32,
 }
 
 impl SmapEntry {
@@ -247,4 +260,19 @@ impl SimpleTextInput for BiosTextInput {
             .into()
         }
     }
+
+    fn modifiers(&mut self) -> Modifier {
+        unsafe {
+            let mut regs = X86StackContext::default();
+            regs.eax.set_d(0x0200);
+            VM86::call_bios(bios::INT16, &mut regs);
+            let flags = regs.eax.b();
+            let mut modifier = Modifier::empty();
+            modifier.set(Modifier::RIGHT_SHIFT, (flags & 0x01) != 0);
+            modifier.set(Modifier::LEFT_SHIFT, (flags & 0x02) != 0);
+            modifier.set(Modifier::LEFT_CTRL, (flags & 0x04) != 0);
+            modifier.set(Modifier::LEFT_ALT, (flags & 0x08) != 0);
+            modifier
+        }
+    }
 }
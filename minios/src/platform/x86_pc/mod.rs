@@ -2,6 +2,7 @@ pub mod fm_towns;
 pub mod ibm_pc;
 pub mod nec98;
 
+mod bios_disk;
 mod pic;
 mod pit;
 
@@ -9,6 +10,7 @@ use super::{Platform, PlatformTrait};
 use crate::arch::{cpu, gdt, idt, lomem, vm86};
 use crate::mem::{MemoryManager, MemoryType};
 use crate::*;
+use core::time::Duration;
 use x86::isolated_io::{IoPortWB, LoIoPortRB, LoIoPortWB};
 
 impl PlatformTrait for Platform {
@@ -19,6 +21,14 @@ impl PlatformTrait for Platform {
             cpu::Cpu::init();
             lomem::LoMemoryManager::init();
 
+            // The SSBL places the initrd between the kernel and the conventional memory
+            if info.initrd_size > 0
+                && let Err(err) =
+                    System::set_initrd(info.initrd_base as usize, info.initrd_size as usize)
+            {
+                println!("INITRD: {:?}", err);
+            }
+
             MemoryManager::register_memmap(
                 0x10_0000..info.start_conventional_memory as u64,
                 MemoryType::Used,
@@ -93,4 +103,19 @@ impl PlatformTrait for Platform {
             Hal::cpu().halt();
         }
     }
+
+    #[inline]
+    fn monotonic() -> Duration {
+        pit::Pit::monotonic()
+    }
+
+    #[inline]
+    fn idle(_deadline: Duration) {
+        if unsafe { Hal::cpu().is_interrupt_enabled() } {
+            // The PIT interrupts every tick
+            Hal::cpu().wait_for_interrupt();
+        } else {
+            Hal::cpu().no_op();
+        }
+    }
 }
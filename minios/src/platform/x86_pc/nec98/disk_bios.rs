@@ -0,0 +1,387 @@
+//! Disk Bios Driver
+
+use super::{bios, *};
+use crate::arch::lomem::{LoMemoryManager, ManagedLowMemory};
+use crate::io::media::cache::CachedBlockDevice;
+use crate::io::media::partition::{ChsGeometry, SharedBlockDevice};
+use crate::platform::x86_pc::bios_disk::{BiosDisk, make_media_info};
+use crate::sync::spin::SpinMutex;
+use alloc::{format, vec};
+use x86::gpr::Flags;
+
+pub(super) struct DiskBios {
+    //
+}
+
+impl DiskBios {
+    #[inline(never)]
+    pub unsafe fn init() {
+        unsafe {
+            let boot_drive = System::boot_info().bios_boot_drive;
+
+            // Equipment flags in the BIOS work area
+            let disk_equip = (0x55c as *const u16).read_volatile();
+            let disk_equips = (0x482 as *const u8).read_volatile();
+
+            let mut drives = Vec::new();
+            for unit in 0..4 {
+                if disk_equip & (0x0001 << unit) != 0 {
+                    drives.push(DaUa::FDD_1MB | unit);
+                }
+            }
+            for unit in 0..4 {
+                if disk_equip & (0x0010 << unit) != 0 {
+                    drives.push(DaUa::FDD_640KB | unit);
+                }
+            }
+            for unit in 0..4 {
+                if disk_equip & (0x0100 << unit) != 0 {
+                    drives.push(DaUa::SASI_HDD | unit);
+                }
+            }
+            for unit in 0..7 {
+                if disk_equips & (0x01 << unit) != 0 {
+                    drives.push(DaUa::SCSI_HDD | unit);
+                }
+            }
+
+            for daua in drives {
+                // 1.44MB media are accessed as a different device type
+                let is_boot_device = daua == boot_drive.0
+                    || (daua & 0xf0 == DaUa::FDD_1MB
+                        && boot_drive.0 == DaUa::FDD_144MB | (daua & 0x0f));
+                let Some(device) = Int1BDevice::new(daua) else {
+                    continue;
+                };
+                // Hard disks may have the partition table of the PC-98 IPL
+                let geometry = (!DaUa::is_floppy(daua)).then_some(ChsGeometry {
+                    heads: device.heads,
+                    sectors_per_track: device.sectors_per_track,
+                });
+                let name = match daua & 0x70 {
+                    0x10 | 0x30 | 0x70 => format!("fd{}", daua & 0x0f),
+                    0x00 => format!("hd{}", daua & 0x0f),
+                    _ => format!("sd{}", daua & 0x0f),
+                };
+                let device = CachedBlockDevice::new(Box::new(device));
+                let device: SharedBlockDevice = Arc::new(SpinMutex::new(Box::new(device)));
+                System::register_block_device(&name, device, geometry, is_boot_device);
+            }
+        }
+    }
+}
+
+/// Device type (DA) and unit number (UA) of the disk BIOS
+struct DaUa;
+
+impl DaUa {
+    /// 640KB floppy disk drive (2DD)
+    const FDD_640KB: u8 = 0x70;
+    /// 1MB floppy disk drive (2HD)
+    const FDD_1MB: u8 = 0x90;
+    /// 1.44MB floppy disk in the 3-mode drive
+    const FDD_144MB: u8 = 0x30;
+    /// SASI or IDE hard disk
+    const SASI_HDD: u8 = 0x80;
+    /// SCSI hard disk
+    const SCSI_HDD: u8 = 0xa0;
+
+    #[inline]
+    const fn is_floppy(daua: u8) -> bool {
+        matches!(daua & 0x70, 0x10 | 0x30 | 0x70)
+    }
+}
+
+/// Block device through INT 1Bh
+struct Int1BDevice {
+    daua: u8,
+    media_info: MediaInfo,
+    heads: u32,
+    sectors_per_track: u32,
+    /// Sector length code of the floppy disk (128 << N)
+    sector_length_code: u8,
+}
+
+impl Int1BDevice {
+    /// MFM mode
+    const FDD_MFM: u8 = 0x40;
+    /// Seek before the transfer
+    const FDD_SEEK: u8 = 0x10;
+
+    const CMD_WRITE: u8 = 0x05;
+    const CMD_READ: u8 = 0x06;
+    const CMD_INITIALIZE: u8 = 0x03;
+    const CMD_READ_ID: u8 = 0x0a;
+    const CMD_NEW_SENSE: u8 = 0x84;
+
+    const MAX_RETRY: usize = 3;
+
+    fn new(daua: u8) -> Option<Self> {
+        if DaUa::is_floppy(daua) {
+            Self::new_floppy(daua).or_else(|| {
+                // The media may be 1.44MB
+                (daua & 0xf0 == DaUa::FDD_1MB)
+                    .then(|| Self::new_floppy(DaUa::FDD_144MB | (daua & 0x0f)))
+                    .flatten()
+            })
+        } else {
+            Self::new_hdd(daua)
+        }
+    }
+
+    fn new_hdd(daua: u8) -> Option<Self> {
+        let mut regs = X86StackContext::default();
+        regs.eax
+            .set_d(((Self::CMD_NEW_SENSE as u32) << 8) | daua as u32);
+        unsafe {
+            VM86::call_bios(bios::INT1B, &mut regs);
+        }
+        if regs.eflags.contains(Flags::CF) {
+            return None;
+        }
+        let block_size = regs.ebx.w() as u32;
+        let cylinders = regs.ecx.w() as u32;
+        let heads = regs.edx.h() as u32;
+        let sectors_per_track = regs.edx.b() as u32;
+        if block_size == 0
+            || block_size as usize > LoMemoryManager::PAGE_SIZE
+            || cylinders == 0
+            || heads == 0
+            || sectors_per_track == 0
+        {
+            return None;
+        }
+        Some(Self {
+            daua,
+            media_info: make_media_info(
+                daua,
+                0,
+                block_size,
+                cylinders as u64 * heads as u64 * sectors_per_track as u64,
+            ),
+            heads,
+            sectors_per_track,
+            sector_length_code: 0,
+        })
+    }
+
+    fn new_floppy(daua: u8) -> Option<Self> {
+        let mut regs = X86StackContext::default();
+        let mut result = None;
+        for _ in 0..Self::MAX_RETRY {
+            regs.eax
+                .set_d((((Self::FDD_MFM | Self::CMD_READ_ID) as u32) << 8) | daua as u32);
+            regs.ecx.set_d(0);
+            regs.edx.set_d(0);
+            unsafe {
+                VM86::call_bios(bios::INT1B, &mut regs);
+            }
+            if !regs.eflags.contains(Flags::CF) {
+                result = Some(regs.ecx.h());
+                break;
+            }
+        }
+        let sector_length_code = result?;
+        if sector_length_code > 3 {
+            return None;
+        }
+        let block_size = 128u32 << sector_length_code;
+
+        // Well-known formats, adjusted later by the BPB
+        let (cylinders, heads, sectors_per_track) = match (sector_length_code, daua & 0xf0) {
+            (3, _) => (77, 2, 8),
+            (2, DaUa::FDD_144MB) => (80, 2, 18),
+            (2, DaUa::FDD_1MB) => (80, 2, 15),
+            (2, _) => (80, 2, 8),
+            _ => (80, 2, 16),
+        };
+        let mut device = Self {
+            daua,
+            media_info: make_media_info(
+                daua,
+                0,
+                block_size,
+                cylinders as u64 * heads as u64 * sectors_per_track as u64,
+            ),
+            heads,
+            sectors_per_track,
+            sector_length_code,
+        };
+
+        let mut boot_sector = vec![0; block_size.max(512) as usize];
+        if device.read(LBA(0), &mut boot_sector).is_ok() {
+            let bytes_per_sector = u16::from_le_bytes([boot_sector[0x0b], boot_sector[0x0c]]);
+            let total_sectors = u16::from_le_bytes([boot_sector[0x13], boot_sector[0x14]]);
+            let sectors_per_track = u16::from_le_bytes([boot_sector[0x18], boot_sector[0x19]]);
+            let heads = u16::from_le_bytes([boot_sector[0x1a], boot_sector[0x1b]]);
+            if bytes_per_sector as u32 == block_size
+                && total_sectors > 0
+                && (1..=2).contains(&heads)
+                && (1..=36).contains(&sectors_per_track)
+            {
+                device.heads = heads as u32;
+                device.sectors_per_track = sectors_per_track as u32;
+                device.media_info.block_count = LBA(total_sectors as u64);
+            }
+        }
+
+        Some(device)
+    }
+}
+
+impl BiosDisk for Int1BDevice {
+    #[inline]
+    fn media(&self) -> &MediaInfo {
+        &self.media_info
+    }
+
+    fn max_blocks_at(&self, block: u64) -> usize {
+        let block_size = self.media_info.block_size as usize;
+        let max_blocks = LoMemoryManager::PAGE_SIZE / block_size;
+        if DaUa::is_floppy(self.daua) {
+            // Transfers do not cross the track without the multi-track flag
+            let remain = self.sectors_per_track as u64 - block % self.sectors_per_track as u64;
+            max_blocks.min(remain as usize)
+        } else {
+            max_blocks
+        }
+    }
+
+    fn transfer(
+        &mut self,
+        is_write: bool,
+        block: u64,
+        count: usize,
+        buffer: &ManagedLowMemory,
+    ) -> Result<(), BlockIoError> {
+        let sector = (block % self.sectors_per_track as u64) as u32;
+        let track = block / self.sectors_per_track as u64;
+        let head = (track % self.heads as u64) as u32;
+        let cylinder = (track / self.heads as u64) as u32;
+        if cylinder > 0xffff {
+            return Err(BlockIoError::InvalidParameter);
+        }
+        let command = if is_write {
+            Self::CMD_WRITE
+        } else {
+            Self::CMD_READ
+        };
+        let is_floppy = DaUa::is_floppy(self.daua);
+
+        let mut regs = X86StackContext::default();
+        let mut result = Ok(());
+        for _ in 0..Self::MAX_RETRY {
+            if is_floppy {
+                regs.eax.set_d(
+                    (((Self::FDD_MFM | Self::FDD_SEEK | command) as u32) << 8) | self.daua as u32,
+                );
+                regs.ecx
+                    .set_d(((self.sector_length_code as u32) << 8) | (cylinder & 0xff));
+                // Sector numbers of floppy disks start from 1
+                regs.edx.set_d((head << 8) | (sector + 1));
+            } else {
+                regs.eax.set_d(((command as u32) << 8) | self.daua as u32);
+                regs.ecx.set_d(cylinder);
+                regs.edx.set_d((head << 8) | sector);
+            }
+            regs.ebx
+                .set_d((count * self.media_info.block_size as usize) as u32);
+            regs.ebp.set_d(0);
+            unsafe {
+                regs.set_vmes(buffer.sel());
+                VM86::call_bios(bios::INT1B, &mut regs);
+            }
+            if !regs.eflags.contains(Flags::CF) {
+                return Ok(());
+            }
+            let error = Int1BErrorCode::from_u8(regs.eax.h());
+            result = Err(error.into());
+            match error {
+                Int1BErrorCode::NotWritable | Int1BErrorCode::NotReady => break,
+                _ => {
+                    let _ = self.reset();
+                }
+            }
+        }
+        result
+    }
+}
+
+impl BlockDevice for Int1BDevice {
+    fn reset(&mut self) -> Result<(), BlockIoError> {
+        let mut regs = X86StackContext::default();
+        regs.eax
+            .set_d(((Self::CMD_INITIALIZE as u32) << 8) | self.daua as u32);
+        unsafe {
+            VM86::call_bios(bios::INT1B, &mut regs);
+        }
+        if regs.eflags.contains(Flags::CF) {
+            Err(Int1BErrorCode::from_u8(regs.eax.h()).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read(&mut self, block: LBA, buf: &mut [u8]) -> Result<(), BlockIoError> {
+        self.read_blocks(block, buf)
+    }
+
+    fn write(&mut self, block: LBA, buf: &[u8]) -> Result<(), BlockIoError> {
+        self.write_blocks(block, buf)
+    }
+
+    #[inline]
+    fn media_info(&self) -> MediaInfo {
+        self.media_info
+    }
+}
+
+/// Status codes of the disk BIOS, the lower 4 bits may have additional information
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Int1BErrorCode {
+    DmaBoundary,
+    EndOfCylinder,
+    EquipmentCheck,
+    Overrun,
+    NotReady,
+    NotWritable,
+    Error,
+    TimedOut,
+    DataError,
+    NoData,
+    BadCylinder,
+    MissingAddressMark,
+}
+
+impl Int1BErrorCode {
+    #[inline]
+    pub fn from_u8(code: u8) -> Self {
+        match code & 0xf0 {
+            0x20 => Self::DmaBoundary,
+            0x30 => Self::EndOfCylinder,
+            0x40 => Self::EquipmentCheck,
+            0x50 => Self::Overrun,
+            0x60 => Self::NotReady,
+            0x70 => Self::NotWritable,
+            0x90 => Self::TimedOut,
+            0xa0 | 0xb0 => Self::DataError,
+            0xc0 => Self::NoData,
+            0xd0 => Self::BadCylinder,
+            0xe0 | 0xf0 => Self::MissingAddressMark,
+            _ => Self::Error,
+        }
+    }
+}
+
+impl From<Int1BErrorCode> for BlockIoError {
+    fn from(value: Int1BErrorCode) -> Self {
+        match value {
+            Int1BErrorCode::NotWritable => BlockIoError::WriteProtected,
+            Int1BErrorCode::NotReady | Int1BErrorCode::TimedOut => BlockIoError::NoMedia,
+            Int1BErrorCode::EquipmentCheck | Int1BErrorCode::EndOfCylinder => {
+                BlockIoError::InvalidParameter
+            }
+            _ => BlockIoError::DeviceError,
+        }
+    }
+}
@@ -5,6 +5,7 @@
 //! May not work or may need to be adjusted as it has not been fully verified on actual hardware.
 //!
 
+mod disk_bios;
 mod pc98_text;
 mod pegc;
 
@@ -14,7 +15,6 @@ mod bios {
     /// Video and keyboard BIOS Services
     pub const INT18: InterruptVector = InterruptVector(0x18);
 
-    #[allow(unused)]
     /// Disk BIOS Services
     pub const INT1B: InterruptVector = InterruptVector(0x1B);
 }
@@ -23,6 +23,7 @@ use crate::arch::vm86::{VM86, X86StackContext};
 use crate::mem::{MemoryManager, MemoryType};
 use crate::platform::x86_pc::pic::Irq;
 use crate::*;
+use libhid::Modifier;
 use x86::isolated_io::LoIoPortDummyB;
 
 pub static PORT_5F: LoIoPortDummyB<0x5F> = LoIoPortDummyB::new();
@@ -82,6 +83,8 @@ pub(super) unsafe fn init(_info: &SsblInfo) {
         System::set_stdin(kbd);
 
         pegc::PegcBios::init();
+
+        disk_bios::DiskBios::init();
     }
 }
 
@@ -93,6 +96,34 @@ static mut STDIN: BiosTextInput = BiosTextInput {};
 
 struct BiosTextInput;
 
+impl BiosTextInput {
+    /// Translates the key code of the PC-98 keyboard into the scan code of the PC BIOS
+    ///
+    /// Other keys only give the character, as their key codes mean different keys on the PC.
+    const fn scan_code(key_code: u8, unicode_char: u16) -> u16 {
+        match key_code {
+            0x00 => InputKey::SCAN_ESC,
+            0x0E => InputKey::SCAN_BACKSPACE,
+            0x0F => InputKey::SCAN_TAB,
+            0x1C => InputKey::SCAN_ENTER,
+            // ROLL UP scrolls to the next page
+            0x36 => InputKey::SCAN_PAGE_DOWN,
+            0x37 => InputKey::SCAN_PAGE_UP,
+            0x38 => InputKey::SCAN_INSERT,
+            0x39 => InputKey::SCAN_DELETE,
+            0x3A => InputKey::SCAN_UP,
+            0x3B => InputKey::SCAN_LEFT,
+            0x3C => InputKey::SCAN_RIGHT,
+            0x3D => InputKey::SCAN_DOWN,
+            // HOME/CLR
+            0x3E => InputKey::SCAN_HOME,
+            0x62..=0x6B => InputKey::SCAN_F1 + (key_code - 0x62) as u16,
+            _ if unicode_char != 0 => InputKey::SCAN_CHAR,
+            _ => 0,
+        }
+    }
+}
+
 impl SimpleTextInput for BiosTextInput {
     fn reset(&mut self) {
         while self.read_key_stroke().is_some() {}
@@ -109,11 +140,27 @@ impl SimpleTextInput for BiosTextInput {
 
             regs.eax.set_d(0);
             VM86::call_bios(bios::INT18, &mut regs);
+            let unicode_char = regs.eax.b() as u16;
             InputKey {
-                scan_code: regs.eax.h() as u16,
-                unicode_char: regs.eax.b() as u16,
+                scan_code: Self::scan_code(regs.eax.h(), unicode_char),
+                unicode_char,
             }
             .into()
         }
     }
+
+    fn modifiers(&mut self) -> Modifier {
+        unsafe {
+            let mut regs = X86StackContext::default();
+            regs.eax.set_d(0x0200);
+            VM86::call_bios(bios::INT18, &mut regs);
+            let flags = regs.eax.b();
+            let mut modifier = Modifier::empty();
+            modifier.set(Modifier::LEFT_SHIFT, (flags & 0x01) != 0);
+            // GRPH
+            modifier.set(Modifier::LEFT_ALT, (flags & 0x08) != 0);
+            modifier.set(Modifier::LEFT_CTRL, (flags & 0x10) != 0);
+            modifier
+        }
+    }
 }
@@ -2,11 +2,14 @@
 
 use crate::{
     arch::cpu::Cpu,
-    io::tty::{SimpleTextOutput, SimpleTextOutputMode},
+    io::tty::{
+        SimpleTextOutput, SimpleTextOutputMode,
+        ansi::{AnsiScreen, AnsiState},
+    },
     platform::x86_pc::nec98::PORT_5F,
     *,
 };
-use core::cell::UnsafeCell;
+use core::{cell::UnsafeCell, ops::Range};
 use x86::isolated_io::{LoIoPortRB, LoIoPortWB};
 
 const COLOR_TABLE: [u8; 8] = [0, 1, 4, 5, 2, 3, 6, 7];
@@ -21,6 +24,7 @@ pub struct Pc98Text {
     mode: SimpleTextOutputMode,
     line_height_m1: u8,
     native_attribute: u8,
+    ansi: AnsiState,
 }
 
 static mut PC98_TEXT: UnsafeCell<Pc98Text> = UnsafeCell::new(Pc98Text {
@@ -34,6 +38,7 @@ static mut PC98_TEXT: UnsafeCell<Pc98Text> = UnsafeCell::new(Pc98Text {
     },
     native_attribute: 0,
     line_height_m1: 15,
+    ansi: AnsiState::new(),
 });
 
 impl Pc98Text {
@@ -99,71 +104,43 @@ impl Pc98Text {
     const fn pos(&self, col: u8, row: u8) -> usize {
         row as usize * self.mode.columns as usize + col as usize
     }
+}
 
-    fn adjust_coords(&self, col: u8, row: u8, wrap_around: bool) -> Option<(u8, u8)> {
-        if row < self.mode.rows && (!wrap_around || col < self.mode.columns) {
-            return None;
-        }
-
-        let mut col = col;
-        let mut row = row;
-
-        if wrap_around {
-            while col >= self.mode.columns {
-                col -= self.mode.columns;
-                row += 1;
-            }
-        }
+impl core::fmt::Write for Pc98Text {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        AnsiState::write_str(self, s);
+        Ok(())
+    }
+}
 
-        while row >= self.mode.rows {
-            unsafe {
-                let count = self.mode.columns as usize * (self.mode.rows as usize - 1) / 2;
-                let dst = self.get_vram() as *mut u32;
-                let (dst, _) =
-                    Cpu::rep_movsd(dst, dst.byte_offset(2 * self.mode.columns as isize), count);
-                Cpu::rep_stosd(dst, 0x20_0020, self.mode.columns as usize / 2);
-
-                let dst = (self.get_vram() as *mut u32).byte_offset(0x2000);
-                let (dst, _) =
-                    Cpu::rep_movsd(dst, dst.byte_offset(2 * self.mode.columns as isize), count);
-                Cpu::rep_stosd(
-                    dst,
-                    (self.native_attribute as u32) * 0x01_0001,
-                    self.mode.columns as usize / 2,
-                );
-            }
-            row -= 1;
-        }
+impl AnsiScreen for Pc98Text {
+    #[inline]
+    fn ansi_state(&mut self) -> &mut AnsiState {
+        &mut self.ansi
+    }
 
-        Some((col, row))
+    #[inline]
+    fn mode_mut(&mut self) -> &mut SimpleTextOutputMode {
+        &mut self.mode
     }
-}
 
-impl core::fmt::Write for Pc98Text {
-    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+    fn put_str(&mut self, s: &str) {
         let mut col = self.mode.cursor_column;
         let mut row = self.mode.cursor_row;
-        let mut pos = self.pos(col, row);
 
         for ch in s.chars() {
             match ch {
                 '\n' => {
                     col = 0;
-                    row += 1;
-                    if let Some((new_col, new_row)) = self.adjust_coords(col, row, false) {
-                        col = new_col;
-                        row = new_row;
-                    }
-                    pos = self.pos(col, row);
+                    row = self.line_feed(row);
                 }
                 '\r' => {
                     col = 0;
-                    pos = self.pos(col, row);
                 }
                 '\x08' => {
                     if col > 0 {
                         col -= 1;
-                        pos -= 1;
                     }
                 }
                 _ => {
@@ -175,41 +152,83 @@ impl core::fmt::Write for Pc98Text {
                         b'?'
                     };
 
-                    if let Some((new_col, new_row)) = self.adjust_coords(col, row, true) {
-                        col = new_col;
-                        row = new_row;
-                        pos = self.pos(col, row);
+                    if col >= self.mode.columns {
+                        col = 0;
+                        row = self.line_feed(row);
                     }
 
                     unsafe {
-                        let offset = pos as isize * 2;
+                        let offset = self.pos(col, row) as isize * 2;
                         let vram = self.get_vram().offset(offset);
                         vram.offset(0x2000).write_volatile(self.native_attribute);
                         vram.write_volatile(ch);
                     }
 
                     col += 1;
-                    pos += 1;
                 }
             }
         }
 
-        if let Some((new_col, new_row)) = self.adjust_coords(col, row, false) {
-            col = new_col;
-            row = new_row;
-        }
         self.mode.cursor_column = col;
         self.mode.cursor_row = row;
-        if self.mode.is_cursor_visible() {
-            self.set_hw_cursor_position(col, row);
+    }
+
+    fn erase(&mut self, row: u8, cols: Range<u8>) {
+        let cols = cols.start..cols.end.min(self.mode.columns);
+        if row >= self.mode.rows {
+            return;
         }
+        unsafe {
+            let vram = self.get_vram() as *mut u16;
+            for col in cols {
+                let p = vram.add(self.pos(col, row));
+                p.write_volatile(0x0020);
+                p.byte_add(0x2000)
+                    .write_volatile(self.native_attribute as u16);
+            }
+        }
+    }
 
-        Ok(())
+    fn scroll_region_up(&mut self, region: Range<u8>, lines: u8) {
+        let lines = lines.min(region.len() as u8);
+        if lines == 0 || region.end > self.mode.rows {
+            return;
+        }
+        let count = self.pos(0, region.end - lines - region.start);
+        unsafe {
+            for plane in [0, 0x2000] {
+                let vram = self.get_vram().add(plane) as *mut u16;
+                vram.add(self.pos(0, region.start + lines))
+                    .copy_to(vram.add(self.pos(0, region.start)), count);
+            }
+        }
+        for row in region.end - lines..region.end {
+            self.erase(row, 0..self.mode.columns);
+        }
+    }
+
+    fn scroll_region_down(&mut self, region: Range<u8>, lines: u8) {
+        let lines = lines.min(region.len() as u8);
+        if lines == 0 || region.end > self.mode.rows {
+            return;
+        }
+        let count = self.pos(0, region.end - lines - region.start);
+        unsafe {
+            for plane in [0, 0x2000] {
+                let vram = self.get_vram().add(plane) as *mut u16;
+                vram.add(self.pos(0, region.start))
+                    .copy_to(vram.add(self.pos(0, region.start + lines)), count);
+            }
+        }
+        for row in region.start..region.start + lines {
+            self.erase(row, 0..self.mode.columns);
+        }
     }
 }
 
 impl SimpleTextOutput for Pc98Text {
     fn reset(&mut self) {
+        self.ansi.reset();
         self.set_attribute(0);
         self.mode.set_cursor_visible(true);
         self.clear_screen();
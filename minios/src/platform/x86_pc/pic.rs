@@ -348,6 +348,22 @@ impl Pic {
         }
     }
 
+    /// Returns whether the IRQ has been requested but not yet serviced
+    pub unsafe fn is_irq_pending(irq: Irq) -> bool {
+        unsafe {
+            without_interrupts!({
+                let shared = Self::shared();
+                let local_irq = irq.local_number();
+                let irr = if irq.is_slave() {
+                    shared.slave.read_irr()
+                } else {
+                    shared.master.read_irr()
+                };
+                (irr & (1 << local_irq)) != 0
+            })
+        }
+    }
+
     /// Set the IRQ enabled state
     pub unsafe fn set_irq_enabled(irq: Irq, enabled: bool) {
         unsafe {
@@ -417,6 +433,16 @@ impl I8259Device {
         }
     }
 
+    #[inline]
+    unsafe fn read_irr(&self) -> u8 {
+        unsafe {
+            let port = IoPortRWB(self.a0);
+            port.write(0x0a);
+            Hal::cpu().no_op();
+            port.read()
+        }
+    }
+
     #[inline]
     unsafe fn read_imr(&self) -> u8 {
         unsafe { self.read_a1() }
@@ -465,6 +491,10 @@ impl Irq {
         unsafe { Pic::set_irq_enabled(*self, false) }
     }
 
+    pub unsafe fn is_pending(&self) -> bool {
+        unsafe { Pic::is_irq_pending(*self) }
+    }
+
     pub const fn is_slave(&self) -> bool {
         self.0 >= 8
     }
@@ -2,18 +2,23 @@
 
 use super::pic::Irq;
 use crate::platform::x86_pc::pic::IrqHandler;
-use core::cell::UnsafeCell;
-use x86::isolated_io::IoPortWB;
-// use core::time::Duration;
+use crate::*;
+use core::{cell::UnsafeCell, time::Duration};
+use x86::isolated_io::{IoPortRB, IoPortWB};
 
 static mut PIT: UnsafeCell<Pit> = UnsafeCell::new(Pit::new());
 
 /// PIT: Programmable Interval Timer i8253/i8254
 pub struct Pit {
     monotonic: u64,
+    /// Ticks that elapsed while the timer interrupt could not be serviced
+    lost_ticks: u64,
+    last: Duration,
+    irq: Irq,
     tmr_cnt0: u16,
     beep_cnt0: u16,
     tmr_ctl: u16,
+    timer_val: u16,
 }
 
 impl Pit {
@@ -23,9 +28,13 @@ impl Pit {
     const fn new() -> Self {
         Self {
             monotonic: 0,
+            lost_ticks: 0,
+            last: Duration::ZERO,
+            irq: Irq(0),
             tmr_cnt0: 0,
             beep_cnt0: 0,
             tmr_ctl: 0,
+            timer_val: 0,
         }
     }
 
@@ -43,9 +52,12 @@ impl Pit {
             shared.tmr_cnt0 = tmr_cnt0;
             shared.beep_cnt0 = beep_cnt0;
             shared.tmr_ctl = tmr_ctl;
+            shared.timer_val = timer_val;
+            shared.irq = irq;
 
             irq.register(irq_handler).unwrap();
-            IoPortWB(tmr_ctl).write(0b0011_0110u8);
+            // counter 0, mode 2 (rate generator) so that the counter can be read linearly
+            IoPortWB(tmr_ctl).write(0b0011_0100u8);
 
             let cnt = IoPortWB(tmr_cnt0);
             cnt.write((timer_val & 0xff) as u8);
@@ -64,6 +76,52 @@ impl Pit {
         let shared = unsafe { Self::shared() };
         shared.monotonic += Self::TIMER_RES;
     }
+
+    /// Returns the elapsed time, including the fraction of the current tick read from the counter
+    ///
+    /// The time keeps advancing with interrupts disabled as long as it is read at least once per tick.
+    pub(super) fn monotonic() -> Duration {
+        let shared = unsafe { Self::shared() };
+        let timer_val = shared.timer_val as u64;
+        if timer_val == 0 {
+            return Duration::ZERO;
+        }
+        unsafe {
+            without_interrupts!({
+                let (pending, count) = loop {
+                    let pending = shared.irq.is_pending();
+                    let count = shared.read_counter() as u64;
+                    if shared.irq.is_pending() == pending {
+                        break (pending, count);
+                    }
+                };
+                // A pending IRQ means the counter has wrapped around but the tick has not advanced yet
+                let tick = shared.monotonic + shared.lost_ticks + pending as u64 * Self::TIMER_RES;
+                let fraction =
+                    (timer_val - count.min(timer_val)) * Self::TIMER_RES * 1_000_000 / timer_val;
+                let mut now = Duration::from_millis(tick) + Duration::from_nanos(fraction);
+                // The counter wrapped around more than once without the IRQ being serviced
+                let tick_duration = Duration::from_millis(Self::TIMER_RES);
+                while now < shared.last {
+                    shared.lost_ticks += Self::TIMER_RES;
+                    now += tick_duration;
+                }
+                shared.last = now;
+                now
+            })
+        }
+    }
+
+    unsafe fn read_counter(&self) -> u16 {
+        unsafe {
+            // counter latch command
+            IoPortWB(self.tmr_ctl).write(0b0000_0000);
+            let cnt = IoPortRB(self.tmr_cnt0);
+            let lo = cnt.read() as u16;
+            let hi = cnt.read() as u16;
+            (hi << 8) | lo
+        }
+    }
 }
 
 // impl TimerSource for Pit {
@@ -0,0 +1,205 @@
+//! Command Shell
+
+mod builtin;
+mod monitor;
+
+use core::fmt;
+use minios::prelude::*;
+
+/// Handler of a shell command, `args` does not include the command name
+pub type CommandFn = fn(shell: &Shell, args: &[&str]) -> Result<(), CommandError>;
+
+/// Shell command entry
+pub struct Command {
+    /// Name of the command
+    pub name: &'static str,
+    /// Arguments of the command, shown after the name in the usage
+    pub usage: &'static str,
+    /// One line description shown in the command list
+    pub summary: &'static str,
+    /// Detailed description shown by `help COMMAND`
+    pub help: &'static str,
+    pub handler: CommandFn,
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    /// The arguments do not match the usage
+    Usage,
+    /// The argument cannot be parsed
+    InvalidArgument(String),
+    /// The command is not available on this platform
+    NotSupported,
+    /// The command failed
+    Failed(String),
+}
+
+impl CommandError {
+    #[inline]
+    pub fn invalid_argument(arg: &str) -> Self {
+        Self::InvalidArgument(arg.to_owned())
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Usage => f.write_str("invalid usage"),
+            Self::InvalidArgument(arg) => write!(f, "invalid argument: {:?}", arg),
+            Self::NotSupported => f.write_str("not supported on this platform"),
+            Self::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Command registry and interpreter
+pub struct Shell {
+    commands: BTreeMap<&'static str, Command>,
+}
+
+impl Shell {
+    /// Maximum length of a command line
+    pub const MAX_LINE: usize = 127;
+
+    pub const PROMPT: &str = "> ";
+
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a shell with the built-in commands
+    pub fn with_builtins() -> Self {
+        let mut shell = Self::new();
+        for command in builtin::commands().chain(monitor::commands()) {
+            shell.register(command);
+        }
+        shell
+    }
+
+    /// Registers the command, replacing the command with the same name
+    #[inline]
+    pub fn register(&mut self, command: Command) {
+        self.commands.insert(command.name, command);
+    }
+
+    #[inline]
+    pub fn commands(&self) -> impl Iterator<Item = &Command> {
+        self.commands.values()
+    }
+
+    #[inline]
+    pub fn find(&self, name: &str) -> Option<&Command> {
+        self.commands.get(name)
+    }
+
+    /// Reads and executes commands forever
+    pub fn run(&self) -> ! {
+        loop {
+            let mut completer = |line: &str| self.complete(line);
+            let Some(line) =
+                System::line_editor().read_line(Self::PROMPT, Self::MAX_LINE, Some(&mut completer))
+            else {
+                println!("");
+                continue;
+            };
+            self.execute(&line);
+        }
+    }
+
+    /// Parses and executes the command line
+    pub fn execute(&self, line: &str) {
+        let args = match split_args(line) {
+            Ok(args) => args,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let args = args.iter().map(|v| v.as_str()).collect::<Vec<_>>();
+        let Some((name, args)) = args.split_first() else {
+            return;
+        };
+        let Some(command) = self.find(name) else {
+            println!("{}: command not found", name);
+            return;
+        };
+        if matches!(args, ["-?"] | ["--help"]) {
+            self.print_help(command);
+            return;
+        }
+        match (command.handler)(self, args) {
+            Ok(()) => {}
+            Err(CommandError::Usage) => self.print_usage(command),
+            Err(err) => println!("{}: {}", command.name, err),
+        }
+    }
+
+    /// Returns the command names that complete the line
+    pub fn complete(&self, line: &str) -> Vec<String> {
+        let line = line.trim_start();
+        if line.contains(' ') {
+            return Vec::new();
+        }
+        self.commands
+            .keys()
+            .filter(|v| v.starts_with(line))
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    pub fn print_usage(&self, command: &Command) {
+        if command.usage.is_empty() {
+            println!("usage: {}", command.name);
+        } else {
+            println!("usage: {} {}", command.name, command.usage);
+        }
+    }
+
+    pub fn print_help(&self, command: &Command) {
+        self.print_usage(command);
+        println!("  {}", command.summary);
+        for line in command.help.lines() {
+            println!("  {}", line);
+        }
+    }
+}
+
+/// Splits the command line into arguments, quotes group words
+pub fn split_args(line: &str) -> Result<Vec<String>, &'static str> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|v| v.is_ascii_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut arg = String::new();
+        while let Some(ch) = chars.next_if(|v| !v.is_ascii_whitespace()) {
+            match ch {
+                '"' | '\'' => loop {
+                    match chars.next() {
+                        Some(v) if v == ch => break,
+                        Some(v) => arg.push(v),
+                        None => return Err("unterminated quote"),
+                    }
+                },
+                _ => arg.push(ch),
+            }
+        }
+        args.push(arg);
+    }
+    Ok(args)
+}
+
+/// Parses a number, `0x` prefix for hexadecimal
+pub fn parse_number(s: &str) -> Option<u64> {
+    let s = s.replace('_', "");
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
@@ -0,0 +1,672 @@
+//! Built-in commands
+
+use super::*;
+use alloc::format;
+use minios::io::graphics::ModeIndex;
+use minios::loader::{LoadError, ceef::CeefImage, elf::ElfImage, megos::MegOsBoot};
+use minios::mem::{MemoryManager, heap::Heap};
+use minios::platform::{Platform, PlatformTrait};
+
+pub(super) fn commands() -> impl Iterator<Item = Command> {
+    [
+        Command {
+            name: "acpi",
+            usage: "",
+            summary: "Lists the ACPI tables",
+            help: "",
+            handler: cmd_acpi,
+        },
+        Command {
+            name: "boot",
+            usage: "PATH [ARGS...]",
+            summary: "Starts the MEG-OS kernel",
+            help: "PATH is the kernel image in the CEEF or ELF format. ARGS are passed to the kernel as the command line.",
+            handler: cmd_boot,
+        },
+        Command {
+            name: "cpu",
+            usage: "",
+            summary: "Shows the processor information",
+            help: "",
+            handler: cmd_cpu,
+        },
+        Command {
+            name: "fdt",
+            usage: "[PATH]",
+            summary: "Dumps the device tree",
+            help: "PATH is the path of the node to dump, such as /cpus. The whole tree is dumped by default.",
+            handler: cmd_fdt,
+        },
+        Command {
+            name: "help",
+            usage: "[COMMAND]",
+            summary: "Shows the list of commands or the help of the command",
+            help: "",
+            handler: cmd_help,
+        },
+        Command {
+            name: "linux",
+            usage: "PATH [-i INITRD] [ARGS...]",
+            summary: "Starts the Linux kernel",
+            help: "PATH is the kernel `Image`, or `bzImage` on x86. INITRD is the initial ramdisk. ARGS are passed to the kernel as the command line.",
+            handler: cmd_linux,
+        },
+        Command {
+            name: "mem",
+            usage: "",
+            summary: "Shows the memory map and the free memory size",
+            help: "",
+            handler: cmd_mem,
+        },
+        Command {
+            name: "mode",
+            usage: "[INDEX|text]",
+            summary: "Lists or sets the video modes",
+            help: "Without arguments, lists the graphics modes. The current mode is marked with `*`.\nINDEX sets the graphics mode, `text` returns to the text mode.",
+            handler: cmd_mode,
+        },
+        Command {
+            name: "mount",
+            usage: "[DEVICE PATH]",
+            summary: "Lists or mounts the file systems",
+            help: "Without arguments, lists the block devices and the mount points. The boot device is marked with `*`.\nDEVICE is the block device, such as `hd0`. The device itself or its first partition that has a file system is mounted on PATH.",
+            handler: cmd_mount,
+        },
+        Command {
+            name: "reboot",
+            usage: "",
+            summary: "Resets the system",
+            help: "",
+            handler: cmd_reboot,
+        },
+        Command {
+            name: "smbios",
+            usage: "[-l]",
+            summary: "Shows the SMBIOS information",
+            help: "-l lists all structures.",
+            handler: cmd_smbios,
+        },
+        Command {
+            name: "ver",
+            usage: "",
+            summary: "Shows the version",
+            help: "",
+            handler: cmd_ver,
+        },
+    ]
+    .into_iter()
+}
+
+/// Human readable size
+struct Size(u64);
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let size = self.0;
+        if size >= 0x4000_0000 && (size & 0x3fff_ffff) == 0 {
+            write!(f, "{}GB", size >> 30)
+        } else if size >= 0x10_0000 && (size & 0xf_ffff) == 0 {
+            write!(f, "{}MB", size >> 20)
+        } else if size >= 0x400 {
+            write!(f, "{}KB", (size + 0x3ff) >> 10)
+        } else {
+            write!(f, "{}B", size)
+        }
+    }
+}
+
+fn cmd_help(shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    match args {
+        [] => {
+            let width = shell.commands().map(|v| v.name.len()).max().unwrap_or(0);
+            for command in shell.commands() {
+                println!("  {:width$}  {}", command.name, command.summary);
+            }
+            Ok(())
+        }
+        [name] => {
+            let command = shell
+                .find(name)
+                .ok_or_else(|| CommandError::invalid_argument(name))?;
+            shell.print_help(command);
+            Ok(())
+        }
+        _ => Err(CommandError::Usage),
+    }
+}
+
+fn cmd_ver(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    println!("{} v{}", crate::SYSTEM_NAME, crate::CURRENT_VERSION);
+    println!("PLATFORM {}", System::platform());
+    Ok(())
+}
+
+fn cmd_reboot(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    Platform::reset_system();
+}
+
+fn cmd_boot(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let [path, cmdline @ ..] = args else {
+        return Err(CommandError::Usage);
+    };
+    let blob = System::vfs()
+        .read(path)
+        .map_err(|err| CommandError::Failed(format!("cannot read {}: {:?}", path, err)))?;
+    let image = match CeefImage::parse(&blob) {
+        Ok(ceef) => ceef.load(),
+        Err(LoadError::BadFormat) => ElfImage::parse(&blob).and_then(|elf| elf.load()),
+        Err(err) => Err(err),
+    }
+    .map_err(|err| CommandError::Failed(format!("cannot load {}: {:?}", path, err)))?;
+    let boot = MegOsBoot::new(image, &cmdline.join(" "))
+        .map_err(|err| CommandError::Failed(format!("cannot boot {}: {:?}", path, err)))?;
+    unsafe { boot.start() }
+}
+
+#[cfg(any(
+    all(target_arch = "x86", feature = "pc"),
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+fn cmd_linux(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let (path, initrd_path, cmdline) = match args {
+        [path, "-i", initrd, cmdline @ ..] => (path, Some(initrd), cmdline),
+        [path, cmdline @ ..] => (path, None, cmdline),
+        _ => return Err(CommandError::Usage),
+    };
+    let read = |path: &str| {
+        System::vfs()
+            .read(path)
+            .map_err(|err| CommandError::Failed(format!("cannot read {}: {:?}", path, err)))
+    };
+    let blob = read(path)?;
+    let initrd = initrd_path.map(|path| read(path)).transpose()?;
+    let cmdline = cmdline.join(" ");
+
+    #[cfg(all(target_arch = "x86", feature = "pc"))]
+    let boot = {
+        use minios::loader::bzimage::BzImage;
+        BzImage::parse(&blob).and_then(|image| image.load(&cmdline, initrd.as_deref()))
+    };
+    #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+    let boot = {
+        use minios::loader::linux::{LinuxBoot, LinuxImage};
+        LinuxImage::parse(&blob)
+            .and_then(|image| image.load())
+            .and_then(|image| LinuxBoot::new(image, &cmdline, initrd.as_deref()))
+    };
+    let boot =
+        boot.map_err(|err| CommandError::Failed(format!("cannot boot {}: {:?}", path, err)))?;
+    unsafe { boot.start() }
+}
+
+#[cfg(not(any(
+    all(target_arch = "x86", feature = "pc"),
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+fn cmd_linux(_shell: &Shell, _args: &[&str]) -> Result<(), CommandError> {
+    Err(CommandError::NotSupported)
+}
+
+fn cmd_mem(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    println!("  BASE             END              SIZE     TYPE");
+    for entry in MemoryManager::memory_list() {
+        println!(
+            "  {:016x} {:016x} {:>8} {:?}",
+            entry.base,
+            (entry.base + entry.size).saturating_sub(1),
+            Size(entry.size).to_string(),
+            entry.mem_type,
+        );
+    }
+
+    let memsize1 = MemoryManager::total_memory_size() as u64;
+    let memsize2 = MemoryManager::total_extended_memory_size() as u64;
+    if memsize2 > 0 {
+        println!("TOTAL {} + {}", Size(memsize1), Size(memsize2 << 20),);
+    } else {
+        println!("TOTAL {}", Size(memsize1));
+    }
+    println!(
+        "FREE {} (MAX {}), HEAP {} IN USE, {} SLAB PAGES",
+        Size(MemoryManager::free_memory_count() as u64),
+        Size(MemoryManager::max_free_memory_size() as u64),
+        Size(Heap::used_size() as u64),
+        Heap::slab_pages(),
+    );
+    Ok(())
+}
+
+fn cmd_mount(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    match args {
+        [] => {
+            for entry in System::block_devices() {
+                let media_info = entry.device.lock().media_info();
+                let mark = if entry.is_boot_device { '*' } else { ' ' };
+                if media_info.is_capacity_known() {
+                    let size = media_info.block_count.0 * media_info.block_size as u64;
+                    println!("{} {:<8} {:>8}", mark, entry.name, Size(size).to_string());
+                } else {
+                    println!("{} {:<8} {:>8}", mark, entry.name, "?");
+                }
+            }
+            for mount in System::vfs().mounts() {
+                println!("  {:<16} {}", mount.path(), mount.fs().fs_type());
+            }
+            Ok(())
+        }
+        [device, path] => System::mount_block_device(device, path)
+            .map_err(|err| CommandError::Failed(format!("cannot mount {}: {:?}", device, err))),
+        _ => Err(CommandError::Usage),
+    }
+}
+
+fn cmd_mode(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let conctl = System::conctl();
+    match args {
+        [] => {
+            let modes = conctl.graphics_modes();
+            if modes.is_empty() {
+                println!("No graphics modes available");
+                return Ok(());
+            }
+            let current = conctl.current_graphics_mode().map(|v| v.current);
+            for (index, mode) in modes.iter().enumerate() {
+                println!(
+                    "{} {:3} {:4} x {:4} {:?}",
+                    if current == Some(ModeIndex(index)) {
+                        '*'
+                    } else {
+                        ' '
+                    },
+                    index,
+                    mode.width,
+                    mode.height,
+                    mode.pixel_format,
+                );
+            }
+            Ok(())
+        }
+        ["text"] => {
+            conctl.set_text_mode();
+            Ok(())
+        }
+        [index] => {
+            let mode = parse_number(index)
+                .map(|v| ModeIndex(v as usize))
+                .ok_or_else(|| CommandError::invalid_argument(index))?;
+            conctl
+                .set_graphics_mode(mode)
+                .map_err(|_| CommandError::Failed("cannot set the mode".to_owned()))
+        }
+        _ => Err(CommandError::Usage),
+    }
+}
+
+fn cmd_smbios(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let list = match args {
+        [] => false,
+        ["-l"] => true,
+        _ => return Err(CommandError::Usage),
+    };
+    let smbios = System::smbios().ok_or(CommandError::NotSupported)?;
+    println!(
+        "SMBIOS {}.{}, {} structures, {} bytes",
+        smbios.major_version(),
+        smbios.minor_version(),
+        smbios.n_structures(),
+        smbios.table_length(),
+    );
+    if let Some(manufacturer) = smbios.manufacturer() {
+        println!("  Manufacturer: {}", manufacturer);
+    }
+    if let Some(product_name) = smbios.product_name() {
+        println!("  Product Name: {}", product_name);
+    }
+    if let Some(serial_number) = smbios.serial_number() {
+        println!("  Serial Number: {}", serial_number);
+    }
+    if let Some(uuid) = smbios.system_uuid() {
+        println!("  UUID: {}", uuid);
+    }
+    if list {
+        for header in smbios.iter() {
+            println!(
+                "  TYPE {:3} HANDLE {:04x} SIZE {}",
+                header.header_type().0,
+                header.handle().0,
+                header.struct_size(),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn cmd_acpi(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    let print_table = |table: &acpi::AcpiHeader| {
+        println!(
+            "  {} {:08x} {:6}",
+            table.signature(),
+            table as *const _ as usize,
+            table.len(),
+        );
+    };
+
+    if let Some(rsdp) = System::find_config_table_entry(&acpi::ACPI_20_TABLE_GUID)
+        .and_then(|v| unsafe { acpi::RsdPtrV2::parse_extended(v.address.get().as_usize() as _) })
+    {
+        let xsdt = rsdp.xsdt();
+        println!(
+            "ACPI rev {}, {} tables in XSDT",
+            rsdp.rev(),
+            xsdt.table_count()
+        );
+        xsdt.tables().for_each(print_table);
+        Ok(())
+    } else if let Some(rsdp) = System::find_config_table_entry(&acpi::ACPI_10_TABLE_GUID)
+        .and_then(|v| unsafe { acpi::RsdPtrV1::parse(v.address.get().as_usize() as _) })
+    {
+        let rsdt = rsdp.rsdt();
+        println!(
+            "ACPI rev {}, {} tables in RSDT",
+            rsdp.rev(),
+            rsdt.table_count()
+        );
+        rsdt.tables().for_each(print_table);
+        Ok(())
+    } else {
+        Err(CommandError::NotSupported)
+    }
+}
+
+#[cfg(target_arch = "x86")]
+fn cmd_cpu(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    use core::arch::x86::{__cpuid, has_cpuid};
+
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    if !has_cpuid() {
+        println!("CPUID is not supported");
+        return Ok(());
+    }
+
+    let leaf0 = unsafe { __cpuid(0) };
+    let mut vendor = [0u8; 12];
+    vendor[0..4].copy_from_slice(&leaf0.ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&leaf0.edx.to_le_bytes());
+    vendor[8..12].copy_from_slice(&leaf0.ecx.to_le_bytes());
+    println!("Vendor: {}", core::str::from_utf8(&vendor).unwrap_or("?"));
+
+    let max_ext_leaf = unsafe { __cpuid(0x8000_0000) }.eax;
+    if max_ext_leaf >= 0x8000_0004 {
+        let mut brand = [0u8; 48];
+        for (i, leaf) in (0x8000_0002..=0x8000_0004).enumerate() {
+            let regs = unsafe { __cpuid(leaf) };
+            for (j, reg) in [regs.eax, regs.ebx, regs.ecx, regs.edx].iter().enumerate() {
+                let offset = i * 16 + j * 4;
+                brand[offset..offset + 4].copy_from_slice(&reg.to_le_bytes());
+            }
+        }
+        let brand = core::str::from_utf8(&brand).unwrap_or("?");
+        println!("Brand: {}", brand.trim_matches(|v| v == '\0' || v == ' '));
+    }
+
+    if leaf0.eax < 1 {
+        return Ok(());
+    }
+    let leaf1 = unsafe { __cpuid(1) };
+    let family = (leaf1.eax >> 8) & 0x0f;
+    let model = (leaf1.eax >> 4) & 0x0f;
+    let (family, model) = match family {
+        0x0f => (
+            family + ((leaf1.eax >> 20) & 0xff),
+            model | ((leaf1.eax >> 12) & 0xf0),
+        ),
+        0x06 => (family, model | ((leaf1.eax >> 12) & 0xf0)),
+        _ => (family, model),
+    };
+    println!(
+        "Family {:#x}, Model {:#x}, Stepping {}",
+        family,
+        model,
+        leaf1.eax & 0x0f
+    );
+
+    let ext_edx = if max_ext_leaf >= 0x8000_0001 {
+        unsafe { __cpuid(0x8000_0001) }.edx
+    } else {
+        0
+    };
+    #[rustfmt::skip]
+    let features = [
+        (leaf1.edx, 0, "FPU"), (leaf1.edx, 4, "TSC"), (leaf1.edx, 5, "MSR"),
+        (leaf1.edx, 6, "PAE"), (leaf1.edx, 8, "CX8"), (leaf1.edx, 9, "APIC"),
+        (leaf1.edx, 15, "CMOV"), (leaf1.edx, 23, "MMX"), (leaf1.edx, 24, "FXSR"),
+        (leaf1.edx, 25, "SSE"), (leaf1.edx, 26, "SSE2"), (leaf1.edx, 28, "HTT"),
+        (leaf1.ecx, 0, "SSE3"), (leaf1.ecx, 9, "SSSE3"), (leaf1.ecx, 19, "SSE4.1"),
+        (leaf1.ecx, 20, "SSE4.2"), (leaf1.ecx, 23, "POPCNT"), (leaf1.ecx, 28, "AVX"),
+        (leaf1.ecx, 31, "HYPERVISOR"), (ext_edx, 20, "NX"), (ext_edx, 29, "LM"),
+    ];
+    print!("Features:");
+    for (reg, bit, name) in features {
+        if (reg & (1 << bit)) != 0 {
+            print!(" {}", name);
+        }
+    }
+    println!("");
+
+    Ok(())
+}
+
+#[cfg(not(target_arch = "x86"))]
+fn cmd_cpu(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        let midr: u64;
+        unsafe {
+            core::arch::asm!("mrs {}, midr_el1", out(reg) midr);
+        }
+        println!(
+            "MIDR_EL1 {:08x}: Implementer {:#04x}, Part {:#05x}, Variant {}, Revision {}",
+            midr,
+            (midr >> 24) & 0xff,
+            (midr >> 4) & 0xfff,
+            (midr >> 20) & 0x0f,
+            midr & 0x0f,
+        );
+    }
+
+    let cpus = System::device_tree()
+        .and_then(|dt| dt.root().cpus())
+        .ok_or(CommandError::NotSupported)?;
+    for cpu in cpus
+        .children()
+        .filter(|v| v.name().without_unit() == fdt::NodeName::CPU)
+    {
+        print!("{}", cpu.name());
+        if let Some(compatible) = cpu.get_prop_str(fdt::PropName::COMPATIBLE) {
+            print!(" {:?}", compatible);
+        }
+        if let Some(isa) = cpu.get_prop_str(fdt::PropName("riscv,isa")) {
+            print!(" {}", isa);
+        }
+        if !cpu.status_is_ok() {
+            print!(" (disabled)");
+        }
+        println!("");
+    }
+    Ok(())
+}
+
+fn cmd_fdt(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let dt = System::device_tree().ok_or(CommandError::NotSupported)?;
+    let path = match args {
+        [] => "/",
+        [path] => *path,
+        _ => return Err(CommandError::Usage),
+    };
+    let mut node: fdt::Node = (*dt.root()).clone();
+    for name in path.split('/').filter(|v| !v.is_empty()) {
+        node = node
+            .children()
+            .find(|v| v.name().0 == name || v.name().without_unit().0 == name)
+            .ok_or_else(|| CommandError::invalid_argument(path))?;
+    }
+    dump_fdt_node(&node, 0);
+    Ok(())
+}
+
+fn dump_fdt_node(node: &fdt::Node, level: usize) {
+    use fdt::*;
+
+    if let Some(compatible) = node.get_prop_str(PropName::COMPATIBLE) {
+        println!(
+            "{}{} ({:?})",
+            "  ".repeat(level),
+            node.name().as_str(),
+            compatible,
+        );
+    } else {
+        println!("{}{}", "  ".repeat(level), node.name().as_str(),);
+    }
+
+    for prop in node.props() {
+        match prop.name() {
+            PropName::COMPATIBLE => {
+                for _ in 0..level {
+                    print!("  ");
+                }
+                print!("  {} <", prop.name().as_str());
+                for (i, s) in prop.string_list().enumerate() {
+                    if i > 0 {
+                        print!(" {:?}", s);
+                    } else {
+                        print!("{:?}", s);
+                    }
+                }
+                println!(">");
+            }
+            PropName::REG => {
+                let reg = node.reg().unwrap();
+                for reg in reg {
+                    for _ in 0..level {
+                        print!("  ");
+                    }
+                    if reg.1 > 0 {
+                        println!("  reg <{:#010x} {:#010x}>", reg.0, reg.1,);
+                    } else {
+                        println!("  reg <{:#010x}>", reg.0,);
+                    }
+                }
+            }
+            PropName::ADDRESS_CELLS
+            | PropName::SIZE_CELLS
+            | PropName::INTERRUPT_CELLS
+            | PropName::INTERRUPT_PARENT
+            | PropName::CLOCK_CELLS
+            | PropName::PHANDLE => {
+                for _ in 0..level {
+                    print!("  ");
+                }
+                println!(
+                    "  {} <{:#x}>",
+                    prop.name().as_str(),
+                    prop.as_u32().unwrap_or_default()
+                );
+            }
+            PropName("linux,initrd-end")
+            | PropName("linux,initrd-start")
+            | PropName::CLOCK_FREQUENCY
+            | PropName::TIMEBASE_FREQUENCY => {
+                for _ in 0..level {
+                    print!("  ");
+                }
+                println!(
+                    "  {} <{:#x}>",
+                    prop.name().as_str(),
+                    prop.as_u32().unwrap_or_default()
+                );
+            }
+            PropName::DEVICE_TYPE | PropName::MODEL | PropName::NAME | PropName::STATUS => {
+                for _ in 0..level {
+                    print!("  ");
+                }
+                println!("  {} <{:?}>", prop.name().as_str(), prop.as_str());
+            }
+            _ => {
+                for _ in 0..level {
+                    print!("  ");
+                }
+                print!("  {} <", prop.name().as_str());
+
+                let bytes = prop.bytes();
+                let len = bytes.len();
+                if len > 0 {
+                    let maybe_words = (len & 3) == 0;
+                    let mut maybe_asciz = false;
+                    if bytes[len - 1] == 0 {
+                        maybe_asciz = true;
+                        for i in 0..len - 1 {
+                            if bytes[i] < 0x20 || bytes[i] > 0x7e {
+                                maybe_asciz = false;
+                                break;
+                            }
+                        }
+                    }
+
+                    if maybe_asciz || !maybe_words {
+                        print!("\"");
+                        for c in bytes {
+                            match *c {
+                                0 => {
+                                    print!("\\0");
+                                }
+                                0x20..=0x7E => {
+                                    print!("{}", *c as char);
+                                }
+                                _ => {
+                                    print!("\\x{:02x}", *c);
+                                }
+                            }
+                        }
+                        print!("\"");
+                    } else {
+                        let words = unsafe {
+                            core::slice::from_raw_parts(bytes.as_ptr() as *const BeU32, len / 4)
+                        };
+                        for (i, w) in words.iter().enumerate() {
+                            if i > 0 {
+                                print!(" {:#x}", w.as_u32());
+                            } else {
+                                print!("{:#x}", w.as_u32());
+                            }
+                        }
+                    }
+                }
+                println!(">");
+            }
+        }
+    }
+
+    for child in node.children() {
+        dump_fdt_node(&child, level + 1);
+    }
+}
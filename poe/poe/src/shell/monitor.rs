@@ -0,0 +1,418 @@
+//! Machine-language monitor commands
+//!
+//! Writes and calls are refused if the range overlaps the memory used by the system itself.
+
+use super::*;
+use alloc::format;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use minios::mem::{MemoryManager, MemoryType, mmio::*};
+
+/// Next address to dump by `peek` without arguments
+static NEXT_PEEK: AtomicUsize = AtomicUsize::new(0);
+
+const DEFAULT_PEEK_LENGTH: usize = 0x80;
+
+/// Maximum number of differences reported by `compare`
+const MAX_COMPARE_REPORT: usize = 32;
+
+pub(super) fn commands() -> impl Iterator<Item = Command> {
+    [
+        Command {
+            name: "peek",
+            usage: "[-b|-w|-d] [ADDRESS [LENGTH]]",
+            summary: "Dumps the memory",
+            help: "Without ADDRESS, continues from the last dump.",
+            handler: cmd_peek,
+        },
+        Command {
+            name: "poke",
+            usage: "[-b|-w|-d] ADDRESS VALUE...",
+            summary: "Writes the values to the memory",
+            help: "",
+            handler: cmd_poke,
+        },
+        Command {
+            name: "fill",
+            usage: "ADDRESS LENGTH BYTE",
+            summary: "Fills the memory with the byte",
+            help: "",
+            handler: cmd_fill,
+        },
+        Command {
+            name: "compare",
+            usage: "ADDRESS1 ADDRESS2 LENGTH",
+            summary: "Compares two memory blocks",
+            help: "",
+            handler: cmd_compare,
+        },
+        Command {
+            name: "move",
+            usage: "SOURCE DEST LENGTH",
+            summary: "Copies the memory block, the blocks may overlap",
+            help: "",
+            handler: cmd_move,
+        },
+        Command {
+            name: "mmio",
+            usage: "ADDRESS [VALUE]",
+            summary: "Reads or writes the 32-bit memory mapped register",
+            help: "",
+            handler: cmd_mmio,
+        },
+        Command {
+            name: "call",
+            usage: "ADDRESS",
+            summary: "Calls the code at the address",
+            help: "The code is called as `extern \"C\" fn() -> usize` and the return value is shown.",
+            handler: cmd_call,
+        },
+        #[cfg(target_arch = "x86")]
+        Command {
+            name: "in",
+            usage: "[-b|-w|-d] PORT",
+            summary: "Reads from the I/O port",
+            help: "",
+            handler: cmd_in,
+        },
+        #[cfg(target_arch = "x86")]
+        Command {
+            name: "out",
+            usage: "[-b|-w|-d] PORT VALUE",
+            summary: "Writes to the I/O port",
+            help: "",
+            handler: cmd_out,
+        },
+    ]
+    .into_iter()
+}
+
+/// Access width of the memory or I/O port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Width {
+    Byte,
+    Word,
+    Dword,
+}
+
+impl Width {
+    /// Takes the width option from the arguments, byte by default
+    fn parse<'a, 'b>(args: &'a [&'b str]) -> (Self, &'a [&'b str]) {
+        match args.split_first() {
+            Some((&"-b", rest)) => (Self::Byte, rest),
+            Some((&"-w", rest)) => (Self::Word, rest),
+            Some((&"-d", rest)) => (Self::Dword, rest),
+            _ => (Self::Byte, args),
+        }
+    }
+
+    #[inline]
+    const fn size(&self) -> usize {
+        match self {
+            Self::Byte => 1,
+            Self::Word => 2,
+            Self::Dword => 4,
+        }
+    }
+
+    #[inline]
+    const fn max_value(&self) -> u64 {
+        match self {
+            Self::Byte => u8::MAX as u64,
+            Self::Word => u16::MAX as u64,
+            Self::Dword => u32::MAX as u64,
+        }
+    }
+
+    fn parse_value(&self, s: &str) -> Result<u32, CommandError> {
+        parse_number(s)
+            .filter(|&v| v <= self.max_value())
+            .map(|v| v as u32)
+            .ok_or_else(|| CommandError::invalid_argument(s))
+    }
+
+    unsafe fn read(&self, addr: usize) -> u32 {
+        unsafe {
+            match self {
+                Self::Byte => (addr as *const u8).read_volatile() as u32,
+                Self::Word => (addr as *const u16).read_volatile() as u32,
+                Self::Dword => (addr as *const u32).read_volatile(),
+            }
+        }
+    }
+
+    unsafe fn write(&self, addr: usize, value: u32) {
+        unsafe {
+            match self {
+                Self::Byte => (addr as *mut u8).write_volatile(value as u8),
+                Self::Word => (addr as *mut u16).write_volatile(value as u16),
+                Self::Dword => (addr as *mut u32).write_volatile(value),
+            }
+        }
+    }
+}
+
+fn parse_address(s: &str) -> Result<usize, CommandError> {
+    parse_number(s)
+        .and_then(|v| usize::try_from(v).ok())
+        .ok_or_else(|| CommandError::invalid_argument(s))
+}
+
+/// Returns the end of the range, or an error if it wraps around the address space
+fn checked_end(base: usize, len: usize) -> Result<usize, CommandError> {
+    base.checked_add(len)
+        .ok_or_else(|| CommandError::Failed("the range exceeds the address space".to_owned()))
+}
+
+/// Refuses the range if it overlaps any memory other than the free memory
+///
+/// The addresses outside the memory map, such as MMIO, are not refused.
+fn check_unused(base: usize, len: usize) -> Result<(), CommandError> {
+    let start = base as u64;
+    let end = checked_end(base, len)? as u64;
+    match MemoryManager::memory_list()
+        .find(|v| v.mem_type != MemoryType::Available && v.base < end && start < v.base + v.size)
+    {
+        Some(entry) => Err(CommandError::Failed(format!(
+            "{:#x}-{:#x} is not free memory ({:?})",
+            entry.base,
+            entry.base + entry.size - 1,
+            entry.mem_type
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn check_aligned(addr: usize, width: Width) -> Result<(), CommandError> {
+    if (addr & (width.size() - 1)) == 0 {
+        Ok(())
+    } else {
+        Err(CommandError::Failed(format!(
+            "{:#x} is not aligned to {} bytes",
+            addr,
+            width.size()
+        )))
+    }
+}
+
+fn cmd_peek(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let (width, args) = Width::parse(args);
+    let (base, len) = match args {
+        [] => (NEXT_PEEK.load(Ordering::Relaxed), DEFAULT_PEEK_LENGTH),
+        [addr] => (parse_address(addr)?, DEFAULT_PEEK_LENGTH),
+        [addr, len] => (parse_address(addr)?, parse_address(len)?),
+        _ => return Err(CommandError::Usage),
+    };
+    check_aligned(base, width)?;
+    let end = checked_end(base, len)?;
+
+    let size = width.size();
+    let mut line = [0u8; 16];
+    let mut addr = base;
+    while addr < end {
+        let line_len = (end - addr).min(16).next_multiple_of(size);
+        print!("{:08x}:", addr);
+        for offset in (0..line_len).step_by(size) {
+            let value = unsafe { width.read(addr + offset) };
+            line[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+            print!(" {:0width$x}", value, width = size * 2);
+        }
+        let padding = (16 - line_len) / size * (size * 2 + 1);
+        print!("{:padding$}  ", "");
+        for &byte in &line[..line_len] {
+            print!(
+                "{}",
+                if (0x20..0x7f).contains(&byte) {
+                    byte as char
+                } else {
+                    '.'
+                }
+            );
+        }
+        println!("");
+        addr = addr.saturating_add(line_len);
+        if addr == usize::MAX {
+            break;
+        }
+    }
+    NEXT_PEEK.store(addr, Ordering::Relaxed);
+    Ok(())
+}
+
+fn cmd_poke(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let (width, args) = Width::parse(args);
+    let [addr, values @ ..] = args else {
+        return Err(CommandError::Usage);
+    };
+    if values.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    let base = parse_address(addr)?;
+    let values = values
+        .iter()
+        .map(|v| width.parse_value(v))
+        .collect::<Result<Vec<_>, _>>()?;
+    check_aligned(base, width)?;
+    check_unused(base, values.len() * width.size())?;
+
+    for (index, value) in values.into_iter().enumerate() {
+        unsafe {
+            width.write(base + index * width.size(), value);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_fill(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let [addr, len, value] = args else {
+        return Err(CommandError::Usage);
+    };
+    let base = parse_address(addr)?;
+    let len = parse_address(len)?;
+    let value = Width::Byte.parse_value(value)? as u8;
+    check_unused(base, len)?;
+
+    unsafe {
+        core::ptr::write_bytes(base as *mut u8, value, len);
+    }
+    Ok(())
+}
+
+fn cmd_compare(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let [addr1, addr2, len] = args else {
+        return Err(CommandError::Usage);
+    };
+    let base1 = parse_address(addr1)?;
+    let base2 = parse_address(addr2)?;
+    let len = parse_address(len)?;
+    checked_end(base1, len)?;
+    checked_end(base2, len)?;
+
+    let mut count = 0;
+    for offset in 0..len {
+        let (byte1, byte2) = unsafe {
+            (
+                Width::Byte.read(base1 + offset),
+                Width::Byte.read(base2 + offset),
+            )
+        };
+        if byte1 != byte2 {
+            if count < MAX_COMPARE_REPORT {
+                println!(
+                    "{:08x}: {:02x}  {:08x}: {:02x}",
+                    base1 + offset,
+                    byte1,
+                    base2 + offset,
+                    byte2
+                );
+            }
+            count += 1;
+        }
+    }
+    if count > MAX_COMPARE_REPORT {
+        println!("...");
+    }
+    println!("{} differences", count);
+    Ok(())
+}
+
+fn cmd_move(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let [src, dest, len] = args else {
+        return Err(CommandError::Usage);
+    };
+    let src = parse_address(src)?;
+    let dest = parse_address(dest)?;
+    let len = parse_address(len)?;
+    checked_end(src, len)?;
+    check_unused(dest, len)?;
+
+    unsafe {
+        core::ptr::copy(src as *const u8, dest as *mut u8, len);
+    }
+    Ok(())
+}
+
+fn cmd_mmio(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let (addr, value) = match args {
+        [addr] => (parse_address(addr)?, None),
+        [addr, value] => (parse_address(addr)?, Some(Width::Dword.parse_value(value)?)),
+        _ => return Err(CommandError::Usage),
+    };
+    check_aligned(addr, Width::Dword)?;
+    let reg = Mmio32Reg(addr);
+    match value {
+        Some(value) => {
+            check_unused(addr, Width::Dword.size())?;
+            unsafe {
+                reg.write(value);
+            }
+        }
+        None => {
+            let value = unsafe { reg.read() };
+            println!("{:08x}: {:08x}", addr, value);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_call(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let [addr] = args else {
+        return Err(CommandError::Usage);
+    };
+    let addr = parse_address(addr)?;
+    if addr == 0 {
+        return Err(CommandError::invalid_argument(args[0]));
+    }
+    check_unused(addr, 1)?;
+
+    let result = unsafe {
+        let f: extern "C" fn() -> usize = core::mem::transmute(addr);
+        f()
+    };
+    println!("result: {:#x}", result);
+    Ok(())
+}
+
+#[cfg(target_arch = "x86")]
+fn parse_port(s: &str) -> Result<u16, CommandError> {
+    Width::Word.parse_value(s).map(|v| v as u16)
+}
+
+#[cfg(target_arch = "x86")]
+fn cmd_in(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    use x86::isolated_io::*;
+
+    let (width, args) = Width::parse(args);
+    let [port] = args else {
+        return Err(CommandError::Usage);
+    };
+    let port = parse_port(port)?;
+    let value = unsafe {
+        match width {
+            Width::Byte => IoPortRB(port).read() as u32,
+            Width::Word => IoPortRW(port).read() as u32,
+            Width::Dword => IoPortRD(port).read(),
+        }
+    };
+    println!("{:04x}: {:0width$x}", port, value, width = width.size() * 2);
+    Ok(())
+}
+
+#[cfg(target_arch = "x86")]
+fn cmd_out(_shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    use x86::isolated_io::*;
+
+    let (width, args) = Width::parse(args);
+    let [port, value] = args else {
+        return Err(CommandError::Usage);
+    };
+    let port = parse_port(port)?;
+    let value = width.parse_value(value)?;
+    unsafe {
+        match width {
+            Width::Byte => IoPortWB(port).write(value as u8),
+            Width::Word => IoPortWW(port).write(value as u16),
+            Width::Dword => IoPortWD(port).write(value),
+        }
+    }
+    Ok(())
+}
@@ -41,6 +41,10 @@
 %define CEEF_BASE           0x08
 %define CEEF_MINALLOC       0x0C
 
+%define MAR_MAGIC           0x0002beef
+%define MAR_OFFSET          0x08
+%define MAR_SIZE            0x0C
+
 %define MAX_PALETTE         16
 
 %define SMAP_AVAILABLE      0x01
@@ -62,6 +66,8 @@ _memsz_lo           dw 0
 _reserved_memsz     dd 0x00100000
 _start_mid          dd 0x00100000
 _memsz_mid          dd 0
+_initrd_base        dd 0
+_initrd_size        dd 0
 
 forever:
     sti
@@ -461,6 +467,24 @@ _next32:
     and edi, 0xfffff000
     add edi, STACK_SIZE
     mov esp, edi
+    push edi
+
+    lea esi, [ebp + CEEF_SKIP_DATA]
+    mov edi, [ebp + CEEF_BASE]
+    call _tek1_decode
+
+    ;; move the initrd that follows the kernel image above the stack
+    pop edi
+    cmp dword [esi], MAR_MAGIC
+    jnz .no_initrd
+    mov ecx, [esi + MAR_OFFSET]
+    add ecx, [esi + MAR_SIZE]
+    mov [_initrd_base], edi
+    mov [_initrd_size], ecx
+    rep movsb
+    add edi, 0x00000fff
+    and edi, 0xfffff000
+.no_initrd:
 
     mov eax, [_start_mid]
     mov [_start_mid], edi
@@ -468,10 +492,6 @@ _next32:
     sub eax, edi
     mov [_memsz_mid], eax
 
-    lea esi, [ebp + CEEF_SKIP_DATA]
-    mov edi, [ebp + CEEF_BASE]
-    call _tek1_decode
-
     push byte 0
     popfd
     sub esp, 12